@@ -1,3 +1,7 @@
+use crate::audio_engine::{FadeCurve, ReplayGainMode, SpectrumSettings};
+use crate::play_order::{RepeatMode, ShuffleMode};
+use crate::playlist::Playlist;
+use crate::track_table::TableConfig;
 use serde::{Deserialize, Serialize};
 use std::fs;
 use std::path::PathBuf;
@@ -5,35 +9,82 @@ use std::path::PathBuf;
 const STATE_FILENAME: &str = "player_state.json";
 
 // שים לב ל-pub כאן! בלי זה, ה-main לא יכול לראות את זה.
+// serde(default) - כדי שקובץ שמור מגרסה ישנה (בלי השדות החדשים) עדיין ייטען
 #[derive(Serialize, Deserialize, Debug)]
+#[serde(default)]
 pub struct AppState {
     pub volume: f32,
+    pub playlists: Vec<Playlist>,
+    pub active_playlist: usize,
+    // הרשימה היחידה מהגרסאות הקודמות - נקראת רק כדי להפוך אותה לפלייליסט הראשון
+    #[serde(skip_serializing)]
     pub playlist: Vec<PathBuf>,
+    #[serde(skip_serializing)]
     pub last_played_index: Option<usize>,
     pub is_dark_mode: bool,    // הוספנו גם את זה
     pub accent_color: [u8; 3], // הוספנו שמירת צבע
+    pub crossfade_secs: f32,   // 0 = בלי Crossfade (מעבר Gapless)
+    pub crossfade_curve: FadeCurve,
+    pub replaygain_mode: ReplayGainMode,
+    pub replaygain_preamp: f32,   // dB
+    pub replaygain_fallback: f32, // dB - לקבצים בלי תגיות
+    pub output_device: Option<String>, // None = ברירת המחדל של המערכת
+    pub watched_roots: Vec<PathBuf>,   // תיקיות שמתעדכנות אוטומטית כשמשהו משתנה בהן
+    pub show_browser: bool,
+    pub repeat_mode: RepeatMode,
+    pub shuffle_mode: ShuffleMode,
+    pub table_config: TableConfig,
+    pub write_rating_tags: bool,
+    pub spectrum: SpectrumSettings,
 }
 
 impl Default for AppState {
     fn default() -> Self {
         Self {
             volume: 0.5,
+            playlists: Vec::new(),
+            active_playlist: 0,
             playlist: Vec::new(),
             last_played_index: None,
             is_dark_mode: true,
             accent_color: [0, 255, 0], // ירוק דיפולטיבי
+            crossfade_secs: 0.0,
+            crossfade_curve: FadeCurve::default(),
+            replaygain_mode: ReplayGainMode::default(),
+            replaygain_preamp: 0.0,
+            replaygain_fallback: 0.0,
+            output_device: None,
+            watched_roots: Vec::new(),
+            show_browser: true,
+            repeat_mode: RepeatMode::default(),
+            shuffle_mode: ShuffleMode::default(),
+            table_config: TableConfig::default(),
+            write_rating_tags: false,
+            spectrum: SpectrumSettings::default(),
         }
     }
 }
 
 impl AppState {
     pub fn load() -> Self {
-        if let Ok(content) = fs::read_to_string(STATE_FILENAME)
+        let mut state = if let Ok(content) = fs::read_to_string(STATE_FILENAME)
             && let Ok(state) = serde_json::from_str(&content)
         {
-            return state;
+            state
+        } else {
+            Self::default()
+        };
+
+        // תמיד יש לפחות פלייליסט אחד, והאינדקס תמיד מצביע על פלייליסט קיים
+        if state.playlists.is_empty() {
+            let mut library = Playlist::new("Library");
+            library.items = std::mem::take(&mut state.playlist);
+            library.current_index = state.last_played_index.filter(|i| *i < library.items.len());
+            state.playlists.push(library);
         }
-        Self::default()
+        state.active_playlist = state.active_playlist.min(state.playlists.len() - 1);
+        state.table_config.normalize();
+        state
     }
 
     pub fn save(&self) {
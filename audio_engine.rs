@@ -1,7 +1,12 @@
+use crate::loudness::LoudnessMap;
+use crate::metadata::TrackMetadata;
 use eframe::egui::Context;
 use gst::prelude::*;
 use gstreamer as gst;
 use gstreamer::glib;
+use gstreamer_audio as gst_audio;
+use serde::{Deserialize, Serialize};
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::mpsc::{self, Receiver, Sender, TryRecvError};
 use std::sync::{Arc, Mutex};
 use std::thread::{self, JoinHandle};
@@ -24,6 +29,145 @@ pub enum AudioCommand {
     Seek(f32),
     Shutdown,
     SetEq(usize, f64),
+    SetSpectrum(SpectrumSettings),
+    SetNextUri(Option<String>),
+    SetCrossfadeDuration(f64),
+    SetCrossfadeCurve(FadeCurve),
+    SetReplayGainMode(ReplayGainMode),
+    SetReplayGainPreamp(f64),
+    SetReplayGainFallback(f64),
+    SetLoudnessSource(LoudnessMap),
+    SetRate(f64),
+    SetLoop(Option<AbLoop>),
+    SetOutputDevice(Option<String>),
+}
+
+/// התקן פלט שמצא ה-DeviceMonitor (None ב-ID = ברירת המחדל של המערכת)
+#[derive(Debug, Clone, PartialEq)]
+pub struct OutputDevice {
+    pub id: String,
+    pub name: String,
+}
+
+/// לולאת A-B לתרגול: מנגנים שוב ושוב את הקטע בין A ל-B
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AbLoop {
+    pub start_secs: f64,
+    pub end_secs: f64,
+    pub speed: f64,         // האטה לתרגול (1.0 = המהירות הרגילה של הנגן)
+    pub count_in_secs: f64, // הפסקה לפני כל חזרה (0 = לולאה רציפה)
+}
+
+/// טווח מהירויות הניגון הנתמך (scaletempo שומר על הגובה הטבעי של הקול)
+pub const MIN_RATE: f64 = 0.5;
+pub const MAX_RATE: f64 = 3.0;
+
+/// איזה ערך ReplayGain מהתגיות של הקובץ מפעילים
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+pub enum ReplayGainMode {
+    #[default]
+    Off,
+    Track,
+    Album,
+}
+
+impl ReplayGainMode {
+    pub fn all() -> [ReplayGainMode; 3] {
+        [
+            ReplayGainMode::Off,
+            ReplayGainMode::Track,
+            ReplayGainMode::Album,
+        ]
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            ReplayGainMode::Off => "Off",
+            ReplayGainMode::Track => "Track",
+            ReplayGainMode::Album => "Album",
+        }
+    }
+}
+
+/// הגדרות ה-ReplayGain (ב-dB) שנשלחות ל-rgvolume
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ReplayGainSettings {
+    pub mode: ReplayGainMode,
+    pub preamp_db: f64,
+    pub fallback_db: f64,
+}
+
+impl Default for ReplayGainSettings {
+    fn default() -> Self {
+        Self {
+            mode: ReplayGainMode::Off,
+            preamp_db: 0.0,
+            fallback_db: 0.0,
+        }
+    }
+}
+
+/// אורך ה-Crossfade המקסימלי (בשניות)
+pub const MAX_CROSSFADE_SECS: f64 = 12.0;
+
+/// צורת המעטפת של ה-Crossfade בין השיר היוצא לנכנס
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+pub enum FadeCurve {
+    #[default]
+    Linear,
+    EqualPower,
+    Logarithmic,
+}
+
+impl FadeCurve {
+    pub fn all() -> [FadeCurve; 3] {
+        [
+            FadeCurve::Linear,
+            FadeCurve::EqualPower,
+            FadeCurve::Logarithmic,
+        ]
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            FadeCurve::Linear => "Linear",
+            FadeCurve::EqualPower => "Equal Power",
+            FadeCurve::Logarithmic => "Logarithmic",
+        }
+    }
+
+    /// מחזיר (ווליום יוצא, ווליום נכנס) עבור התקדמות t בין 0 ל-1
+    fn gains(&self, t: f64) -> (f64, f64) {
+        let t = t.clamp(0.0, 1.0);
+        match self {
+            FadeCurve::Linear => (1.0 - t, t),
+            FadeCurve::EqualPower => {
+                let angle = t * std::f64::consts::FRAC_PI_2;
+                (angle.cos(), angle.sin())
+            }
+            // ירידה של 60dB לאורך המעבר - נשמע טבעי יותר לאוזן
+            FadeCurve::Logarithmic => (10f64.powf(-3.0 * t), 10f64.powf(-3.0 * (1.0 - t))),
+        }
+    }
+}
+
+/// הגדרות אלמנט ה-spectrum של GStreamer (מספר פסים, קצב הודעות וסף רעש)
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SpectrumSettings {
+    pub bands: u32,
+    pub interval_ms: u64,
+    pub threshold_db: i32,
+}
+
+impl Default for SpectrumSettings {
+    fn default() -> Self {
+        Self {
+            bands: 40,
+            interval_ms: 50,
+            threshold_db: -60,
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -33,6 +177,47 @@ pub enum AudioStatus {
     DurationUpdated(f64),
     Error(String),
     EndOfStream,
+    Spectrum(Vec<f32>),
+    TrackChanged(String),
+    LoopCleared,
+    OutputDevices(Vec<OutputDevice>),
+    OutputDeviceAdded(OutputDevice),
+    OutputDeviceRemoved(String),
+    OutputDeviceChanged(Option<String>),
+    StreamInfo(StreamInfo),
+    Tags(TrackMetadata),
+}
+
+/// מה באמת מתנגן: הפורמט שה-decoder מוציא (caps) + תגיות הזרם (codec, bitrate)
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct StreamInfo {
+    pub codec: Option<String>,
+    pub bitrate: Option<u32>, // bits per second
+    pub sample_rate: u32,
+    pub bit_depth: u32,
+    pub channels: u32,
+}
+
+impl StreamInfo {
+    /// "44.1kHz" / "96kHz"
+    pub fn sample_rate_label(&self) -> String {
+        let khz = self.sample_rate as f64 / 1000.0;
+        if khz.fract() == 0.0 {
+            format!("{:.0}kHz", khz)
+        } else {
+            format!("{:.1}kHz", khz)
+        }
+    }
+
+    pub fn channels_label(&self) -> String {
+        match self.channels {
+            1 => "Mono".to_string(),
+            2 => "Stereo".to_string(),
+            6 => "5.1".to_string(),
+            8 => "7.1".to_string(),
+            n => format!("{}ch", n),
+        }
+    }
 }
 
 pub struct AudioEngine {
@@ -44,6 +229,21 @@ pub struct AudioEngine {
     pub current_duration: f64,
     pub current_position: f64,
     pub spectrum_data: Arc<Mutex<Vec<f32>>>,
+    track_change: Option<String>,
+
+    // סמני לולאת A-B (בשניות) והגדרות התרגול
+    pub loop_a: Option<f64>,
+    pub loop_b: Option<f64>,
+    pub loop_speed: f32,
+    pub loop_count_in: f32,
+
+    // התקני הפלט הזמינים וזה שמתנגן עליו עכשיו (None = ברירת המחדל)
+    pub output_devices: Vec<OutputDevice>,
+    pub current_output: Option<String>,
+
+    pub stream_info: Option<StreamInfo>,
+    pub current_metadata: Option<TrackMetadata>,
+    metadata_update: Option<TrackMetadata>,
 }
 
 impl AudioEngine {
@@ -65,6 +265,16 @@ impl AudioEngine {
             current_duration: 0.0,
             current_position: 0.0,
             spectrum_data: Arc::new(Mutex::new(Vec::new())),
+            track_change: None,
+            loop_a: None,
+            loop_b: None,
+            loop_speed: 1.0,
+            loop_count_in: 0.0,
+            output_devices: Vec::new(),
+            current_output: None,
+            stream_info: None,
+            current_metadata: None,
+            metadata_update: None,
         }
     }
 
@@ -77,19 +287,25 @@ impl AudioEngine {
     }
 
     pub fn load(&self, path: &str) {
-        let uri = if path.starts_with("file://") {
-            path.to_string()
-        } else {
-            match glib::filename_to_uri(path, None) {
-                Ok(u) => u.to_string(),
-                Err(e) => {
-                    eprintln!("URI conversion error: {}", e);
-                    return;
-                }
-            }
-        };
+        if let Some(uri) = path_to_uri(path) {
+            let _ = self.command_tx.send(AudioCommand::LoadFile(uri));
+        }
+    }
+
+    /// מגדיר מראש את השיר הבא למעבר Gapless (None מבטל)
+    pub fn set_next(&self, path: Option<&str>) {
+        let uri = path.and_then(path_to_uri);
+        let _ = self.command_tx.send(AudioCommand::SetNextUri(uri));
+    }
 
-        let _ = self.command_tx.send(AudioCommand::LoadFile(uri));
+    /// התגיות החדשות של השיר שמתנגן, אם הגיעו מאז הקריאה הקודמת
+    pub fn take_metadata(&mut self) -> Option<TrackMetadata> {
+        self.metadata_update.take()
+    }
+
+    /// מחזיר את ה-URI של השיר שהמנוע עבר אליו לבד (Gapless) מאז הקריאה הקודמת
+    pub fn take_track_change(&mut self) -> Option<String> {
+        self.track_change.take()
     }
 
     pub fn play(&self) {
@@ -113,6 +329,92 @@ impl AudioEngine {
         let _ = self.command_tx.send(AudioCommand::SetVolume(v));
     }
 
+    /// אורך ה-Crossfade בשניות (0 מכבה, ואז המעבר הוא Gapless)
+    pub fn set_crossfade_duration(&self, seconds: f32) {
+        let secs = (seconds as f64).clamp(0.0, MAX_CROSSFADE_SECS);
+        let _ = self.command_tx.send(AudioCommand::SetCrossfadeDuration(secs));
+    }
+
+    pub fn set_crossfade_curve(&self, curve: FadeCurve) {
+        let _ = self.command_tx.send(AudioCommand::SetCrossfadeCurve(curve));
+    }
+
+    pub fn set_replaygain_mode(&self, mode: ReplayGainMode) {
+        let _ = self.command_tx.send(AudioCommand::SetReplayGainMode(mode));
+    }
+
+    /// הגברה שמתווספת לכל ערך ReplayGain (בין -15 ל-15 dB)
+    pub fn set_replaygain_preamp(&self, db: f32) {
+        let db = (db as f64).clamp(-15.0, 15.0);
+        let _ = self.command_tx.send(AudioCommand::SetReplayGainPreamp(db));
+    }
+
+    /// ההגברה שמשמשת לקבצים בלי תגיות ReplayGain
+    pub fn set_replaygain_fallback(&self, db: f32) {
+        let db = (db as f64).clamp(-15.0, 15.0);
+        let _ = self.command_tx.send(AudioCommand::SetReplayGainFallback(db));
+    }
+
+    /// תוצאות סורק העוצמה - משמשות כ-fallback לקבצים בלי תגיות ReplayGain
+    pub fn set_loudness_source(&self, results: LoudnessMap) {
+        let _ = self.command_tx.send(AudioCommand::SetLoudnessSource(results));
+    }
+
+    /// מהירות ניגון (1.0 = רגיל) בלי לשנות את גובה הצליל
+    pub fn set_rate(&self, rate: f32) {
+        let rate = (rate as f64).clamp(MIN_RATE, MAX_RATE);
+        let _ = self.command_tx.send(AudioCommand::SetRate(rate));
+    }
+
+    pub fn set_spectrum(&self, settings: SpectrumSettings) {
+        let _ = self.command_tx.send(AudioCommand::SetSpectrum(settings));
+    }
+
+    pub fn set_loop_a(&mut self, secs: f64) {
+        self.loop_a = Some(secs.max(0.0));
+        self.sync_loop();
+    }
+
+    pub fn set_loop_b(&mut self, secs: f64) {
+        self.loop_b = Some(secs.max(0.0));
+        self.sync_loop();
+    }
+
+    pub fn set_loop_practice(&mut self, speed: f32, count_in_secs: f32) {
+        self.loop_speed = speed.clamp(0.25, 1.0);
+        self.loop_count_in = count_in_secs.clamp(0.0, 10.0);
+        self.sync_loop();
+    }
+
+    pub fn clear_loop(&mut self) {
+        self.loop_a = None;
+        self.loop_b = None;
+        self.sync_loop();
+    }
+
+    /// הלולאה פעילה רק כששני הסמנים קיימים ו-B אחרי A
+    pub fn active_loop(&self) -> Option<AbLoop> {
+        let (a, b) = (self.loop_a?, self.loop_b?);
+        if b - a < 0.1 {
+            return None;
+        }
+        Some(AbLoop {
+            start_secs: a,
+            end_secs: b,
+            speed: self.loop_speed as f64,
+            count_in_secs: self.loop_count_in as f64,
+        })
+    }
+
+    fn sync_loop(&self) {
+        let _ = self.command_tx.send(AudioCommand::SetLoop(self.active_loop()));
+    }
+
+    /// מחליף את התקן הפלט תוך כדי ניגון (None = ברירת המחדל של המערכת)
+    pub fn set_output_device(&self, id: Option<String>) {
+        let _ = self.command_tx.send(AudioCommand::SetOutputDevice(id));
+    }
+
     pub fn seek(&self, percent: f32) {
         let percent = percent.clamp(0.0, 100.0);
         let _ = self.command_tx.send(AudioCommand::Seek(percent));
@@ -120,6 +422,7 @@ impl AudioEngine {
 
     pub fn update(&mut self) -> bool {
         let mut finished = false;
+        let mut latest_spectrum: Option<Vec<f32>> = None;
 
         // 1. קריאת הודעות מה-Thread
         loop {
@@ -132,36 +435,47 @@ impl AudioEngine {
                     self.current_position = 0.0;
                     finished = true;
                 }
+                Ok(AudioStatus::Spectrum(m)) => latest_spectrum = Some(m),
+                Ok(AudioStatus::TrackChanged(uri)) => {
+                    self.current_position = 0.0;
+                    self.track_change = Some(uri);
+                }
+                Ok(AudioStatus::LoopCleared) => {
+                    self.loop_a = None;
+                    self.loop_b = None;
+                }
+                Ok(AudioStatus::OutputDevices(devices)) => self.output_devices = devices,
+                Ok(AudioStatus::OutputDeviceAdded(device)) => {
+                    if !self.output_devices.iter().any(|d| d.id == device.id) {
+                        self.output_devices.push(device);
+                    }
+                }
+                Ok(AudioStatus::OutputDeviceRemoved(id)) => {
+                    self.output_devices.retain(|d| d.id != id);
+                }
+                Ok(AudioStatus::OutputDeviceChanged(id)) => self.current_output = id,
+                Ok(AudioStatus::StreamInfo(info)) => self.stream_info = Some(info),
+                Ok(AudioStatus::Tags(meta)) => {
+                    self.current_metadata = Some(meta.clone());
+                    self.metadata_update = Some(meta);
+                }
                 Ok(AudioStatus::Error(e)) => eprintln!("Audio error: {}", e),
                 Err(TryRecvError::Empty) => break,
                 Err(TryRecvError::Disconnected) => break,
             }
         }
 
-        // 2. סימולציה סופר-חלקה (Smooth Visualization) 🌊
-        if self.current_state == PlayerState::Playing {
+        // 2. החלקה של הספקטרום האמיתי שמגיע מה-GStreamer 🌊
+        if let Some(magnitudes) = latest_spectrum {
             if let Ok(mut data) = self.spectrum_data.lock() {
-                if data.len() != 40 {
-                    *data = vec![0.0; 40];
+                if data.len() != magnitudes.len() {
+                    *data = vec![-60.0; magnitudes.len()];
                 }
-
-                let time = std::time::SystemTime::now()
-                    .duration_since(std::time::UNIX_EPOCH)
-                    .unwrap_or_default()
-                    .as_secs_f64();
-
-                for i in 0..40 {
-                    let x = i as f64 * 0.2;
-                    let wave1 = (time * 8.0 + x).sin();
-                    let wave2 = (time * 4.0 - x * 1.5).cos();
-                    let wave3 = ((time * 2.0) + (i as f64 * 0.5)).sin();
-
-                    let combined = (wave1 + wave2 + wave3).abs() / 3.0;
-                    let target_val = -50.0 + (combined * 50.0);
-                    data[i] = data[i] * 0.8 + (target_val as f32) * 0.2;
+                for (current, target) in data.iter_mut().zip(magnitudes.iter()) {
+                    *current = *current * 0.5 + *target * 0.5;
                 }
             }
-        } else {
+        } else if self.current_state != PlayerState::Playing {
             // ניקוי הדרגתי כשהשיר עוצר
             if let Ok(mut data) = self.spectrum_data.lock()
                 && !data.is_empty()
@@ -198,8 +512,14 @@ impl Drop for AudioEngine {
 // Run Loop - המנוע שרץ ברקע
 // =========================================================
 fn run_loop(cmd_rx: Receiver<AudioCommand>, event_tx: Sender<AudioStatus>, ctx: Context) {
-    // 1. יצירת ה-Playbin
-    let pipeline = match gst::ElementFactory::make("playbin").build() {
+    // Gapless: ה-URI שהוכן מראש משותף לכל ה-playbin-ים (ה-callback רץ ב-Thread של GStreamer)
+    let handover = Arc::new(Handover {
+        gapless: AtomicBool::new(true),
+        ..Default::default()
+    });
+
+    // 1. יצירת ה-Playbin עם שרשרת הפילטרים
+    let mut player = match Player::new(&handover) {
         Ok(p) => p,
         Err(e) => {
             let _ = event_tx.send(AudioStatus::Error(format!(
@@ -210,56 +530,94 @@ fn run_loop(cmd_rx: Receiver<AudioCommand>, event_tx: Sender<AudioStatus>, ctx:
         }
     };
 
-    let fakesink = gst::ElementFactory::make("fakesink")
-        .build()
-        .expect("Failed to create fakesink");
-
-    // אומרים למנוע: "כל וידאו שאתה מוצא, תזרוק לפח הזה אל תפתח חלון!"
-    pipeline.set_property("video-sink", &fakesink);
-
-    // 2. יצירת האקולייזר וחיבורו (תוקן: אין יותר כפילות)
-    let equalizer = gst::ElementFactory::make("equalizer-10bands")
-        .build()
-        .expect("Missing gst-plugins-good");
-    fakesink.set_property("sync", true);
-
-    // חיבור האקולייזר לנגן
-    pipeline.set_property("audio-filter", &equalizer);
-
-    let bus = match pipeline.bus() {
-        Some(b) => b,
-        None => return,
-    };
+    let mut settings = PlayerSettings::default();
+    let mut crossfade_secs = 0.0_f64;
+    let mut crossfade_curve = FadeCurve::default();
+    let mut crossfade: Option<Crossfade> = None;
 
     let mut current_state = PlayerState::Stopped;
     let mut last_update = std::time::Instant::now();
+    let mut rate_pending = false;
+    let mut ab_loop: Option<AbLoop> = None;
+    let mut count_in_until: Option<std::time::Instant> = None;
+
+    // התקני פלט: ה-DeviceMonitor מדווח על חיבור וניתוק (Hot-plug) דרך ה-Bus שלו
+    let monitor = gst::DeviceMonitor::new();
+    monitor.add_filter(Some("Audio/Sink"), None);
+    if let Err(e) = monitor.start() {
+        eprintln!("Device monitor unavailable: {}", e);
+    }
+    let monitor_bus = monitor.bus();
+    let _ = event_tx.send(AudioStatus::OutputDevices(
+        monitor
+            .devices()
+            .into_iter()
+            .map(|d| OutputDevice::from_device(&d))
+            .collect(),
+    ));
+    let mut preferred_output: Option<String> = None;
+    // אחרי החלפת התקן הנגן מתחיל מההתחלה - לכאן חוזרים כשהוא מוכן
+    let mut resume_at: Option<gst::ClockTime> = None;
+    let mut last_stream_info: Option<StreamInfo> = None;
+    // התגיות מגיעות בכמה הודעות (מה-demuxer, מה-decoder...) אז אוספים אותן לאורך השיר
+    let mut track_tags = TrackMetadata::default();
 
     loop {
         // --- טיפול בפקודות ---
         while let Ok(cmd) = cmd_rx.try_recv() {
             match cmd {
                 AudioCommand::LoadFile(uri) => {
-                    let _ = pipeline.set_state(gst::State::Ready);
-                    pipeline.set_property("uri", &uri);
-                    let _ = pipeline.set_state(gst::State::Playing);
+                    crossfade = None;
+                    if ab_loop.take().is_some() {
+                        count_in_until = None;
+                        let _ = event_tx.send(AudioStatus::LoopCleared);
+                    }
+                    track_tags = TrackMetadata::default();
+                    if let Ok(mut switched) = handover.switched_uri.lock() {
+                        *switched = None;
+                    }
+                    let _ = player.pipeline.set_state(gst::State::Ready);
+                    player.pipeline.set_property("volume", settings.volume);
+                    player.pipeline.set_property("uri", &uri);
+                    player.apply_replaygain(&settings);
+                    let _ = player.pipeline.set_state(gst::State::Playing);
+                    // שיר חדש מתחיל תמיד ב-Segment רגיל - את המהירות מחילים כשהוא כבר מתנגן
+                    rate_pending = settings.rate != 1.0;
                 }
                 AudioCommand::Play => {
-                    let _ = pipeline.set_state(gst::State::Playing);
+                    // Play באמצע ספירת הכניסה - מדלגים ישר לתחילת הלולאה
+                    if count_in_until.take().is_some()
+                        && let Some(l) = &ab_loop
+                    {
+                        player.seek_segment(l, settings.rate, l.start_secs, true);
+                    }
+                    let _ = player.pipeline.set_state(gst::State::Playing);
                 }
                 AudioCommand::Pause => {
-                    let _ = pipeline.set_state(gst::State::Paused);
+                    // השהייה באמצע Crossfade - השיר היוצא פשוט נחתך
+                    crossfade = None;
+                    count_in_until = None;
+                    player.pipeline.set_property("volume", settings.volume);
+                    let _ = player.pipeline.set_state(gst::State::Paused);
                 }
                 AudioCommand::Stop => {
-                    let _ = pipeline.set_state(gst::State::Null);
+                    crossfade = None;
+                    let _ = player.pipeline.set_state(gst::State::Null);
                     current_state = PlayerState::Stopped;
                     let _ = event_tx.send(AudioStatus::StateChanged(current_state.clone()));
                 }
                 AudioCommand::SetVolume(v) => {
-                    pipeline.set_property("volume", v);
+                    settings.volume = v;
+                    // בזמן Crossfade המעטפת מחשבת את הווליום בעצמה
+                    if crossfade.is_none() {
+                        player.pipeline.set_property("volume", v);
+                    }
                 }
                 // כאן טיפול באקולייזר
                 AudioCommand::SetEq(band_idx, gain) => {
-                    let prop_name = format!("band{}", band_idx);
+                    if band_idx >= settings.eq.len() {
+                        continue;
+                    }
 
                     // --- התיקון: הגבלת הטווח (Clamping) ---
                     // GStreamer 10-bands תומך מקסימום ב-12dB
@@ -267,47 +625,195 @@ fn run_loop(cmd_rx: Receiver<AudioCommand>, event_tx: Sender<AudioStatus>, ctx:
 
                     // הדפסה לטרמינל כדי שתראה שזה עובד
                     println!(
-                        "🎚 EQ {}: {:.1} dB (Clamped from {:.1})",
-                        prop_name, safe_gain, gain
+                        "🎚 EQ band{}: {:.1} dB (Clamped from {:.1})",
+                        band_idx, safe_gain, gain
                     );
 
-                    equalizer.set_property(&prop_name, safe_gain);
+                    settings.eq[band_idx] = safe_gain;
+                    player.set_eq_band(band_idx, safe_gain);
+                    if let Some(fade) = &crossfade {
+                        fade.outgoing.set_eq_band(band_idx, safe_gain);
+                    }
+                }
+                AudioCommand::SetNextUri(uri) => {
+                    if let Ok(mut next) = handover.next_uri.lock() {
+                        *next = uri;
+                    }
+                }
+                AudioCommand::SetSpectrum(spectrum) => {
+                    settings.spectrum = spectrum;
+                    apply_spectrum_settings(&player.spectrum, &spectrum);
+                }
+                AudioCommand::SetCrossfadeDuration(secs) => {
+                    crossfade_secs = secs.clamp(0.0, MAX_CROSSFADE_SECS);
+                    // כשיש Crossfade הוא זה שמחליף שירים, לא ה-about-to-finish
+                    handover.gapless.store(crossfade_secs <= 0.0, Ordering::SeqCst);
+                }
+                AudioCommand::SetCrossfadeCurve(curve) => {
+                    crossfade_curve = curve;
+                }
+                AudioCommand::SetReplayGainMode(mode) => {
+                    settings.replaygain.mode = mode;
+                    player.apply_replaygain(&settings);
+                }
+                AudioCommand::SetReplayGainPreamp(db) => {
+                    settings.replaygain.preamp_db = db;
+                    player.apply_replaygain(&settings);
+                }
+                AudioCommand::SetReplayGainFallback(db) => {
+                    settings.replaygain.fallback_db = db;
+                    player.apply_replaygain(&settings);
+                }
+                AudioCommand::SetLoudnessSource(results) => {
+                    settings.loudness = Some(results);
+                    player.apply_replaygain(&settings);
+                }
+                AudioCommand::SetRate(rate) => {
+                    settings.rate = rate;
+                    rate_pending = !match &ab_loop {
+                        Some(l) => player.seek_segment(l, settings.rate, l.start_secs, true),
+                        None => player.seek_to(None, settings.rate, gst::SeekFlags::ACCURATE),
+                    };
+                }
+                AudioCommand::SetLoop(new_loop) => {
+                    crossfade = None;
+                    count_in_until = None;
+                    player.pipeline.set_property("volume", settings.volume);
+                    match &new_loop {
+                        // Segment Seek: ב-B מקבלים SEGMENT_DONE במקום EOS וקופצים חזרה ל-A
+                        Some(l) => {
+                            player.seek_segment(l, settings.rate, l.start_secs, true);
+                        }
+                        // ביטול הלולאה - Seek רגיל מהמקום הנוכחי מנקה את נקודת העצירה
+                        None if ab_loop.is_some() => {
+                            player.seek_to(None, settings.rate, gst::SeekFlags::ACCURATE);
+                        }
+                        None => {}
+                    }
+                    ab_loop = new_loop;
                 }
                 AudioCommand::Seek(percent) => {
-                    if let Some(dur) = pipeline.query_duration::<gst::ClockTime>() {
+                    crossfade = None;
+                    count_in_until = None;
+                    player.pipeline.set_property("volume", settings.volume);
+                    if let Some(dur) = player.pipeline.query_duration::<gst::ClockTime>() {
                         let target_ns = (dur.nseconds() as f64 * (percent as f64 / 100.0)) as u64;
-                        let _ = pipeline.seek_simple(
-                            gst::SeekFlags::FLUSH | gst::SeekFlags::KEY_UNIT,
-                            gst::ClockTime::from_nseconds(target_ns),
-                        );
+                        match &ab_loop {
+                            // בזמן לולאה נשארים בתוך הקטע
+                            Some(l) => {
+                                let target = (target_ns as f64 / 1_000_000_000.0)
+                                    .clamp(l.start_secs, l.end_secs);
+                                player.seek_segment(l, settings.rate, target, true);
+                            }
+                            None => {
+                                player.seek_to(
+                                    Some(gst::ClockTime::from_nseconds(target_ns)),
+                                    settings.rate,
+                                    gst::SeekFlags::KEY_UNIT,
+                                );
+                            }
+                        }
                     }
                 }
+                AudioCommand::SetOutputDevice(id) => {
+                    crossfade = None;
+                    preferred_output = id.clone();
+                    settings.output_device = id.as_ref().and_then(|id| find_device(&monitor, id));
+                    if let Some(pos) = player.switch_output(settings.output_device.as_ref()) {
+                        resume_at = Some(pos);
+                    }
+                    let active = settings.output_device.as_ref().and(id);
+                    let _ = event_tx.send(AudioStatus::OutputDeviceChanged(active));
+                }
                 AudioCommand::Shutdown => {
-                    let _ = pipeline.set_state(gst::State::Null);
+                    monitor.stop();
+                    let _ = player.pipeline.set_state(gst::State::Null);
                     return;
                 }
             }
         }
 
         // --- טיפול בהודעות מהמנוע (GStreamer Bus) ---
-        if let Some(msg) = bus.timed_pop(gst::ClockTime::from_mseconds(30)) {
+        if let Some(msg) = player.bus.timed_pop(gst::ClockTime::from_mseconds(30)) {
             use gst::MessageView;
             match msg.view() {
                 MessageView::Eos(..) => {
-                    let _ = pipeline.set_state(gst::State::Ready);
+                    let _ = player.pipeline.set_state(gst::State::Ready);
                     current_state = PlayerState::Stopped;
                     let _ = event_tx.send(AudioStatus::EndOfStream);
                     ctx.request_repaint();
                 }
 
+                MessageView::AsyncDone(..) => {
+                    // הנגן מוכן אחרי החלפת התקן - חוזרים לאותו מקום בשיר
+                    if let Some(pos) = resume_at.take() {
+                        let secs = pos.mseconds() as f64 / 1000.0;
+                        match &ab_loop {
+                            Some(l) => {
+                                let start = secs.clamp(l.start_secs, l.end_secs);
+                                player.seek_segment(l, settings.rate, start, true);
+                            }
+                            None => {
+                                player.seek_to(
+                                    Some(pos),
+                                    settings.rate,
+                                    gst::SeekFlags::ACCURATE,
+                                );
+                            }
+                        }
+                    }
+                }
+
+                MessageView::SegmentDone(..) => {
+                    // הגענו ל-B: או שקופצים ישר ל-A (בלי Flush - רציף לגמרי) או שעוצרים לספירה
+                    if let Some(l) = &ab_loop {
+                        if l.count_in_secs > 0.0 {
+                            let _ = player.pipeline.set_state(gst::State::Paused);
+                            count_in_until = Some(
+                                std::time::Instant::now()
+                                    + std::time::Duration::from_secs_f64(l.count_in_secs),
+                            );
+                        } else {
+                            player.seek_segment(l, settings.rate, l.start_secs, false);
+                        }
+                    }
+                }
+
+                MessageView::StreamStart(..) => {
+                    // הזרם החדש התחיל - אם זה מעבר Gapless, מודיעים ל-UI
+                    let switched = handover.switched_uri.lock().ok().and_then(|mut s| s.take());
+                    if let Some(uri) = switched {
+                        player.apply_replaygain(&settings);
+                        rate_pending = settings.rate != 1.0;
+                        // הסמנים של הלולאה שייכים לשיר הקודם
+                        if ab_loop.take().is_some() {
+                            let _ = event_tx.send(AudioStatus::LoopCleared);
+                        }
+                        // מתחילים תגיות מחדש, עם מה שה-playbin כבר אסף על הזרם החדש
+                        track_tags = TrackMetadata::default();
+                        if let Some(tags) = player
+                            .pipeline
+                            .emit_by_name::<Option<gst::TagList>>("get-audio-tags", &[&0i32])
+                        {
+                            track_tags.merge_tags(&tags);
+                        }
+                        let _ = event_tx.send(AudioStatus::Tags(track_tags.clone()));
+                        let _ = event_tx.send(AudioStatus::TrackChanged(uri));
+                        if let Some(dur) = player.pipeline.query_duration::<gst::ClockTime>() {
+                            let _ = event_tx.send(AudioStatus::DurationUpdated(dur.seconds() as f64));
+                        }
+                        ctx.request_repaint();
+                    }
+                }
+
                 MessageView::DurationChanged(..) => {
-                    if let Some(dur) = pipeline.query_duration::<gst::ClockTime>() {
+                    if let Some(dur) = player.pipeline.query_duration::<gst::ClockTime>() {
                         let _ = event_tx.send(AudioStatus::DurationUpdated(dur.seconds() as f64));
                     }
                 }
                 MessageView::StateChanged(s) => {
                     if s.src()
-                        .map(|src| src == pipeline.upcast_ref::<gst::Object>())
+                        .map(|src| src == player.pipeline.upcast_ref::<gst::Object>())
                         .unwrap_or(false)
                     {
                         let new_state = match s.current() {
@@ -321,23 +827,573 @@ fn run_loop(cmd_rx: Receiver<AudioCommand>, event_tx: Sender<AudioStatus>, ctx:
                         }
                     }
                 }
+                MessageView::Tag(t) => {
+                    let before = track_tags.clone();
+                    track_tags.merge_tags(&t.tags());
+                    if track_tags != before {
+                        let _ = event_tx.send(AudioStatus::Tags(track_tags.clone()));
+                        ctx.request_repaint();
+                    }
+                }
+                MessageView::Element(e) => {
+                    if let Some(magnitudes) = e.structure().and_then(parse_spectrum) {
+                        let _ = event_tx.send(AudioStatus::Spectrum(magnitudes));
+                        ctx.request_repaint();
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        // --- Hot-plug של התקני פלט ---
+        while let Some(msg) = monitor_bus.pop() {
+            use gst::MessageView;
+            match msg.view() {
+                MessageView::DeviceAdded(m) => {
+                    let device = m.device();
+                    let added = OutputDevice::from_device(&device);
+                    // ההתקן שהמשתמש בחר חזר? עוברים אליו אוטומטית
+                    if preferred_output.as_ref() == Some(&added.id)
+                        && settings.output_device.is_none()
+                    {
+                        settings.output_device = Some(device.clone());
+                        if let Some(pos) = player.switch_output(settings.output_device.as_ref()) {
+                            resume_at = Some(pos);
+                        }
+                        let _ = event_tx
+                            .send(AudioStatus::OutputDeviceChanged(Some(added.id.clone())));
+                    }
+                    let _ = event_tx.send(AudioStatus::OutputDeviceAdded(added));
+                    ctx.request_repaint();
+                }
+                MessageView::DeviceRemoved(m) => {
+                    let removed_id = device_id(&m.device());
+                    // ההתקן הפעיל נותק - חוזרים לברירת המחדל כדי שהמוזיקה לא תיעצר
+                    let was_active = settings
+                        .output_device
+                        .as_ref()
+                        .map(|d| device_id(d) == removed_id)
+                        .unwrap_or(false);
+                    if was_active {
+                        crossfade = None;
+                        settings.output_device = None;
+                        if let Some(pos) = player.switch_output(None) {
+                            resume_at = Some(pos);
+                        }
+                        let _ = event_tx.send(AudioStatus::OutputDeviceChanged(None));
+                    }
+                    let _ = event_tx.send(AudioStatus::OutputDeviceRemoved(removed_id));
+                    ctx.request_repaint();
+                }
                 _ => {}
             }
         }
 
+        // --- סוף ספירת הכניסה: חוזרים ל-A וממשיכים לנגן ---
+        if let Some(until) = count_in_until
+            && std::time::Instant::now() >= until
+        {
+            count_in_until = None;
+            if let Some(l) = &ab_loop {
+                player.seek_segment(l, settings.rate, l.start_secs, true);
+                let _ = player.pipeline.set_state(gst::State::Playing);
+            }
+        }
+
+        // --- ReplayGain כבוי: מבטלים את מה ש-rgvolume חישב מהתגיות ---
+        player.update_replaygain_bypass(settings.replaygain.mode);
+
+        // --- מעטפת הווליום של ה-Crossfade ---
+        if let Some(fade) = &crossfade {
+            // השיר היוצא נגמר או נכשל לפני הזמן? אין מה לחכות לו
+            let outgoing_done = std::iter::from_fn(|| fade.outgoing.bus.pop()).any(|msg| {
+                matches!(msg.view(), gst::MessageView::Eos(..) | gst::MessageView::Error(..))
+            });
+
+            let t = fade.progress();
+            if outgoing_done || t >= 1.0 {
+                crossfade = None;
+                player.pipeline.set_property("volume", settings.volume);
+            } else {
+                let (out_gain, in_gain) = fade.curve.gains(t);
+                fade.outgoing.pipeline.set_property("volume", settings.volume * out_gain);
+                player.pipeline.set_property("volume", settings.volume * in_gain);
+            }
+        }
+
         // --- עדכון מיקום (Progress Bar) ---
         // זה החלק שהיה חסר לך או לא עבד בגלל הבלגן בסטייט
         if current_state == PlayerState::Playing && last_update.elapsed().as_millis() > 100 {
-            if let Some(pos) = pipeline.query_position::<gst::ClockTime>() {
-                let _ = event_tx.send(AudioStatus::PositionUpdated(pos.seconds() as f64));
+            let position = player.pipeline.query_position::<gst::ClockTime>();
+            let duration = player.pipeline.query_duration::<gst::ClockTime>();
+
+            if rate_pending {
+                rate_pending = !match (&ab_loop, position) {
+                    // בתוך לולאה חייבים Segment Seek, אחרת נקודת העצירה ב-B הולכת לאיבוד
+                    (Some(l), Some(pos)) => {
+                        let start =
+                            (pos.mseconds() as f64 / 1000.0).clamp(l.start_secs, l.end_secs);
+                        player.seek_segment(l, settings.rate, start, true)
+                    }
+                    (Some(_), None) => false,
+                    (None, _) => player.seek_to(position, settings.rate, gst::SeekFlags::ACCURATE),
+                };
+            }
+
+            if let Some(pos) = position {
+                // מילישניות - במעגל של 100ms שניות שלמות היו נראות כמו קפיצות (ListenTracker)
+                let secs = pos.mseconds() as f64 / 1000.0;
+                let _ = event_tx.send(AudioStatus::PositionUpdated(secs));
                 last_update = std::time::Instant::now();
                 ctx.request_repaint();
             }
 
             // בונוס: וידוא שה-Duration מעודכן
-            if let Some(dur) = pipeline.query_duration::<gst::ClockTime>() {
+            if let Some(dur) = duration {
                 let _ = event_tx.send(AudioStatus::DurationUpdated(dur.seconds() as f64));
             }
+
+            // פורמט הזרם (מתעדכן במעבר שיר וגם כשה-bitrate של VBR זז)
+            let info = player.stream_info();
+            if info.is_some() && info != last_stream_info {
+                last_stream_info = info.clone();
+                if let Some(info) = info {
+                    let _ = event_tx.send(AudioStatus::StreamInfo(info));
+                }
+            }
+
+            // --- Crossfade: מתחילים את השיר הבא לפני שהנוכחי נגמר ---
+            if crossfade.is_none()
+                && ab_loop.is_none()
+                && crossfade_secs > 0.0
+                && let (Some(pos), Some(dur)) = (position, duration)
+            {
+                let pos_secs = pos.mseconds() as f64 / 1000.0;
+                let dur_secs = dur.mseconds() as f64 / 1000.0;
+                // שירים קצרים מדי לא עוברים Crossfade - הם היו נבלעים כולם בתוך המעבר
+                let long_enough = dur_secs > crossfade_secs * 2.0;
+
+                // הזמן שנשאר בשניות "אמיתיות" - במהירות 2x השיר נגמר פי 2 מהר
+                let remaining_secs = (dur_secs - pos_secs) / settings.rate;
+
+                if long_enough && remaining_secs <= crossfade_secs {
+                    let next = handover.next_uri.lock().ok().and_then(|mut n| n.take());
+                    if let Some(uri) = next {
+                        match Player::new(&handover) {
+                            Ok(incoming) => {
+                                incoming.pipeline.set_property("uri", &uri);
+                                incoming.apply(&settings);
+                                incoming.pipeline.set_property("volume", 0.0_f64);
+                                let _ = incoming.pipeline.set_state(gst::State::Playing);
+                                rate_pending = settings.rate != 1.0;
+
+                                let outgoing = std::mem::replace(&mut player, incoming);
+                                track_tags = TrackMetadata::default();
+                                crossfade = Some(Crossfade {
+                                    outgoing,
+                                    started: std::time::Instant::now(),
+                                    duration: std::time::Duration::from_secs_f64(crossfade_secs),
+                                    curve: crossfade_curve,
+                                });
+
+                                let _ = event_tx.send(AudioStatus::TrackChanged(uri));
+                                ctx.request_repaint();
+                            }
+                            Err(e) => {
+                                let _ = event_tx.send(AudioStatus::Error(format!(
+                                    "Failed to start crossfade: {}",
+                                    e
+                                )));
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+// =========================================================
+// Player - playbin אחד עם שרשרת הפילטרים שלו
+// (בזמן Crossfade חיים שניים כאלה במקביל)
+// =========================================================
+
+/// המצב שמשותף בין ה-Thread של המנוע ל-callback של about-to-finish
+#[derive(Default)]
+struct Handover {
+    next_uri: Mutex<Option<String>>,
+    switched_uri: Mutex<Option<String>>,
+    gapless: AtomicBool,
+}
+
+/// ההגדרות שצריך להעתיק ל-playbin חדש כשהוא מחליף את הקודם
+struct PlayerSettings {
+    volume: f64,
+    eq: [f64; 10],
+    spectrum: SpectrumSettings,
+    replaygain: ReplayGainSettings,
+    loudness: Option<LoudnessMap>,
+    rate: f64,
+    output_device: Option<gst::Device>,
+}
+
+impl Default for PlayerSettings {
+    fn default() -> Self {
+        Self {
+            volume: 1.0,
+            eq: [0.0; 10],
+            spectrum: SpectrumSettings::default(),
+            replaygain: ReplayGainSettings::default(),
+            loudness: None,
+            rate: 1.0,
+            output_device: None,
+        }
+    }
+}
+
+struct Player {
+    pipeline: gst::Element,
+    rgvolume: gst::Element,
+    rg_bypass: gst::Element,
+    rglimiter: gst::Element,
+    equalizer: gst::Element,
+    spectrum: gst::Element,
+    bus: gst::Bus,
+}
+
+impl Player {
+    fn new(handover: &Arc<Handover>) -> Result<Self, glib::BoolError> {
+        let pipeline = gst::ElementFactory::make("playbin").build()?;
+
+        let fakesink = gst::ElementFactory::make("fakesink").build()?;
+
+        // אומרים למנוע: "כל וידאו שאתה מוצא, תזרוק לפח הזה אל תפתח חלון!"
+        pipeline.set_property("video-sink", &fakesink);
+        fakesink.set_property("sync", true);
+
+        // 2. ReplayGain: rgvolume קורא את התגיות ומיישם את ההגברה, rglimiter מונע קליפינג.
+        // rgvolume לא יודע להיכבות, אז כשהמצב Off אלמנט volume אחריו מבטל את ההגברה שלו
+        let rgvolume = gst::ElementFactory::make("rgvolume").build()?;
+        let rg_bypass = gst::ElementFactory::make("volume").build()?;
+        let rglimiter = gst::ElementFactory::make("rglimiter").build()?;
+
+        // 3. scaletempo - משנה מהירות בלי להפוך קול ל"סנאי" או ל"מפלצת"
+        let scaletempo = gst::ElementFactory::make("scaletempo").build()?;
+
+        // 4. יצירת האקולייזר
+        let equalizer = gst::ElementFactory::make("equalizer-10bands").build()?;
+
+        // 5. אלמנט הספקטרום - מחשב FFT אמיתי על האודיו שמתנגן
+        let spectrum = gst::ElementFactory::make("spectrum").build()?;
+        spectrum.set_property("post-messages", true);
+        spectrum.set_property("message-magnitude", true);
+        apply_spectrum_settings(&spectrum, &SpectrumSettings::default());
+
+        // חיבור שרשרת הפילטרים (ReplayGain -> מהירות -> אקולייזר -> ספקטרום) לנגן
+        let audio_filter = build_audio_filter(&[
+            rgvolume.clone(),
+            rg_bypass.clone(),
+            rglimiter.clone(),
+            scaletempo.clone(),
+            equalizer.clone(),
+            spectrum.clone(),
+        ])?;
+        pipeline.set_property("audio-filter", &audio_filter);
+
+        // 6. Gapless: כשה-playbin מבקש את השיר הבא, מזינים לו את ה-URI שהוכן מראש
+        let handover = handover.clone();
+        pipeline.connect("about-to-finish", false, move |args| {
+            if !handover.gapless.load(Ordering::SeqCst) {
+                return None;
+            }
+            let playbin = args[0].get::<gst::Element>().ok()?;
+            if let Ok(mut next) = handover.next_uri.lock()
+                && let Some(uri) = next.take()
+            {
+                playbin.set_property("uri", &uri);
+                if let Ok(mut switched) = handover.switched_uri.lock() {
+                    *switched = Some(uri);
+                }
+            }
+            None
+        });
+
+        let bus = pipeline.bus().ok_or_else(|| glib::bool_error!("playbin has no bus"))?;
+
+        let player = Self {
+            pipeline,
+            rgvolume,
+            rg_bypass,
+            rglimiter,
+            equalizer,
+            spectrum,
+            bus,
+        };
+        player.apply_replaygain(&PlayerSettings::default());
+
+        Ok(player)
+    }
+
+    fn apply(&self, settings: &PlayerSettings) {
+        // audio-sink אפשר להחליף רק לפני שהנגן התחיל (NULL/READY)
+        self.set_output(settings.output_device.as_ref());
+        self.pipeline.set_property("volume", settings.volume);
+        for (band_idx, gain) in settings.eq.iter().enumerate() {
+            self.set_eq_band(band_idx, *gain);
+        }
+        apply_spectrum_settings(&self.spectrum, &settings.spectrum);
+        self.apply_replaygain(settings);
+    }
+
+    fn apply_replaygain(&self, settings: &PlayerSettings) {
+        let rg = &settings.replaygain;
+        self.rgvolume.set_property("album-mode", rg.mode == ReplayGainMode::Album);
+        self.rgvolume.set_property("pre-amp", rg.preamp_db);
+        self.rgvolume.set_property("fallback-gain", self.fallback_gain(settings));
+        self.rglimiter.set_property("enabled", rg.mode != ReplayGainMode::Off);
+        self.update_replaygain_bypass(rg.mode);
+    }
+
+    /// rgvolume משתמש ב-fallback רק כשאין תגיות - אז אם הסורק כבר מדד את השיר, זה הערך שלו
+    fn fallback_gain(&self, settings: &PlayerSettings) -> f64 {
+        let rg = &settings.replaygain;
+        let scanned = settings.loudness.as_ref().and_then(|loudness| {
+            let uri = self.pipeline.property::<Option<String>>("uri")?;
+            let (path, _) = glib::filename_from_uri(&uri).ok()?;
+            let map = loudness.lock().ok()?;
+            let info = map.get(&path)?;
+            Some(match rg.mode {
+                ReplayGainMode::Album => info.album_gain_db.unwrap_or(info.track_gain_db),
+                _ => info.track_gain_db,
+            })
+        });
+        scanned.unwrap_or(rg.fallback_db)
+    }
+
+    /// במצב Off מחזירים את ההגברה שחישב rgvolume ל-0dB בדיוק
+    fn update_replaygain_bypass(&self, mode: ReplayGainMode) {
+        let compensation = if mode == ReplayGainMode::Off {
+            let applied_db = self.rgvolume.property::<f64>("result-gain");
+            // אלמנט volume מוגבל ל-x10 (20dB)
+            10f64.powf(-applied_db / 20.0).clamp(0.0, 10.0)
+        } else {
+            1.0
+        };
+        self.rg_bypass.set_property("volume", compensation);
+    }
+
+    fn set_output(&self, device: Option<&gst::Device>) {
+        let sink = device.and_then(|d| match d.create_element(None) {
+            Ok(sink) => Some(sink),
+            Err(e) => {
+                eprintln!("Failed to open output device {}: {}", d.display_name(), e);
+                None
+            }
+        });
+        // None מחזיר את playbin לבחירה האוטומטית של המערכת
+        self.pipeline.set_property("audio-sink", sink.as_ref());
+    }
+
+    /// מחליף התקן באמצע ניגון: חוזרים ל-READY, מחליפים sink וחוזרים למצב הקודם.
+    /// מחזיר את המיקום שצריך לחזור אליו כשהנגן מוכן (AsyncDone)
+    fn switch_output(&self, device: Option<&gst::Device>) -> Option<gst::ClockTime> {
+        let position = self.pipeline.query_position::<gst::ClockTime>();
+        let (_, state, _) = self.pipeline.state(gst::ClockTime::ZERO);
+
+        let _ = self.pipeline.set_state(gst::State::Ready);
+        self.set_output(device);
+
+        if matches!(state, gst::State::Playing | gst::State::Paused) {
+            let _ = self.pipeline.set_state(state);
+            position
+        } else {
+            None
+        }
+    }
+
+    /// Seek עם מהירות - בלי position נשארים במקום ורק משנים את המהירות.
+    /// מחזיר false אם הנגן עוד לא מוכן (ואז צריך לנסות שוב אחר כך)
+    fn seek_to(&self, position: Option<gst::ClockTime>, rate: f64, flags: gst::SeekFlags) -> bool {
+        let Some(position) = position.or_else(|| self.pipeline.query_position::<gst::ClockTime>())
+        else {
+            return false;
+        };
+        self.pipeline
+            .seek(
+                rate,
+                gst::SeekFlags::FLUSH | flags,
+                gst::SeekType::Set,
+                position,
+                gst::SeekType::End,
+                gst::ClockTime::ZERO,
+            )
+            .is_ok()
+    }
+
+    /// Segment Seek בין start ל-B של הלולאה. בלי flush ההמשך צמוד לסוף הקטע הקודם (בלי קליק)
+    fn seek_segment(&self, ab: &AbLoop, rate: f64, start_secs: f64, flush: bool) -> bool {
+        let mut flags = gst::SeekFlags::SEGMENT | gst::SeekFlags::ACCURATE;
+        if flush {
+            flags |= gst::SeekFlags::FLUSH;
         }
+        self.pipeline
+            .seek(
+                rate * ab.speed,
+                flags,
+                gst::SeekType::Set,
+                gst::ClockTime::from_mseconds((start_secs * 1000.0) as u64),
+                gst::SeekType::Set,
+                gst::ClockTime::from_mseconds((ab.end_secs * 1000.0) as u64),
+            )
+            .is_ok()
+    }
+
+    /// קורא את ה-caps שנסגרו על ה-Pad של האודיו ואת התגיות של הזרם
+    fn stream_info(&self) -> Option<StreamInfo> {
+        let pad = self.pipeline.emit_by_name::<Option<gst::Pad>>("get-audio-pad", &[&0i32])?;
+        let caps = pad.current_caps()?;
+        let audio = gst_audio::AudioInfo::from_caps(&caps).ok()?;
+
+        let tags = self.pipeline.emit_by_name::<Option<gst::TagList>>("get-audio-tags", &[&0i32]);
+        let codec = tags
+            .as_ref()
+            .and_then(|t| t.get::<gst::tags::AudioCodec>())
+            .map(|c| c.get().to_string());
+        let bitrate = tags.as_ref().and_then(|t| {
+            t.get::<gst::tags::Bitrate>()
+                .or_else(|| t.get::<gst::tags::NominalBitrate>())
+                .map(|b| b.get())
+        });
+
+        Some(StreamInfo {
+            codec,
+            bitrate,
+            sample_rate: audio.rate(),
+            bit_depth: audio.depth(),
+            channels: audio.channels(),
+        })
+    }
+
+    fn set_eq_band(&self, band_idx: usize, gain: f64) {
+        self.equalizer.set_property(&format!("band{}", band_idx), gain);
+    }
+}
+
+impl Drop for Player {
+    fn drop(&mut self) {
+        let _ = self.pipeline.set_state(gst::State::Null);
     }
 }
+
+/// Crossfade פעיל: השיר היוצא ממשיך להתנגן עד שהמעטפת שלו יורדת לאפס
+struct Crossfade {
+    outgoing: Player,
+    started: std::time::Instant,
+    duration: std::time::Duration,
+    curve: FadeCurve,
+}
+
+impl Crossfade {
+    fn progress(&self) -> f64 {
+        if self.duration.is_zero() {
+            return 1.0;
+        }
+        (self.started.elapsed().as_secs_f64() / self.duration.as_secs_f64()).clamp(0.0, 1.0)
+    }
+}
+
+// =========================================================
+// פונקציות עזר ל-Pipeline
+// =========================================================
+
+fn path_to_uri(path: &str) -> Option<String> {
+    if path.starts_with("file://") {
+        return Some(path.to_string());
+    }
+    match glib::filename_to_uri(path, None) {
+        Ok(u) => Some(u.to_string()),
+        Err(e) => {
+            eprintln!("URI conversion error: {}", e);
+            None
+        }
+    }
+}
+
+/// בונה Bin אחד מרשימת אלמנטים מחוברים בטור, כדי שנוכל להכניס אותו ל-audio-filter של playbin
+fn build_audio_filter(elements: &[gst::Element]) -> Result<gst::Element, glib::BoolError> {
+    let bin = gst::Bin::builder().name("audio_filters").build();
+
+    // audioconvert בהתחלה ובסוף כדי שכל פילטר יקבל את הפורמט שהוא צריך
+    let convert_in = gst::ElementFactory::make("audioconvert").build()?;
+    let convert_out = gst::ElementFactory::make("audioconvert").build()?;
+
+    let mut chain = vec![convert_in];
+    chain.extend(elements.iter().cloned());
+    chain.push(convert_out);
+
+    bin.add_many(&chain)?;
+    gst::Element::link_many(&chain)?;
+
+    let sink_pad = chain
+        .first()
+        .and_then(|e| e.static_pad("sink"))
+        .ok_or_else(|| glib::bool_error!("Filter chain has no sink pad"))?;
+    let src_pad = chain
+        .last()
+        .and_then(|e| e.static_pad("src"))
+        .ok_or_else(|| glib::bool_error!("Filter chain has no src pad"))?;
+
+    bin.add_pad(&gst::GhostPad::with_target(&sink_pad)?)?;
+    bin.add_pad(&gst::GhostPad::with_target(&src_pad)?)?;
+
+    Ok(bin.upcast())
+}
+
+fn apply_spectrum_settings(spectrum: &gst::Element, settings: &SpectrumSettings) {
+    spectrum.set_property("bands", settings.bands.max(1));
+    spectrum.set_property("threshold", settings.threshold_db);
+    spectrum.set_property(
+        "interval",
+        gst::ClockTime::from_mseconds(settings.interval_ms.max(10)).nseconds(),
+    );
+}
+
+impl OutputDevice {
+    fn from_device(device: &gst::Device) -> Self {
+        Self {
+            id: device_id(device),
+            name: device.display_name().to_string(),
+        }
+    }
+}
+
+/// מזהה יציב להתקן (נשמר ב-AppState) - השם הפנימי של PipeWire/Pulse/ALSA אם יש, אחרת שם התצוגה
+fn device_id(device: &gst::Device) -> String {
+    device
+        .properties()
+        .and_then(|props| {
+            ["node.name", "device.name", "alsa.id", "udev.id"]
+                .iter()
+                .find_map(|key| props.get::<String>(*key).ok())
+        })
+        .unwrap_or_else(|| device.display_name().to_string())
+}
+
+fn find_device(monitor: &gst::DeviceMonitor, id: &str) -> Option<gst::Device> {
+    monitor.devices().into_iter().find(|d| device_id(d) == id)
+}
+
+/// שולף את מערך ה-magnitude (ב-dB) מהודעת element שהגיעה מאלמנט ה-spectrum
+fn parse_spectrum(structure: &gst::StructureRef) -> Option<Vec<f32>> {
+    if structure.name() != "spectrum" {
+        return None;
+    }
+
+    let magnitudes = structure.get::<gst::List>("magnitude").ok()?;
+    Some(
+        magnitudes
+            .iter()
+            .filter_map(|v| v.get::<f32>().ok())
+            .collect(),
+    )
+}
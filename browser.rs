@@ -0,0 +1,517 @@
+use crate::covers::CoverCache;
+use crate::library_db::{AlbumId, ArtistId, FolderId, LibraryDb, TrackId};
+use eframe::egui::{self, Color32, RichText};
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+/// מזהה "אמן" לשירים בלי תגית אמן (המזהים האמיתיים מתחילים מ-1)
+const UNKNOWN_ARTIST: ArtistId = 0;
+
+const ROW_HEIGHT: f32 = 22.0;
+const ALBUM_ROW_HEIGHT: f32 = 52.0;
+const COVER_SIZE: f32 = 44.0;
+
+#[derive(Clone, Copy, PartialEq, Eq, Default)]
+pub enum BrowserView {
+    #[default]
+    Artists,
+    Genres,
+    Folders,
+}
+
+/// מה המשתמש ביקש לעשות עם השירים שבחר
+pub enum BrowserAction {
+    /// להחליף את מה שמתנגן: לנגן את הרשימה החל מהאינדקס
+    Play(Vec<PathBuf>, usize),
+    /// להוסיף לסוף הפלייליסט
+    Append(Vec<PathBuf>),
+}
+
+/// הרשימות הממוינות של כל תצוגה. נבנות מחדש רק כשה-revision של ה-DB משתנה
+#[derive(Default)]
+struct BrowseIndex {
+    revision: Option<u64>,
+    artists: Vec<ArtistId>,
+    albums_by_artist: HashMap<ArtistId, Vec<AlbumId>>,
+    singles_by_artist: HashMap<ArtistId, Vec<TrackId>>, // שירים בלי אלבום
+    tracks_by_album: HashMap<AlbumId, Vec<TrackId>>,
+    genres: Vec<(String, Vec<TrackId>)>,
+    folders: Vec<(FolderId, Vec<TrackId>)>,
+}
+
+impl BrowseIndex {
+    fn build(db: &LibraryDb) -> Self {
+        let mut index = BrowseIndex {
+            revision: Some(db.revision()),
+            ..Default::default()
+        };
+        let mut genres: HashMap<String, Vec<TrackId>> = HashMap::new();
+        let mut folders: HashMap<FolderId, Vec<TrackId>> = HashMap::new();
+
+        for (id, track) in &db.tracks {
+            let artist = track.artist.unwrap_or(UNKNOWN_ARTIST);
+            match track.album {
+                Some(album) => {
+                    index.tracks_by_album.entry(album).or_default().push(*id);
+                    // אלבום מופיע גם אצל ה-Album Artist וגם אצל האמן של כל שיר בו (אוסף)
+                    let album_artist = db.albums.get(&album).and_then(|a| a.artist);
+                    for owner in [Some(artist), album_artist].into_iter().flatten() {
+                        let albums = index.albums_by_artist.entry(owner).or_default();
+                        if !albums.contains(&album) {
+                            albums.push(album);
+                        }
+                    }
+                }
+                None => index.singles_by_artist.entry(artist).or_default().push(*id),
+            }
+            let genre = track
+                .info
+                .metadata
+                .genre
+                .clone()
+                .unwrap_or_else(|| "Unknown Genre".to_string());
+            genres.entry(genre).or_default().push(*id);
+            folders.entry(track.folder).or_default().push(*id);
+        }
+
+        let artist_name = |id: &ArtistId| {
+            db.artists
+                .get(id)
+                .map(|a| a.name.to_lowercase())
+                .unwrap_or_default()
+        };
+        index.artists = index
+            .albums_by_artist
+            .keys()
+            .chain(index.singles_by_artist.keys())
+            .copied()
+            .collect::<std::collections::HashSet<_>>()
+            .into_iter()
+            .collect();
+        index.artists.sort_by_key(artist_name);
+
+        for albums in index.albums_by_artist.values_mut() {
+            albums.sort_by_key(|id| {
+                db.albums
+                    .get(id)
+                    .map(|a| (a.year, a.title.to_lowercase()))
+                    .unwrap_or_default()
+            });
+        }
+        for tracks in index.tracks_by_album.values_mut() {
+            sort_tracks(db, tracks);
+        }
+        for tracks in index.singles_by_artist.values_mut() {
+            sort_tracks(db, tracks);
+        }
+
+        index.genres = genres.into_iter().collect();
+        index.genres.sort_by_key(|(name, _)| name.to_lowercase());
+        for (_, tracks) in index.genres.iter_mut() {
+            sort_tracks(db, tracks);
+        }
+
+        index.folders = folders.into_iter().collect();
+        index.folders.sort_by_key(|(id, _)| db.folders.get(id).map(|f| f.path.clone()));
+        for (_, tracks) in index.folders.iter_mut() {
+            tracks.sort_by_key(|id| db.tracks.get(id).map(|t| t.path.clone()));
+        }
+        index
+    }
+}
+
+/// לפי מספר השיר באלבום, ואחריו לפי השם
+fn sort_tracks(db: &LibraryDb, tracks: &mut [TrackId]) {
+    tracks.sort_by_key(|id| {
+        db.tracks
+            .get(id)
+            .map(|t| {
+                (
+                    t.info.metadata.track_number.unwrap_or(u32::MAX),
+                    t.info.metadata.display_title(&t.path).to_lowercase(),
+                )
+            })
+            .unwrap_or_default()
+    });
+}
+
+// =========================================================
+// Library Browser - אמן > אלבום > שיר, ז'אנרים ותיקיות
+// =========================================================
+#[derive(Default)]
+pub struct LibraryBrowser {
+    view: BrowserView,
+    artist: Option<ArtistId>,
+    album: Option<AlbumId>,
+    genre: Option<usize>,
+    folder: Option<FolderId>,
+    index: BrowseIndex,
+}
+
+impl LibraryBrowser {
+    pub fn show(
+        &mut self,
+        ui: &mut egui::Ui,
+        db: &LibraryDb,
+        covers: &mut CoverCache,
+        accent_color: Color32,
+    ) -> Option<BrowserAction> {
+        if self.index.revision != Some(db.revision()) {
+            self.index = BrowseIndex::build(db);
+            // מה שנבחר אולי נמחק בינתיים מהספרייה
+            self.artist = self.artist.filter(|a| self.index.artists.contains(a));
+            self.album = self.album.filter(|a| self.index.tracks_by_album.contains_key(a));
+            self.genre = self.genre.filter(|g| *g < self.index.genres.len());
+            self.folder = self.folder.filter(|f| db.folders.contains_key(f));
+        }
+
+        ui.add_space(5.0);
+        ui.horizontal(|ui| {
+            ui.selectable_value(&mut self.view, BrowserView::Artists, "🎤 Artists");
+            ui.selectable_value(&mut self.view, BrowserView::Genres, "🏷 Genres");
+            ui.selectable_value(&mut self.view, BrowserView::Folders, "📁 Folders");
+        });
+        ui.separator();
+
+        if db.tracks.is_empty() {
+            ui.label(
+                RichText::new("The library is empty.\nAdd a music folder to start.")
+                    .color(Color32::GRAY),
+            );
+            return None;
+        }
+
+        match self.view {
+            BrowserView::Artists => self.show_artists(ui, db, covers, accent_color),
+            BrowserView::Genres => self.show_genres(ui, db, accent_color),
+            BrowserView::Folders => self.show_folders(ui, db, accent_color),
+        }
+    }
+
+    fn show_artists(
+        &mut self,
+        ui: &mut egui::Ui,
+        db: &LibraryDb,
+        covers: &mut CoverCache,
+        accent_color: Color32,
+    ) -> Option<BrowserAction> {
+        // רמה 3: השירים של אלבום
+        if let (Some(artist), Some(album)) = (self.artist, self.album) {
+            let title = db
+                .albums
+                .get(&album)
+                .map(|a| a.title.clone())
+                .unwrap_or_default();
+            if breadcrumb(ui, &format!("{} › {}", artist_name(db, artist), title)) {
+                self.album = None;
+            }
+            let tracks = self.index.tracks_by_album.get(&album)?;
+            return track_list(ui, db, tracks, accent_color);
+        }
+
+        // רמה 2: האלבומים של אמן (ושירים בודדים בלי אלבום)
+        if let Some(artist) = self.artist {
+            if breadcrumb(ui, &artist_name(db, artist)) {
+                self.artist = None;
+                return None;
+            }
+            let albums = self.index.albums_by_artist.get(&artist).cloned();
+            let mut action = None;
+            egui::ScrollArea::vertical()
+                .id_salt("browser_albums")
+                .auto_shrink([false; 2])
+                .show(ui, |ui| {
+                    for album in albums.iter().flatten() {
+                        if self.album_row(ui, db, covers, *album) {
+                            self.album = Some(*album);
+                        }
+                    }
+                    if let Some(singles) = self.index.singles_by_artist.get(&artist) {
+                        ui.add_space(6.0);
+                        ui.label(RichText::new("Other tracks").strong().color(Color32::GRAY));
+                        action = track_rows(ui, db, singles, accent_color);
+                    }
+                });
+            return action;
+        }
+
+        // רמה 1: כל האמנים
+        let artists = &self.index.artists;
+        let mut clicked = None;
+        egui::ScrollArea::vertical()
+            .id_salt("browser_artists")
+            .auto_shrink([false; 2])
+            .show_rows(ui, ROW_HEIGHT, artists.len(), |ui, range| {
+                for artist in &artists[range] {
+                    let albums = self.index.albums_by_artist.get(artist).map_or(0, Vec::len);
+                    let label = format!("{}   ({} albums)", artist_name(db, *artist), albums);
+                    if ui.selectable_label(false, label).clicked() {
+                        clicked = Some(*artist);
+                    }
+                }
+            });
+        if clicked.is_some() {
+            self.artist = clicked;
+        }
+        None
+    }
+
+    /// שורה של אלבום עם עטיפה. מחזיר true אם לחצו עליה
+    fn album_row(
+        &self,
+        ui: &mut egui::Ui,
+        db: &LibraryDb,
+        covers: &mut CoverCache,
+        album: AlbumId,
+    ) -> bool {
+        let Some(row) = db.albums.get(&album) else {
+            return false;
+        };
+        let first_track = self
+            .index
+            .tracks_by_album
+            .get(&album)
+            .and_then(|tracks| tracks.first())
+            .and_then(|id| db.tracks.get(id));
+
+        let response = ui
+            .horizontal(|ui| {
+                ui.set_min_height(ALBUM_ROW_HEIGHT);
+                let cover = first_track.and_then(|t| covers.get(ui.ctx(), album, &t.path));
+                match cover {
+                    Some(texture) => {
+                        ui.add(
+                            egui::Image::new(&texture)
+                                .fit_to_exact_size(egui::vec2(COVER_SIZE, COVER_SIZE))
+                                .corner_radius(4),
+                        );
+                    }
+                    None => {
+                        let (rect, _) = ui.allocate_exact_size(
+                            egui::vec2(COVER_SIZE, COVER_SIZE),
+                            egui::Sense::hover(),
+                        );
+                        ui.painter().rect_filled(rect, 4.0, Color32::from_gray(45));
+                        ui.painter().text(
+                            rect.center(),
+                            egui::Align2::CENTER_CENTER,
+                            "💿",
+                            egui::FontId::proportional(20.0),
+                            Color32::GRAY,
+                        );
+                    }
+                }
+                ui.vertical(|ui| {
+                    ui.label(RichText::new(&row.title).strong());
+                    let year = row.year.map(|y| y.to_string()).unwrap_or_default();
+                    let count = self.index.tracks_by_album.get(&album).map_or(0, Vec::len);
+                    ui.label(
+                        RichText::new(format!("{}  {} tracks", year, count))
+                            .size(11.0)
+                            .color(Color32::GRAY),
+                    );
+                });
+            })
+            .response;
+
+        let id = ui.id().with(("album", album));
+        let response = ui.interact(response.rect, id, egui::Sense::click());
+        if response.hovered() {
+            ui.ctx().set_cursor_icon(egui::CursorIcon::PointingHand);
+        }
+        response.clicked()
+    }
+
+    fn show_genres(
+        &mut self,
+        ui: &mut egui::Ui,
+        db: &LibraryDb,
+        accent_color: Color32,
+    ) -> Option<BrowserAction> {
+        if let Some(genre) = self.genre {
+            let (name, tracks) = &self.index.genres[genre];
+            if breadcrumb(ui, name) {
+                self.genre = None;
+                return None;
+            }
+            return track_list(ui, db, tracks, accent_color);
+        }
+
+        let genres = &self.index.genres;
+        let mut clicked = None;
+        egui::ScrollArea::vertical()
+            .id_salt("browser_genres")
+            .auto_shrink([false; 2])
+            .show_rows(ui, ROW_HEIGHT, genres.len(), |ui, range| {
+                for idx in range {
+                    let (name, tracks) = &genres[idx];
+                    let label = format!("{}   ({})", name, tracks.len());
+                    if ui.selectable_label(false, label).clicked() {
+                        clicked = Some(idx);
+                    }
+                }
+            });
+        if clicked.is_some() {
+            self.genre = clicked;
+        }
+        None
+    }
+
+    fn show_folders(
+        &mut self,
+        ui: &mut egui::Ui,
+        db: &LibraryDb,
+        accent_color: Color32,
+    ) -> Option<BrowserAction> {
+        if let Some(folder) = self.folder {
+            let path = db
+                .folders
+                .get(&folder)
+                .map(|f| f.path.to_string_lossy().to_string())
+                .unwrap_or_default();
+            if breadcrumb(ui, &path) {
+                self.folder = None;
+                return None;
+            }
+            let tracks = self
+                .index
+                .folders
+                .iter()
+                .find(|(id, _)| *id == folder)
+                .map(|(_, tracks)| tracks)?;
+            return track_list(ui, db, tracks, accent_color);
+        }
+
+        let folders = &self.index.folders;
+        let mut clicked = None;
+        egui::ScrollArea::vertical()
+            .id_salt("browser_folders")
+            .auto_shrink([false; 2])
+            .show_rows(ui, ROW_HEIGHT, folders.len(), |ui, range| {
+                for (id, tracks) in &folders[range] {
+                    let Some(folder) = db.folders.get(id) else {
+                        continue;
+                    };
+                    let label = format!("📁 {}   ({})", folder.path.to_string_lossy(), tracks.len());
+                    if ui.selectable_label(false, label).clicked() {
+                        clicked = Some(*id);
+                    }
+                }
+            });
+        if clicked.is_some() {
+            self.folder = clicked;
+        }
+        None
+    }
+}
+
+fn artist_name(db: &LibraryDb, artist: ArtistId) -> String {
+    db.artists
+        .get(&artist)
+        .map(|a| a.name.clone())
+        .unwrap_or_else(|| "Unknown Artist".to_string())
+}
+
+/// שורת "חזרה" מעל רשימה. מחזיר true אם לחצו על החץ
+fn breadcrumb(ui: &mut egui::Ui, title: &str) -> bool {
+    let mut back = false;
+    ui.horizontal(|ui| {
+        back = ui.button("⬅").clicked();
+        ui.label(RichText::new(title).strong().size(14.0));
+    });
+    ui.separator();
+    back
+}
+
+/// רשימת שירים עם "נגן הכל" / "הוסף הכל" מעליה
+fn track_list(
+    ui: &mut egui::Ui,
+    db: &LibraryDb,
+    tracks: &[TrackId],
+    accent_color: Color32,
+) -> Option<BrowserAction> {
+    let paths = track_paths(db, tracks);
+    let mut action = None;
+    ui.horizontal(|ui| {
+        if ui.button("▶ Play All").clicked() {
+            action = Some(BrowserAction::Play(paths.clone(), 0));
+        }
+        if ui.button("➕ Add All").clicked() {
+            action = Some(BrowserAction::Append(paths.clone()));
+        }
+    });
+    egui::ScrollArea::vertical()
+        .id_salt("browser_tracks")
+        .auto_shrink([false; 2])
+        .show(ui, |ui| {
+            if let Some(row_action) = track_rows(ui, db, tracks, accent_color) {
+                action = Some(row_action);
+            }
+        });
+    action
+}
+
+/// השירים עצמם: לחיצה כפולה מנגנת מהשיר הזה, קליק ימני פותח תפריט
+fn track_rows(
+    ui: &mut egui::Ui,
+    db: &LibraryDb,
+    tracks: &[TrackId],
+    accent_color: Color32,
+) -> Option<BrowserAction> {
+    let mut action = None;
+    for (idx, id) in tracks.iter().enumerate() {
+        let Some(track) = db.tracks.get(id) else {
+            continue;
+        };
+        let meta = &track.info.metadata;
+        let number = meta
+            .track_number
+            .map(|n| format!("{:>2}. ", n))
+            .unwrap_or_default();
+        let secs = track.info.duration_secs as u64;
+
+        let response = ui
+            .horizontal(|ui| {
+                ui.label(
+                    RichText::new(format!("{}{}", number, meta.display_title(&track.path)))
+                        .color(Color32::LIGHT_GRAY),
+                );
+                ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
+                    ui.label(
+                        RichText::new(format!("{}:{:02}", secs / 60, secs % 60))
+                            .size(11.0)
+                            .color(Color32::GRAY),
+                    );
+                });
+            })
+            .response;
+        let response =
+            ui.interact(response.rect, ui.id().with(("track", *id)), egui::Sense::click());
+        if response.hovered() {
+            ui.painter()
+                .rect_filled(response.rect, 4.0, accent_color.gamma_multiply(0.08));
+        }
+        if response.double_clicked() {
+            action = Some(BrowserAction::Play(track_paths(db, tracks), idx));
+        }
+        response.context_menu(|ui| {
+            if ui.button("▶ Play").clicked() {
+                action = Some(BrowserAction::Play(vec![track.path.clone()], 0));
+                ui.close();
+            }
+            if ui.button("➕ Add to Playlist").clicked() {
+                action = Some(BrowserAction::Append(vec![track.path.clone()]));
+                ui.close();
+            }
+        });
+    }
+    action
+}
+
+fn track_paths(db: &LibraryDb, tracks: &[TrackId]) -> Vec<PathBuf> {
+    tracks
+        .iter()
+        .filter_map(|id| db.tracks.get(id))
+        .map(|t| t.path.clone())
+        .collect()
+}
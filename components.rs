@@ -1,24 +1,46 @@
 use crate::audio_engine::{AudioEngine, PlayerState};
+use crate::history::{self, HistoryEntry};
+use crate::library_db::{LibraryDb, TrackStats};
+use crate::metadata::TrackMetadata;
+use crate::play_order::{RepeatMode, ShuffleMode};
+use crate::playlist::Playlist;
+use crate::search;
+use crate::smart_playlist::{MatchMode, Rule, RuleField, RuleOp, SmartOrder, SmartRules};
+use crate::track_table::{RowData, TableColumn, TableConfig, sort_rows};
 use eframe::egui;
 use eframe::egui::{
     Align, Color32, CornerRadius, Layout, Pos2, Rect, RichText, Sense, Stroke, Vec2,
 };
+use egui_extras::{Column, TableBuilder};
+use std::collections::{HashSet, VecDeque};
 use std::path::PathBuf;   //COMPONENETS RS 
                           //COMPONENETS RS 
 // =========================================================
 // 1. ה-HEADER המקצועי (שתי קומות)
+
+/// מה נלחץ בכותרת. בקודם/הבא את השיר עצמו בוחר MusicApp לפי סדר הניגון
+pub enum HeaderAction {
+    Previous,
+    Next,
+    Rate(u8),
+    Love(bool),
+}
+
 pub fn draw_compact_header(
     ui: &mut egui::Ui,
     engine: &mut AudioEngine,
-    playlist: &Vec<std::path::PathBuf>,
-    selected_track: &mut Option<usize>,
+    repeat: &mut RepeatMode,
+    shuffle: &mut ShuffleMode,
     title: &str,
+    artist: &str,
+    stats: Option<&TrackStats>, // None - אין שיר, או שהוא עוד לא באינדקס
     accent_color: egui::Color32,
     icon_play: Option<&egui::TextureHandle>,
     icon_pause: Option<&egui::TextureHandle>,
     icon_next: Option<&egui::TextureHandle>,
     icon_prev: Option<&egui::TextureHandle>,
-) {
+) -> Option<HeaderAction> {
+    let mut action = None;
     ui.vertical(|ui| {
         ui.add_space(5.0);
 
@@ -38,12 +60,7 @@ pub fn draw_compact_header(
                 .frame(false); // מחקנו את הטינט מפה
 
                 if ui.add(prev_btn).clicked() {
-                    if let Some(idx) = *selected_track {
-                        if idx > 0 {
-                            *selected_track = Some(idx - 1);
-                            load_track(playlist, idx - 1, engine);
-                        }
-                    }
+                    action = Some(HeaderAction::Previous);
                 }
             }
 
@@ -83,24 +100,70 @@ pub fn draw_compact_header(
                 .frame(false);
 
                 if ui.add(next_btn).clicked() {
-                    if let Some(idx) = *selected_track {
-                        if idx < playlist.len() - 1 {
-                            *selected_track = Some(idx + 1);
-                            load_track(playlist, idx + 1, engine);
-                        }
-                    }
+                    action = Some(HeaderAction::Next);
                 }
             }
 
-            ui.add_space(15.0);
+            ui.add_space(10.0);
 
-            // כותרת השיר
-            ui.label(
-                egui::RichText::new(title)
+            // --- חזרה וערבוב: כל לחיצה עוברת למצב הבא ---
+            let mode_color = |active: bool| {
+                if active {
+                    accent_color
+                } else {
+                    egui::Color32::GRAY
+                }
+            };
+            let repeat_icon = if *repeat == RepeatMode::One { "🔂" } else { "🔁" };
+            let repeat_btn = egui::Button::new(
+                egui::RichText::new(repeat_icon)
                     .size(18.0)
-                    .strong()
-                    .color(egui::Color32::WHITE),
-            );
+                    .color(mode_color(*repeat != RepeatMode::Off)),
+            )
+            .frame(false);
+            if ui.add(repeat_btn).on_hover_text(repeat.label()).clicked() {
+                *repeat = repeat.cycle();
+            }
+
+            let shuffle_text = if *shuffle == ShuffleMode::Smart { "🔀✨" } else { "🔀" };
+            let shuffle_btn = egui::Button::new(
+                egui::RichText::new(shuffle_text)
+                    .size(18.0)
+                    .color(mode_color(*shuffle != ShuffleMode::Off)),
+            )
+            .frame(false);
+            if ui.add(shuffle_btn).on_hover_text(shuffle.label()).clicked() {
+                *shuffle = shuffle.cycle();
+            }
+
+            ui.add_space(15.0);
+
+            // כותרת השיר והאמן (מהתגיות של הקובץ)
+            ui.vertical(|ui| {
+                ui.label(
+                    egui::RichText::new(title)
+                        .size(18.0)
+                        .strong()
+                        .color(egui::Color32::WHITE),
+                );
+                if !artist.is_empty() {
+                    ui.label(
+                        egui::RichText::new(artist)
+                            .size(13.0)
+                            .color(egui::Color32::LIGHT_GRAY),
+                    );
+                }
+                if let Some(stats) = stats {
+                    ui.horizontal(|ui| {
+                        if love_button(ui, stats.loved, 15.0) {
+                            action = Some(HeaderAction::Love(!stats.loved));
+                        }
+                        if let Some(rating) = rating_stars(ui, stats.rating, 15.0, accent_color) {
+                            action = Some(HeaderAction::Rate(rating));
+                        }
+                    });
+                }
+            });
         });
 
         ui.add_space(12.0);
@@ -110,6 +173,7 @@ pub fn draw_compact_header(
 
         ui.add_space(5.0);
     });
+    action
 }
 
 
@@ -130,8 +194,8 @@ pub fn draw_progress_bar(ui: &mut egui::Ui, engine: &mut AudioEngine, color: Col
         // זמן נוכחי (עכשיו יזוז חלק עם העכבר)
         ui.label(RichText::new(format_time(position as f64)).size(11.0).color(color));
 
-        // חישוב רוחב: לוקחים את כל מה שנשאר פחות המקום לזמן בצד השני
-        let available_width = ui.available_width() - 40.0;
+        // חישוב רוחב: לוקחים את כל מה שנשאר פחות המקום לזמן ולכפתורי A-B בצד השני
+        let available_width = ui.available_width() - 120.0;
         let height = 6.0; // עובי הבר
 
         let (rect, response) =
@@ -148,6 +212,39 @@ pub fn draw_progress_bar(ui: &mut egui::Ui, engine: &mut AudioEngine, color: Col
             Color32::from_white_alpha(20),
         );
 
+        // 1.5 לולאת A-B: הקטע המסומן והסמנים עצמם
+        if duration > 0.0 {
+            let x_for = |secs: f64| {
+                bg_rect.min.x + bg_rect.width() * (secs / duration).clamp(0.0, 1.0) as f32
+            };
+
+            if let (Some(a), Some(b)) = (engine.loop_a, engine.loop_b) {
+                let loop_rect = Rect::from_min_max(
+                    Pos2::new(x_for(a), rect.min.y),
+                    Pos2::new(x_for(b), rect.max.y),
+                );
+                ui.painter()
+                    .rect_filled(loop_rect, CornerRadius::same(2), color.gamma_multiply(0.25));
+            }
+
+            for (marker, label) in [(engine.loop_a, "A"), (engine.loop_b, "B")] {
+                if let Some(secs) = marker {
+                    let x = x_for(secs);
+                    ui.painter().line_segment(
+                        [Pos2::new(x, rect.min.y - 2.0), Pos2::new(x, rect.max.y + 2.0)],
+                        Stroke::new(2.0, Color32::WHITE),
+                    );
+                    ui.painter().text(
+                        Pos2::new(x, rect.min.y - 2.0),
+                        egui::Align2::CENTER_BOTTOM,
+                        label,
+                        egui::FontId::proportional(9.0),
+                        Color32::WHITE,
+                    );
+                }
+            }
+        }
+
         // 2. ציור המילוי (הפס הצבעוני)
         if duration > 0.0 {
             let percent = (position / duration).clamp(0.0, 1.0);
@@ -183,7 +280,7 @@ pub fn draw_progress_bar(ui: &mut egui::Ui, engine: &mut AudioEngine, color: Col
 
             // שולחים פקודה למנוע מכה אחת בולטת *רק* בעזיבת עכבר או קליק
             if response.drag_stopped() || response.clicked() {
-                engine.seek(target_time as f32);
+                engine.seek(p * 100.0); // המנוע מצפה לאחוזים, לא לשניות
                 ui.data_mut(|d| d.remove_temp::<f32>(id)); // מנקים את הזיכרון
             }
         } else if !response.dragged() {
@@ -191,12 +288,68 @@ pub fn draw_progress_bar(ui: &mut egui::Ui, engine: &mut AudioEngine, color: Col
             ui.data_mut(|d| d.remove_temp::<f32>(id));
         }
 
+        // --- קליק ימני על הבר: סימון A/B במקום שנלחץ והגדרות תרגול ---
+        let menu_pos_id = id.with("loop_menu_pos");
+        if response.secondary_clicked()
+            && let Some(pointer_pos) = response.interact_pointer_pos()
+        {
+            let p = ((pointer_pos.x - rect.min.x) / rect.width()).clamp(0.0, 1.0);
+            ui.data_mut(|d| d.insert_temp(menu_pos_id, p as f64 * duration));
+        }
+        response.context_menu(|ui| {
+            let secs = ui.data(|d| d.get_temp::<f64>(menu_pos_id)).unwrap_or(position);
+            if ui.button(format!("Set A at {}", format_time(secs))).clicked() {
+                engine.set_loop_a(secs);
+                ui.close();
+            }
+            if ui.button(format!("Set B at {}", format_time(secs))).clicked() {
+                engine.set_loop_b(secs);
+                ui.close();
+            }
+            if ui.button("✖ Clear Loop").clicked() {
+                engine.clear_loop();
+                ui.close();
+            }
+
+            ui.separator();
+            ui.label("🎸 Practice Mode:");
+            let mut speed = engine.loop_speed;
+            let mut count_in = engine.loop_count_in;
+            let speed_changed = ui
+                .add(egui::Slider::new(&mut speed, 0.25..=1.0).text("Loop Speed"))
+                .changed();
+            let count_in_changed = ui
+                .add(
+                    egui::Slider::new(&mut count_in, 0.0..=10.0)
+                        .suffix(" s")
+                        .text("Count-in Gap"),
+                )
+                .changed();
+            if speed_changed || count_in_changed {
+                engine.set_loop_practice(speed, count_in);
+            }
+        });
+
         // זמן סיום
         ui.label(
             RichText::new(format_time(duration as f64))
                 .size(11.0)
                 .color(Color32::GRAY),
         );
+
+        // --- כפתורי A-B מהירים (מסמנים את המיקום הנוכחי) ---
+        let now = engine.current_position;
+        if ui.small_button("A").on_hover_text("Set loop start").clicked() {
+            engine.set_loop_a(now);
+        }
+        if ui.small_button("B").on_hover_text("Set loop end").clicked() {
+            engine.set_loop_b(now);
+        }
+        if (engine.loop_a.is_some() || engine.loop_b.is_some())
+            && ui.small_button("✖").on_hover_text("Clear loop").clicked()
+        {
+            engine.clear_loop();
+        }
     });
 }
 
@@ -321,16 +474,101 @@ pub fn draw_equalizer(
         });
 }
 
+/// מה קרה ברשימה שצריך לטפל בו ב-MusicApp
+pub enum PlaylistAction {
+    Started, // שיר נבחר והתחיל להתנגן
+    PlayNext(Vec<PathBuf>),
+    AddToQueue(Vec<PathBuf>),
+    AddToPlaylist(Vec<PathBuf>, usize),
+    DropAt(usize), // קבצים שוחררו מעל הרשימה - לפני השיר הזה
+    AddFiles(Vec<PathBuf>), // מ-"Add Files" - עוברים דרך הסריקות כמו כל קובץ חדש
+    Rate(Vec<PathBuf>, u8),
+    Love(Vec<PathBuf>, bool),
+}
+
+/// הבחירה המרובה ברשימה. שמורה לפי נתיב, כדי שתישאר נכונה גם כשהשורות זזות
+#[derive(Default)]
+pub struct TrackSelection {
+    paths: HashSet<PathBuf>,
+    anchor: Option<PathBuf>, // השורה שממנה Shift+Click בוחר טווח
+}
+
+impl TrackSelection {
+    fn select_only(&mut self, path: &PathBuf) {
+        self.paths.clear();
+        self.paths.insert(path.clone());
+        self.anchor = Some(path.clone());
+    }
+
+    /// האינדקסים הנבחרים, בסדר של הרשימה
+    fn indices(&self, items: &[PathBuf]) -> Vec<usize> {
+        (0..items.len())
+            .filter(|&idx| self.paths.contains(&items[idx]))
+            .collect()
+    }
+}
+
+/// גרירה של שורות בתוך הרשימה (ה-Payload של egui - האינדקס של השורה שנגררת)
+struct RowDrag(usize);
+
+/// שינוי ברשימה עצמה - מתבצע אחרי הלולאה על השורות
+enum RowEdit {
+    Move(Vec<usize>, usize),
+    Remove(Vec<usize>),
+}
+
+/// "אמן - שם" אם כבר יש תגיות לשיר, אחרת שם הקובץ
+pub fn track_display_name(
+    path: &std::path::Path,
+    track_tags: &std::collections::HashMap<PathBuf, TrackMetadata>,
+) -> String {
+    match track_tags.get(path) {
+        Some(meta) => match &meta.artist {
+            Some(artist) => format!("{} - {}", artist, meta.display_title(path)),
+            None => meta.display_title(path),
+        },
+        None => path
+            .file_stem()
+            .unwrap_or_default()
+            .to_string_lossy()
+            .to_string(),
+    }
+}
+
+/// מה שהטבלה צריכה מסביב לפלייליסט עצמו: מצב התצוגה שנשמר בין פריימים,
+/// ומה שהיא רק קוראת (שמות הרשימות, תגיות, הספרייה)
+pub struct PlaylistView<'a> {
+    pub selection: &'a mut TrackSelection,
+    pub search: &'a mut String,
+    pub table: &'a mut TableConfig,
+    pub add_targets: &'a [(usize, String)], // "Add to Playlist": רשימות רגילות חוץ מזו
+    pub track_tags: &'a std::collections::HashMap<PathBuf, TrackMetadata>,
+    pub library: Option<&'a LibraryDb>,
+}
+
 pub fn draw_playlist(
     ui: &mut egui::Ui,
-    playlist: &mut Vec<std::path::PathBuf>,
-    selected_track: &mut Option<usize>,
+    playlist: &mut Playlist,
+    view: PlaylistView<'_>,
     engine: &mut AudioEngine,
     accent_color: Color32, // מביאים את הצבע מה-main!
-) {
+) -> Option<PlaylistAction> {
+    let PlaylistView {
+        selection,
+        search,
+        table,
+        add_targets,
+        track_tags,
+        library,
+    } = view;
+    // רשימה חכמה נבנית מהכללים - אין בה הוספה, הסרה או סידור ידני
+    let editable = playlist.rules.is_none();
+    let mut action = None;
+    let mut edit = None;
+    let mut play_first_match = false;
     ui.add_space(5.0);
 
-    // --- כותרת וכפתור הוספה ---
+    // --- כותרת, חיפוש וכפתור הוספה ---
     ui.horizontal(|ui| {
         ui.label(
             RichText::new("🎵 MY LIBRARY")
@@ -339,76 +577,857 @@ pub fn draw_playlist(
                 .color(Color32::LIGHT_GRAY),
         );
         ui.with_layout(Layout::right_to_left(Align::Center), |ui| {
-            if ui.button(RichText::new("➕ Add Files").size(12.0)).clicked()
+            if editable
+                && ui.button(RichText::new("➕ Add Files").size(12.0)).clicked()
                 && let Some(paths) = rfd::FileDialog::new()
                     .add_filter("Audio", &["mp3", "wav", "flac", "ogg", "aac", "m4a", "mp4"])
                     .pick_files()
             {
-                for path in paths {
-                    playlist.push(path);
+                action = Some(PlaylistAction::AddFiles(paths));
+            }
+
+            if !search.is_empty() && ui.small_button("✖").on_hover_text("Clear").clicked() {
+                search.clear();
+            }
+            let search_response = ui.add(
+                egui::TextEdit::singleline(search)
+                    .hint_text("🔍 Search (Ctrl+F)")
+                    .desired_width(220.0),
+            );
+            if ui.input_mut(|i| i.consume_key(egui::Modifiers::COMMAND, egui::Key::F)) {
+                search_response.request_focus();
+            }
+            if search_response.has_focus() && ui.input(|i| i.key_pressed(egui::Key::Escape)) {
+                search.clear();
+                search_response.surrender_focus();
+            }
+            // Enter בשדה החיפוש - מנגן את התוצאה הראשונה
+            play_first_match =
+                search_response.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter));
+        });
+    });
+
+    ui.add_space(5.0);
+    ui.separator();
+
+    let row_data = |idx: usize| {
+        let path = &playlist.items[idx];
+        let track = library.and_then(|db| db.track(path));
+        RowData {
+            number: idx + 1,
+            path,
+            meta: track_tags.get(path),
+            entry: track.map(|row| &row.info),
+            stats: track.map(|row| &row.stats),
+        }
+    };
+
+    // השורות שמוצגות: כולן, או רק מה שמתאים לחיפוש - בסדר של הפלייליסט או לפי המיון
+    let query = search.trim();
+    let mut visible: Vec<usize> = (0..playlist.items.len())
+        .filter(|&idx| {
+            let path = &playlist.items[idx];
+            query.is_empty() || search::matches_track(query, path, track_tags.get(path))
+        })
+        .collect();
+    if let Some((column, ascending)) = table.sort {
+        sort_rows(&mut visible, column, ascending, row_data);
+    }
+    if !query.is_empty() {
+        ui.label(
+            RichText::new(format!("{} of {} tracks", visible.len(), playlist.items.len()))
+                .size(11.0)
+                .color(Color32::GRAY),
+        );
+    }
+    if play_first_match && let Some(&idx) = visible.first() {
+        selection.select_only(&playlist.items[idx]);
+        playlist.current_index = Some(idx);
+        load_track(&playlist.items, idx, engine);
+        action = Some(PlaylistAction::Started);
+    }
+
+    // --- גרירה: קבצים מבחוץ או שורות בתוך הרשימה - איפה הם ייכנסו ---
+    let (hovering_files, dropped_files) = ui
+        .ctx()
+        .input(|i| (!i.raw.hovered_files.is_empty(), !i.raw.dropped_files.is_empty()));
+    // בתצוגה ממוינת אין משמעות ל"לפני השורה הזו" - אז אין גם סידור בגרירה
+    let can_reorder = editable && table.sort.is_none();
+    let dragging_rows = egui::DragAndDrop::has_payload_of_type::<RowDrag>(ui.ctx());
+    let released = ui.input(|i| i.pointer.any_released());
+    let pointer = ui.ctx().input(|i| i.pointer.latest_pos());
+    if hovering_files && editable {
+        ui.label(RichText::new("📥 Drop to add here").size(12.0).color(accent_color));
+    }
+
+    // Delete מוחק את השורות הנבחרות (כשלא מקלידים בשדה טקסט)
+    if editable
+        && !selection.paths.is_empty()
+        && !ui.ctx().wants_keyboard_input()
+        && ui.input(|i| i.key_pressed(egui::Key::Delete))
+    {
+        edit = Some(RowEdit::Remove(selection.indices(&playlist.items)));
+    }
+
+    // --- הטבלה ---
+    let list_rect = ui.available_rect_before_wrap();
+    let drop_pointer = pointer.filter(|pos| {
+        editable && (hovering_files || dropped_files || dragging_rows) && list_rect.contains(*pos)
+    });
+    let mut drop_at: Option<(usize, f32)> = None; // (אינדקס, גובה הקו)
+    let mut last_bottom = None;
+
+    let columns = table.visible_columns();
+    let mut builder = TableBuilder::new(ui)
+        .id_salt("track_table")
+        .striped(true)
+        .resizable(true)
+        .sense(Sense::click_and_drag())
+        .cell_layout(Layout::left_to_right(Align::Center));
+    for state in &columns {
+        builder = builder.column(Column::initial(state.width).at_least(30.0).clip(true));
+    }
+
+    let mut sort_clicked = None;
+    let mut toggle_column = None;
+    builder
+        .header(24.0, |mut header| {
+            for state in &columns {
+                let (rect, _) = header.col(|ui| {
+                    let arrow = match table.sort {
+                        Some((column, true)) if column == state.column => " ⏶",
+                        Some((column, false)) if column == state.column => " ⏷",
+                        _ => "",
+                    };
+                    let label = RichText::new(format!("{}{}", state.column.label(), arrow))
+                        .strong()
+                        .color(Color32::LIGHT_GRAY);
+                    let response = ui.add(egui::Button::new(label).frame(false));
+                    if response.clicked() {
+                        sort_clicked = Some(state.column);
+                    }
+                    // לחיצה ימנית על כותרת - הסתרה / הצגה של עמודות
+                    response.context_menu(|ui| {
+                        for other in &table.columns {
+                            let mut shown = other.visible;
+                            let checkbox = egui::Checkbox::new(&mut shown, other.column.label());
+                            if ui.add_enabled(other.column.can_hide(), checkbox).changed() {
+                                toggle_column = Some(other.column);
+                            }
+                        }
+                    });
+                });
+                // הרוחב אחרי גרירה של המפריד - נשמר ב-AppState
+                table.set_width(state.column, rect.width());
+            }
+        })
+        .body(|body| {
+            body.rows(28.0, visible.len(), |mut row| {
+                let idx = visible[row.index()];
+                let data = row_data(idx);
+                let path = &playlist.items[idx];
+                let is_current = Some(idx) == playlist.current_index;
+                let text_color = if is_current { accent_color } else { Color32::LIGHT_GRAY };
+                row.set_selected(selection.paths.contains(path));
+
+                for state in &columns {
+                    row.col(|ui| {
+                        if state.column == TableColumn::Rating {
+                            // שיר שעוד לא באינדקס - אין איפה לשמור דירוג
+                            let Some(stats) = data.stats else {
+                                return;
+                            };
+                            let target = vec![path.clone()];
+                            if love_button(ui, stats.loved, 13.0) {
+                                action = Some(PlaylistAction::Love(target.clone(), !stats.loved));
+                            }
+                            if let Some(rating) = rating_stars(ui, stats.rating, 13.0, accent_color)
+                            {
+                                action = Some(PlaylistAction::Rate(target, rating));
+                            }
+                            return;
+                        }
+                        let text = match state.column {
+                            TableColumn::Number if is_current => "▶".to_string(),
+                            column => data.text(column),
+                        };
+                        let highlights = match state.column {
+                            TableColumn::Title | TableColumn::Artist | TableColumn::Album => {
+                                search::highlight_positions(query, &text)
+                            }
+                            _ => Vec::new(),
+                        };
+                        ui.add(
+                            egui::Label::new(highlighted_text(
+                                &text,
+                                &highlights,
+                                text_color,
+                                accent_color,
+                                13.0,
+                            ))
+                            .selectable(false)
+                            .truncate(),
+                        );
+                    });
                 }
+
+                let response = row.response();
+                let rect = response.rect;
+                if row.index() + 1 == visible.len() {
+                    last_bottom = Some(rect.bottom());
+                }
+                if let Some(pos) = drop_pointer
+                    && (rect.top()..=rect.bottom()).contains(&pos.y)
+                {
+                    drop_at = if pos.y < rect.center().y {
+                        Some((idx, rect.top()))
+                    } else {
+                        Some((idx + 1, rect.bottom()))
+                    };
+                }
+                if can_reorder {
+                    response.dnd_set_drag_payload(RowDrag(idx));
+                }
+
+                if response.clicked() {
+                    let modifiers = response.ctx.input(|i| i.modifiers);
+                    let anchor = selection
+                        .anchor
+                        .as_ref()
+                        .and_then(|a| playlist.items.iter().position(|p| p == a))
+                        .and_then(|a| visible.iter().position(|&i| i == a));
+                    let position = row.index();
+                    if modifiers.shift && let Some(anchor) = anchor {
+                        // Shift: טווח מהעוגן לפי מה שמוצג (עם Ctrl - מוסיף לבחירה הקיימת)
+                        if !modifiers.command {
+                            selection.paths.clear();
+                        }
+                        let (from, to) = (anchor.min(position), anchor.max(position));
+                        let range = visible[from..=to].iter().map(|&i| playlist.items[i].clone());
+                        selection.paths.extend(range);
+                    } else if modifiers.command {
+                        if !selection.paths.remove(path) {
+                            selection.paths.insert(path.clone());
+                        }
+                        selection.anchor = Some(path.clone());
+                    } else {
+                        selection.select_only(path);
+                        playlist.current_index = Some(idx);
+                        load_track(&playlist.items, idx, engine);
+                        action = Some(PlaylistAction::Started);
+                    }
+                }
+
+                // לחיצה ימנית על שורה שלא בבחירה - התפריט פועל רק עליה
+                if response.secondary_clicked() && !selection.paths.contains(path) {
+                    selection.select_only(path);
+                }
+                response.context_menu(|ui| {
+                    // ברשימה חכמה הסדר והתוכן נקבעים מהכללים - אין הזזה או הסרה
+                    row_context_menu(
+                        ui,
+                        &playlist.items,
+                        selection,
+                        path,
+                        add_targets,
+                        &mut action,
+                        editable.then_some(&mut edit),
+                    );
+                });
+            });
+        });
+
+    if let Some(column) = sort_clicked {
+        table.toggle_sort(column);
+    }
+    if let Some(column) = toggle_column
+        && let Some(state) = table.columns.iter_mut().find(|c| c.column == column)
+    {
+        state.visible = !state.visible;
+    }
+
+    // מתחת לשיר האחרון (או ברשימה ריקה) = בסוף הרשימה
+    if drop_pointer.is_some() {
+        let (idx, y) = match (drop_at, can_reorder) {
+            (Some(at), true) => at,
+            _ => (playlist.items.len(), last_bottom.unwrap_or(list_rect.top())),
+        };
+        ui.painter().hline(list_rect.x_range(), y, Stroke::new(2.0, accent_color));
+        if dropped_files {
+            action = Some(PlaylistAction::DropAt(idx));
+        }
+        if released
+            && can_reorder
+            && let Some(drag) = egui::DragAndDrop::take_payload::<RowDrag>(ui.ctx())
+        {
+            // גוררים שורה מתוך הבחירה - זזה כל הבחירה, אחרת רק השורה
+            let moving = match playlist.items.get(drag.0) {
+                Some(dragged) if selection.paths.contains(dragged) => {
+                    selection.indices(&playlist.items)
+                }
+                _ => vec![drag.0],
+            };
+            edit = Some(RowEdit::Move(moving, idx));
+        }
+    }
+
+    match edit {
+        Some(RowEdit::Move(indices, at)) => playlist.move_indices(&indices, at),
+        Some(RowEdit::Remove(indices)) => {
+            playlist.remove_indices(&indices);
+            selection.paths.clear();
+        }
+        None => {}
+    }
+    action
+}
+
+/// התפריט של שורה (או של כל השורות הנבחרות)
+fn row_context_menu(
+    ui: &mut egui::Ui,
+    items: &[PathBuf],
+    selection: &TrackSelection,
+    path: &std::path::Path,
+    add_targets: &[(usize, String)],
+    action: &mut Option<PlaylistAction>,
+    edit: Option<&mut Option<RowEdit>>, // None = רשימה שלא נערכת ידנית
+) {
+    let targets = selection.indices(items);
+    let target_paths: Vec<PathBuf> = targets.iter().map(|&i| items[i].clone()).collect();
+
+    if ui.button("⏭ Play Next").clicked() {
+        *action = Some(PlaylistAction::PlayNext(target_paths.clone()));
+        ui.close();
+    }
+    if ui.button("➕ Add to Queue").clicked() {
+        *action = Some(PlaylistAction::AddToQueue(target_paths.clone()));
+        ui.close();
+    }
+    ui.add_enabled_ui(!add_targets.is_empty(), |ui| {
+        ui.menu_button("📋 Add to Playlist", |ui| {
+            for (list, name) in add_targets {
+                if ui.button(name).clicked() {
+                    *action = Some(PlaylistAction::AddToPlaylist(target_paths.clone(), *list));
+                    ui.close();
+                }
+            }
+        });
+    });
+    ui.menu_button("★ Rating", |ui| {
+        for stars in (0..=5u8).rev() {
+            let label = if stars == 0 { "No Rating".to_string() } else { "★".repeat(stars.into()) };
+            if ui.button(label).clicked() {
+                *action = Some(PlaylistAction::Rate(target_paths.clone(), stars));
+                ui.close();
+            }
+        }
+    });
+    if ui.button("♥ Love").clicked() {
+        *action = Some(PlaylistAction::Love(target_paths.clone(), true));
+        ui.close();
+    }
+    if ui.button("♡ Unlove").clicked() {
+        *action = Some(PlaylistAction::Love(target_paths.clone(), false));
+        ui.close();
+    }
+    if let Some(edit) = edit {
+        ui.separator();
+        if ui.button("⏫ Move to Top").clicked() {
+            *edit = Some(RowEdit::Move(targets.clone(), 0));
+            ui.close();
+        }
+        if ui.button("⏬ Move to Bottom").clicked() {
+            *edit = Some(RowEdit::Move(targets.clone(), items.len()));
+            ui.close();
+        }
+        if ui.button("✖ Remove").clicked() {
+            *edit = Some(RowEdit::Remove(targets.clone()));
+            ui.close();
+        }
+    }
+    ui.separator();
+    if ui.button("📂 Show in Folder").clicked() {
+        show_in_folder(path);
+        ui.close();
+    }
+    if ui.button("📄 Copy Path").clicked() {
+        let text: Vec<String> = target_paths
+            .iter()
+            .map(|p| p.to_string_lossy().to_string())
+            .collect();
+        ui.ctx().copy_text(text.join("\n"));
+        ui.close();
+    }
+}
+
+/// חמישה כוכבים ללחיצה. לחיצה על הדירוג הנוכחי מוחקת אותו. מחזיר את הדירוג החדש
+pub fn rating_stars(ui: &mut egui::Ui, rating: u8, size: f32, accent_color: Color32) -> Option<u8> {
+    let mut changed = None;
+    ui.horizontal(|ui| {
+        ui.spacing_mut().item_spacing.x = 1.0;
+        for star in 1..=5u8 {
+            let (text, color) = if star <= rating {
+                ("★", accent_color)
+            } else {
+                ("☆", Color32::GRAY)
+            };
+            let label = egui::Label::new(RichText::new(text).size(size).color(color));
+            let response = ui
+                .add(label.sense(Sense::click()))
+                .on_hover_text(format!("{} / 5", star));
+            if response.clicked() {
+                changed = Some(if star == rating { 0 } else { star });
+            }
+        }
+    });
+    changed
+}
+
+/// הלב של "אהבתי" - מחזיר true כשלוחצים עליו
+pub fn love_button(ui: &mut egui::Ui, loved: bool, size: f32) -> bool {
+    let (text, color, hint) = if loved {
+        ("♥", Color32::from_rgb(230, 70, 90), "Loved")
+    } else {
+        ("♡", Color32::GRAY, "Love")
+    };
+    ui.add(egui::Label::new(RichText::new(text).size(size).color(color)).sense(Sense::click()))
+        .on_hover_text(hint)
+        .clicked()
+}
+
+/// טקסט של שורה שבו התווים שהתאימו לחיפוש צבועים ברקע של צבע ההדגשה
+fn highlighted_text(
+    text: &str,
+    highlights: &[usize],
+    color: Color32,
+    accent_color: Color32,
+    size: f32,
+) -> egui::text::LayoutJob {
+    let normal = egui::TextFormat {
+        font_id: egui::FontId::proportional(size),
+        color,
+        ..Default::default()
+    };
+    let highlighted = egui::TextFormat {
+        background: accent_color.gamma_multiply(0.35),
+        color: Color32::WHITE,
+        ..normal.clone()
+    };
+
+    let mut job = egui::text::LayoutJob::default();
+    // רצפים של תווים מודגשים / רגילים - כל רצף הוא קטע אחד ב-LayoutJob
+    let chars: Vec<char> = text.chars().collect();
+    let mut start = 0;
+    while start < chars.len() {
+        let lit = highlights.contains(&start);
+        let end = (start..chars.len())
+            .find(|i| highlights.contains(i) != lit)
+            .unwrap_or(chars.len());
+        let segment: String = chars[start..end].iter().collect();
+        let format = if lit { &highlighted } else { &normal };
+        job.append(&segment, 0.0, format.clone());
+        start = end;
+    }
+    job
+}
+
+/// פותח את התיקייה של הקובץ במנהל הקבצים
+fn show_in_folder(path: &std::path::Path) {
+    if let Some(folder) = path.parent()
+        && let Err(e) = std::process::Command::new("xdg-open").arg(folder).spawn()
+    {
+        eprintln!("Failed to open folder {:?}: {}", folder, e);
+    }
+}
+
+/// התור ("Up Next"): סידור מחדש, הסרה וניקוי. מוצג רק כשיש בו משהו
+pub fn draw_queue(
+    ui: &mut egui::Ui,
+    queue: &mut VecDeque<PathBuf>,
+    track_tags: &std::collections::HashMap<PathBuf, TrackMetadata>,
+    accent_color: Color32,
+) {
+    if queue.is_empty() {
+        return;
+    }
+
+    ui.horizontal(|ui| {
+        ui.label(
+            RichText::new(format!("⏭ UP NEXT ({})", queue.len()))
+                .strong()
+                .size(12.0)
+                .color(Color32::LIGHT_GRAY),
+        );
+        ui.with_layout(Layout::right_to_left(Align::Center), |ui| {
+            if ui.small_button("Clear").clicked() {
+                queue.clear();
             }
         });
     });
 
+    let mut move_up = None;
+    let mut remove = None;
+    egui::ScrollArea::vertical()
+        .id_salt("play_queue")
+        .max_height(200.0)
+        .show(ui, |ui| {
+            for (idx, path) in queue.iter().enumerate() {
+                ui.horizontal(|ui| {
+                    let text_color = if idx == 0 { accent_color } else { Color32::LIGHT_GRAY };
+                    if ui.small_button("✖").on_hover_text("Remove from queue").clicked() {
+                        remove = Some(idx);
+                    }
+                    if ui
+                        .add_enabled(idx > 0, egui::Button::new("⬆").small())
+                        .on_hover_text("Move up")
+                        .clicked()
+                    {
+                        move_up = Some(idx);
+                    }
+                    ui.add(
+                        egui::Label::new(
+                            RichText::new(track_display_name(path, track_tags))
+                                .size(12.0)
+                                .color(text_color),
+                        )
+                        .truncate(),
+                    );
+                });
+            }
+        });
+
+    if let Some(idx) = move_up {
+        queue.swap(idx, idx - 1);
+    }
+    if let Some(idx) = remove {
+        queue.remove(idx);
+    }
+}
+
+/// מה המשתמש ביקש מחלון ההיסטוריה
+pub enum HistoryAction {
+    Play(PathBuf),
+    RecentlyPlayed,
+}
+
+/// יומן ההאזנה, מהחדש לישן. לחיצה כפולה מנגנת את השיר שוב
+pub fn draw_history(
+    ui: &mut egui::Ui,
+    entries: &[HistoryEntry],
+    track_tags: &std::collections::HashMap<PathBuf, TrackMetadata>,
+    now: u64,
+    accent_color: Color32,
+) -> Option<HistoryAction> {
+    let mut action = None;
+
+    ui.horizontal(|ui| {
+        ui.label(
+            RichText::new(format!("{} plays", entries.len()))
+                .size(12.0)
+                .color(Color32::LIGHT_GRAY),
+        );
+        ui.with_layout(Layout::right_to_left(Align::Center), |ui| {
+            if ui
+                .button("✨ Recently Played")
+                .on_hover_text("Open a smart playlist of recently played tracks")
+                .clicked()
+            {
+                action = Some(HistoryAction::RecentlyPlayed);
+            }
+        });
+    });
+    ui.separator();
+
+    if entries.is_empty() {
+        ui.label(RichText::new("Nothing played yet").italics().color(Color32::GRAY));
+        return action;
+    }
+
+    let row_height = ui.text_style_height(&egui::TextStyle::Body) + 4.0;
+    egui::ScrollArea::vertical()
+        .id_salt("listening_history")
+        .auto_shrink([false, false])
+        .show_rows(ui, row_height, entries.len(), |ui, range| {
+            for entry in entries.iter().rev().skip(range.start).take(range.len()) {
+                ui.horizontal(|ui| {
+                    ui.add_sized(
+                        [90.0, row_height],
+                        egui::Label::new(
+                            RichText::new(history::format_played_at(entry.played_at, now))
+                                .size(12.0)
+                                .color(Color32::GRAY),
+                        ),
+                    );
+                    let name = ui
+                        .add(
+                            egui::Label::new(
+                                RichText::new(track_display_name(&entry.path, track_tags))
+                                    .color(accent_color),
+                            )
+                            .truncate()
+                            .sense(Sense::click()),
+                        )
+                        .on_hover_text(entry.path.to_string_lossy());
+                    if name.double_clicked() {
+                        action = Some(HistoryAction::Play(entry.path.clone()));
+                    }
+                });
+            }
+        });
+
+    action
+}
+
+/// מה המשתמש ביקש לעשות עם הפלייליסטים. מחיקה ושכפול מזיזים אינדקסים שמוחזקים
+/// גם מחוץ לסרגל (הרשימה שמתנגנת, עורך הכללים, הערבוב) - ולכן מטופלים ב-main
+pub enum SidebarAction {
+    Duplicate(usize),
+    Delete(usize),
+    Smart(SmartPlaylistRequest),
+}
+
+/// פלייליסטים חכמים נערכים בחלון נפרד - הסרגל רק מבקש לפתוח אותו
+pub enum SmartPlaylistRequest {
+    New,
+    Edit(usize),
+}
+
+/// הרשימה בצד: מעבר בין פלייליסטים, יצירה, שינוי שם, שכפול ומחיקה
+pub fn draw_playlist_sidebar(
+    ui: &mut egui::Ui,
+    playlists: &mut Vec<Playlist>,
+    active: &mut usize,
+    playing: usize,
+    renaming: &mut Option<(usize, String)>,
+    accent_color: Color32,
+) -> Option<SidebarAction> {
+    let mut action = None;
     ui.add_space(5.0);
+    ui.horizontal(|ui| {
+        ui.label(
+            RichText::new("📃 PLAYLISTS")
+                .strong()
+                .size(14.0)
+                .color(Color32::LIGHT_GRAY),
+        );
+        ui.with_layout(Layout::right_to_left(Align::Center), |ui| {
+            if ui.small_button("➕").on_hover_text("New Playlist").clicked() {
+                let name = unique_playlist_name(playlists, "New Playlist");
+                playlists.push(Playlist::new(name.clone()));
+                *active = playlists.len() - 1;
+                *renaming = Some((*active, name));
+            }
+            if ui.small_button("✨").on_hover_text("New Smart Playlist").clicked() {
+                action = Some(SidebarAction::Smart(SmartPlaylistRequest::New));
+            }
+        });
+    });
     ui.separator();
 
-    // --- אזור הפלייליסט ---
+    // לא מותחים לגובה מלא - מתחת לרשימות מוצג התור
     egui::ScrollArea::vertical()
-        .auto_shrink([false; 2])
+        .auto_shrink([false, true])
         .show(ui, |ui| {
-            for (idx, path) in playlist.iter().enumerate() {
-                let name = path.file_stem().unwrap_or_default().to_string_lossy();
-                let is_selected = Some(idx) == *selected_track;
-
-                // 1. תיקון Frame: שימוש ב-Frame::NONE וב-i8 עבור Margin, ו-corner_radius
-                let mut frame = egui::Frame::NONE
-                    .inner_margin(egui::Margin::symmetric(10, 8)) // <-- תוקן ל-i8 (בלי .0)
-                    .corner_radius(8); // <-- תוקן מ-rounding
-
-                if is_selected {
-                    frame = frame
-                        .stroke(Stroke::new(2.0, accent_color))
-                        .fill(accent_color.gamma_multiply(0.08));
-                } else {
-                    frame = frame.stroke(Stroke::new(2.0, Color32::TRANSPARENT));
+            for idx in 0..playlists.len() {
+                // מצב עריכת שם: Enter או יציאה מהשדה שומרים, Escape מבטל
+                if let Some((rename_idx, text)) = renaming
+                    && *rename_idx == idx
+                {
+                    let response = ui.text_edit_singleline(text);
+                    if !response.has_focus() && !response.lost_focus() {
+                        response.request_focus();
+                    }
+                    if ui.input(|i| i.key_pressed(egui::Key::Escape)) {
+                        *renaming = None;
+                    } else if response.lost_focus() {
+                        let name = text.trim().to_string();
+                        if !name.is_empty() {
+                            playlists[idx].name = name;
+                        }
+                        *renaming = None;
+                    }
+                    continue;
                 }
 
-                // 2. ציור המסגרת
-                let frame_response = frame.show(ui, |ui| {
-                    ui.set_min_width(ui.available_width());
-                    
-                    let text_color = if is_selected { accent_color } else { Color32::LIGHT_GRAY };
-                    let prefix = if is_selected { "▶" } else { " " };
-                    
-                    ui.label(
-                        RichText::new(format!("{}  {}", prefix, name))
-                            .color(text_color)
-                            .size(14.0)
-                    );
-                }).response;
-
-                // 3. אינטראקציה ואפקט Hover
-                let interact_response = ui.interact(frame_response.rect, ui.id().with(idx), egui::Sense::click());
-                
-                if interact_response.hovered() && !is_selected {
-                    // תיקון StrokeKind: הוספת הפרמטר החסר
-                    ui.painter().rect_stroke(
-                        frame_response.rect,
-                        8.0,
-                        Stroke::new(1.0, Color32::from_gray(100)),
-                        egui::StrokeKind::Inside, // <-- תוקן: הוסף הפרמטר הרביעי ש-egui 0.33 דורש
-                    );
-                    ui.ctx().set_cursor_icon(egui::CursorIcon::PointingHand);
+                let playlist = &playlists[idx];
+                let icon = if playlist.rules.is_some() { "✨ " } else { "" };
+                let label = if idx == playing {
+                    format!("🔊 {}{}", icon, playlist.name)
+                } else {
+                    format!("{}{}", icon, playlist.name)
+                };
+                let color = if idx == *active {
+                    accent_color
+                } else {
+                    Color32::LIGHT_GRAY
+                };
+                let response = ui
+                    .selectable_label(idx == *active, RichText::new(label).color(color))
+                    .on_hover_text(format!("{} tracks", playlist.items.len()));
+
+                if response.clicked() {
+                    *active = idx;
+                }
+                if response.double_clicked() {
+                    *renaming = Some((idx, playlist.name.clone()));
                 }
+                response.context_menu(|ui| {
+                    if ui.button("✏ Rename").clicked() {
+                        *renaming = Some((idx, playlists[idx].name.clone()));
+                        ui.close();
+                    }
+                    if playlists[idx].rules.is_some() && ui.button("⚙ Edit Rules...").clicked() {
+                        action = Some(SidebarAction::Smart(SmartPlaylistRequest::Edit(idx)));
+                        ui.close();
+                    }
+                    if ui.button("📑 Duplicate").clicked() {
+                        action = Some(SidebarAction::Duplicate(idx));
+                        ui.close();
+                    }
+                    // תמיד נשאר לפחות פלייליסט אחד
+                    let can_delete = playlists.len() > 1;
+                    if ui
+                        .add_enabled(can_delete, egui::Button::new("🗑 Delete"))
+                        .clicked()
+                    {
+                        action = Some(SidebarAction::Delete(idx));
+                        ui.close();
+                    }
+                });
+            }
+        });
+    action
+}
 
-                if interact_response.clicked() {
-                    *selected_track = Some(idx);
-                    load_track(playlist, idx, engine);
+/// הטיוטה שבעריכה בחלון של הפלייליסט החכם. playlist = None - פלייליסט חדש
+pub struct SmartEditor {
+    pub playlist: Option<usize>,
+    pub name: String,
+    pub rules: SmartRules,
+}
+
+pub enum SmartEditorAction {
+    Save,
+    Cancel,
+    Import,
+    Export,
+}
+
+/// עורך הכללים: שם, All/Any, רשימת תנאים (שדה / פעולה / ערך), סדר ומגבלה
+pub fn draw_smart_editor(
+    ui: &mut egui::Ui,
+    editor: &mut SmartEditor,
+    matching: usize, // כמה שירים בספרייה עונים על הטיוטה כרגע
+) -> Option<SmartEditorAction> {
+    let mut action = None;
+    let rules = &mut editor.rules;
+
+    ui.horizontal(|ui| {
+        ui.label("Name:");
+        ui.text_edit_singleline(&mut editor.name);
+    });
+    ui.horizontal(|ui| {
+        ui.label("Match");
+        ui.selectable_value(&mut rules.match_mode, MatchMode::All, "all");
+        ui.selectable_value(&mut rules.match_mode, MatchMode::Any, "any");
+        ui.label("of these rules:");
+    });
+    ui.separator();
+
+    let mut remove = None;
+    for (idx, rule) in rules.rules.iter_mut().enumerate() {
+        ui.horizontal(|ui| {
+            egui::ComboBox::from_id_salt(("smart_field", idx))
+                .selected_text(rule.field.label())
+                .width(110.0)
+                .show_ui(ui, |ui| {
+                    for field in RuleField::ALL {
+                        ui.selectable_value(&mut rule.field, field, field.label());
+                    }
+                });
+            // שדה מסוג אחר - הפעולה הקודמת אולי כבר לא מתאימה לו
+            let ops = RuleOp::for_kind(rule.field.kind());
+            if !ops.contains(&rule.op) {
+                rule.op = ops[0];
+            }
+            egui::ComboBox::from_id_salt(("smart_op", idx))
+                .selected_text(rule.op.label())
+                .width(140.0)
+                .show_ui(ui, |ui| {
+                    for &op in ops {
+                        ui.selectable_value(&mut rule.op, op, op.label());
+                    }
+                });
+            ui.add(egui::TextEdit::singleline(&mut rule.value).desired_width(120.0));
+            if ui.small_button("✖").on_hover_text("Remove rule").clicked() {
+                remove = Some(idx);
+            }
+        });
+    }
+    if let Some(idx) = remove {
+        rules.rules.remove(idx);
+    }
+    if ui.button("➕ Add Rule").clicked() {
+        rules.rules.push(Rule::default());
+    }
+
+    ui.separator();
+    ui.horizontal(|ui| {
+        ui.label("Order by:");
+        egui::ComboBox::from_id_salt("smart_order")
+            .selected_text(rules.order.label())
+            .show_ui(ui, |ui| {
+                for order in SmartOrder::ALL {
+                    ui.selectable_value(&mut rules.order, order, order.label());
                 }
+            });
+    });
+    ui.horizontal(|ui| {
+        let mut limited = rules.limit.is_some();
+        if ui.checkbox(&mut limited, "Limit to").changed() {
+            rules.limit = limited.then_some(25);
+        }
+        if let Some(limit) = &mut rules.limit {
+            ui.add(egui::DragValue::new(limit).range(1..=10_000).suffix(" tracks"));
+        }
+    });
+
+    ui.add_space(6.0);
+    ui.label(
+        RichText::new(format!("{} tracks match", matching))
+            .size(12.0)
+            .color(Color32::GRAY),
+    );
+    ui.separator();
+    ui.horizontal(|ui| {
+        if ui.button("💾 Save").clicked() {
+            action = Some(SmartEditorAction::Save);
+        }
+        if ui.button("Cancel").clicked() {
+            action = Some(SmartEditorAction::Cancel);
+        }
+        ui.with_layout(Layout::right_to_left(Align::Center), |ui| {
+            if ui.button("📤 Export JSON...").clicked() {
+                action = Some(SmartEditorAction::Export);
+            }
+            if ui.button("📥 Import JSON...").clicked() {
+                action = Some(SmartEditorAction::Import);
             }
         });
+    });
+    action
+}
+
+/// "New Playlist", ואם כבר תפוס - "New Playlist 2", "New Playlist 3"...
+pub fn unique_playlist_name(playlists: &[Playlist], base: &str) -> String {
+    let taken = |name: &str| playlists.iter().any(|p| p.name == name);
+    if !taken(base) {
+        return base.to_string();
+    }
+    (2..)
+        .map(|n| format!("{} {}", base, n))
+        .find(|name| !taken(name))
+        .unwrap_or_else(|| base.to_string())
 }
 
 
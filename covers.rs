@@ -0,0 +1,167 @@
+use crate::library_db::AlbumId;
+use crate::metadata::TrackMetadata;
+use eframe::egui;
+use gstreamer as gst;
+use gstreamer::glib;
+use gstreamer_pbutils as gst_pbutils;
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::thread::{self, JoinHandle};
+
+/// גודל התמונה הממוזערת (פיקסלים) - מספיק גם לתצוגה של 64px במסך HiDPI
+const THUMB_SIZE: u32 = 128;
+
+/// כמה עטיפות מחזיקים בזיכרון. מעבר לזה מה שלא הוצג הכי הרבה זמן משתחרר ונטען שוב כשצריך
+const MAX_TEXTURES: usize = 400;
+
+/// קבצי תמונה שמחפשים בתיקיית האלבום אם אין עטיפה מוטמעת
+const FOLDER_IMAGES: [&str; 6] = [
+    "cover.jpg",
+    "cover.png",
+    "folder.jpg",
+    "folder.png",
+    "front.jpg",
+    "front.png",
+];
+
+struct Cover {
+    texture: Option<egui::TextureHandle>, // None = לאלבום אין עטיפה
+    last_used: u64,
+}
+
+/// עטיפות אלבומים כ-Texture של egui: נטענות ברקע בפעם הראשונה שמבקשים אותן
+pub struct CoverCache {
+    textures: HashMap<AlbumId, Cover>,
+    requested: HashSet<AlbumId>,
+    clock: u64, // עולה בכל get - העטיפה עם ה-last_used הכי ישן היא הראשונה לצאת
+    request_tx: Option<Sender<(AlbumId, PathBuf)>>,
+    result_rx: Receiver<(AlbumId, Option<egui::ColorImage>)>,
+    worker: Option<JoinHandle<()>>,
+}
+
+impl CoverCache {
+    pub fn new(ctx: egui::Context) -> Self {
+        let (request_tx, request_rx) = mpsc::channel();
+        let (result_tx, result_rx) = mpsc::channel();
+        let worker = thread::spawn(move || cover_loop(request_rx, result_tx, ctx));
+
+        Self {
+            textures: HashMap::new(),
+            requested: HashSet::new(),
+            clock: 0,
+            request_tx: Some(request_tx),
+            result_rx,
+            worker: Some(worker),
+        }
+    }
+
+    /// העטיפה של האלבום אם היא כבר נטענה. אם לא - מבקש אותה מה-Thread ומחזיר None בינתיים
+    pub fn get(
+        &mut self,
+        ctx: &egui::Context,
+        album: AlbumId,
+        track_path: &Path,
+    ) -> Option<egui::TextureHandle> {
+        self.receive(ctx);
+
+        self.clock += 1;
+        if let Some(cover) = self.textures.get_mut(&album) {
+            cover.last_used = self.clock;
+            return cover.texture.clone();
+        }
+        if self.requested.insert(album)
+            && let Some(tx) = &self.request_tx
+        {
+            let _ = tx.send((album, track_path.to_path_buf()));
+        }
+        None
+    }
+
+    fn receive(&mut self, ctx: &egui::Context) {
+        while let Ok((album, image)) = self.result_rx.try_recv() {
+            let texture = image.map(|image| {
+                ctx.load_texture(
+                    format!("cover-{}", album),
+                    image,
+                    egui::TextureOptions::LINEAR,
+                )
+            });
+            let last_used = self.clock;
+            self.textures.insert(album, Cover { texture, last_used });
+        }
+
+        // LRU: עטיפות שעל המסך מתעדכנות בכל פריים, אז יוצאות אלה שלא הוצגו הכי הרבה זמן
+        while self.textures.len() > MAX_TEXTURES {
+            let Some(oldest) = self
+                .textures
+                .iter()
+                .min_by_key(|(_, cover)| cover.last_used)
+                .map(|(album, _)| *album)
+            else {
+                break;
+            };
+            self.textures.remove(&oldest);
+            self.requested.remove(&oldest);
+        }
+    }
+}
+
+impl Drop for CoverCache {
+    fn drop(&mut self) {
+        self.request_tx.take();
+        if let Some(worker) = self.worker.take() {
+            let _ = worker.join();
+        }
+    }
+}
+
+// =========================================================
+// Cover Loop - שולף את העטיפה מהתגיות (או מהתיקייה) וממזער אותה
+// =========================================================
+fn cover_loop(
+    request_rx: Receiver<(AlbumId, PathBuf)>,
+    result_tx: Sender<(AlbumId, Option<egui::ColorImage>)>,
+    ctx: egui::Context,
+) {
+    let discoverer = gst_pbutils::Discoverer::new(gst::ClockTime::from_seconds(5)).ok();
+
+    while let Ok((album, path)) = request_rx.recv() {
+        let bytes = discoverer
+            .as_ref()
+            .and_then(|d| embedded_cover(d, &path))
+            .or_else(|| folder_cover(&path));
+        let image = bytes.and_then(|bytes| decode_thumbnail(&bytes));
+
+        if result_tx.send((album, image)).is_err() {
+            break;
+        }
+        ctx.request_repaint();
+    }
+}
+
+fn embedded_cover(discoverer: &gst_pbutils::Discoverer, path: &Path) -> Option<Vec<u8>> {
+    let uri = glib::filename_to_uri(path, None).ok()?;
+    let info = discoverer.discover_uri(uri.as_str()).ok()?;
+    let mut metadata = TrackMetadata::default();
+    metadata.merge_tags(&info.tags()?);
+    metadata.cover
+}
+
+fn folder_cover(path: &Path) -> Option<Vec<u8>> {
+    let folder = path.parent()?;
+    FOLDER_IMAGES
+        .iter()
+        .find_map(|name| fs::read(folder.join(name)).ok())
+}
+
+fn decode_thumbnail(bytes: &[u8]) -> Option<egui::ColorImage> {
+    let image = image::load_from_memory(bytes)
+        .ok()?
+        .thumbnail(THUMB_SIZE, THUMB_SIZE)
+        .to_rgba8();
+    let size = [image.width() as _, image.height() as _];
+    let pixels = image.as_flat_samples();
+    Some(egui::ColorImage::from_rgba_unmultiplied(size, pixels.as_slice()))
+}
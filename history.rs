@@ -0,0 +1,298 @@
+use serde::{Deserialize, Serialize};
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+
+/// יומן ההאזנה: שורת JSON לכל השמעה, כדי שהוספה לא תצטרך לכתוב מחדש את כל הקובץ
+const HISTORY_FILENAME: &str = "history.jsonl";
+
+/// כללי ה-Scrobbling המקובלים: שיר נספר אחרי חצי ממנו או 4 דקות, ושירים קצרים מ-30 שניות לא נספרים
+const SCROBBLE_SECS: f64 = 240.0;
+const MIN_TRACK_SECS: f64 = 30.0;
+
+/// כמה מהר המיקום יכול להתקדם ביחס לשעון (מהירות ניגון) לפני שזה נחשב קפיצה
+const MAX_POSITION_SPEED: f64 = 4.0;
+
+/// התקדמות קטנה כזו תמיד נספרת (דיווחי המיקום לא מגיעים בדיוק בזמן)
+const POSITION_TOLERANCE_SECS: f64 = 0.5;
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct HistoryEntry {
+    pub path: PathBuf,
+    pub played_at: u64, // מתי השיר התחיל (שניות מ-1970)
+    pub listened_secs: f64,
+}
+
+#[derive(Default)]
+pub struct ListeningHistory {
+    pub entries: Vec<HistoryEntry>, // מהישן לחדש
+}
+
+impl ListeningHistory {
+    pub fn load() -> Self {
+        let entries = fs::read_to_string(HISTORY_FILENAME)
+            .map(|content| {
+                content
+                    .lines()
+                    .filter_map(|line| serde_json::from_str(line).ok())
+                    .collect()
+            })
+            .unwrap_or_default();
+        Self { entries }
+    }
+
+    pub fn append(&mut self, entry: HistoryEntry) {
+        if let Ok(line) = serde_json::to_string(&entry)
+            && let Ok(mut file) = OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(HISTORY_FILENAME)
+        {
+            let _ = writeln!(file, "{}", line);
+        }
+        self.entries.push(entry);
+    }
+}
+
+/// השיר שמתנגן עכשיו וכמה ממנו באמת נשמע
+struct Listen {
+    path: PathBuf,
+    started_at: u64,
+    listened_secs: f64,
+    duration: f64,
+    last_position: f64,
+    last_change: Instant, // מתי המיקום השתנה בפעם האחרונה (או מתי הנגינה חזרה מהשהיה)
+    counted: bool,
+}
+
+/// סופר זמן האזנה אמיתי: השהיה לא נספרת, וקפיצה קדימה (Seek) לא נחשבת כהאזנה
+#[derive(Default)]
+pub struct ListenTracker {
+    current: Option<Listen>,
+}
+
+impl ListenTracker {
+    /// שיר חדש התחיל. מחזיר את השיר הקודם אם עזבו אותו לפני שנספר (דילוג)
+    pub fn start(&mut self, path: &Path, now: u64) -> Option<PathBuf> {
+        let skipped = self
+            .current
+            .take()
+            .filter(|listen| !listen.counted && listen.duration >= MIN_TRACK_SECS)
+            .map(|listen| listen.path);
+        self.current = Some(Listen {
+            path: path.to_path_buf(),
+            started_at: now,
+            listened_secs: 0.0,
+            duration: 0.0,
+            last_position: 0.0,
+            last_change: Instant::now(),
+            counted: false,
+        });
+        skipped
+    }
+
+    /// נקרא בכל פריים עם המיקום של המנוע. מחזיר רשומה ברגע שההאזנה עוברת את הסף
+    pub fn update(&mut self, position: f64, duration: f64, playing: bool) -> Option<HistoryEntry> {
+        self.update_at(position, duration, playing, Instant::now())
+    }
+
+    fn update_at(
+        &mut self,
+        position: f64,
+        duration: f64,
+        playing: bool,
+        now: Instant,
+    ) -> Option<HistoryEntry> {
+        let listen = self.current.as_mut()?;
+        // ה-UI מצייר הרבה יותר פעמים ממה שהמנוע מדווח מיקום, אז ההתקדמות נמדדת מול הזמן
+        // מאז שהמיקום השתנה, לא מאז הפריים הקודם. בהשהיה השעון מתאפס בכל פריים, כדי שזמן
+        // ההשהיה לא יכסה על קפיצה קדימה אחריה
+        let advanced = position - listen.last_position;
+        if !playing || advanced != 0.0 {
+            let elapsed = now.saturating_duration_since(listen.last_change).as_secs_f64();
+            let max_advance = elapsed * MAX_POSITION_SPEED + POSITION_TOLERANCE_SECS;
+            if playing && advanced > 0.0 && advanced <= max_advance {
+                listen.listened_secs += advanced;
+            }
+            listen.last_position = position;
+            listen.last_change = now;
+        }
+        if duration > 0.0 {
+            listen.duration = duration;
+        }
+
+        let threshold = (listen.duration / 2.0).min(SCROBBLE_SECS);
+        if listen.counted || listen.duration < MIN_TRACK_SECS || listen.listened_secs < threshold {
+            return None;
+        }
+        listen.counted = true;
+        Some(HistoryEntry {
+            path: listen.path.clone(),
+            played_at: listen.started_at,
+            listened_secs: listen.listened_secs,
+        })
+    }
+}
+
+/// "לפני 5 דקות", "אתמול", ומעבר לשבוע תאריך מלא (UTC)
+pub fn format_played_at(played_at: u64, now: u64) -> String {
+    let ago = now.saturating_sub(played_at);
+    match ago {
+        0..60 => "just now".to_string(),
+        60..3600 => format!("{} min ago", ago / 60),
+        3600..86400 => format!("{} h ago", ago / 3600),
+        86400..172800 => "yesterday".to_string(),
+        172800..604800 => format!("{} days ago", ago / 86400),
+        _ => {
+            let (year, month, day) = civil_date(played_at / 86400);
+            format!("{}-{:02}-{:02}", year, month, day)
+        }
+    }
+}
+
+/// ימים מ-1970 -> (שנה, חודש, יום), האלגוריתם של Howard Hinnant
+fn civil_date(days: u64) -> (i64, u32, u32) {
+    let z = days as i64 + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = (if mp < 10 { mp + 3 } else { mp - 9 }) as u32;
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    /// מדמה ניגון: המנוע מדווח מיקום כל 100ms וה-UI מצייר 60 פריימים בשנייה.
+    /// מחזיר את הרשומה הראשונה שהתקבלה
+    fn play(
+        tracker: &mut ListenTracker,
+        clock: &mut Instant,
+        position: &mut f64,
+        secs: f64,
+        duration: f64,
+    ) -> Option<HistoryEntry> {
+        let mut entry = None;
+        for _ in 0..(secs * 10.0).round() as usize {
+            *position += 0.1;
+            for _ in 0..6 {
+                *clock += Duration::from_millis(100) / 6;
+                let result = tracker.update_at(*position, duration, true, *clock);
+                entry = entry.or(result);
+            }
+        }
+        entry
+    }
+
+    fn started(path: &str) -> (ListenTracker, Instant) {
+        let mut tracker = ListenTracker::default();
+        tracker.start(Path::new(path), 1000);
+        (tracker, Instant::now())
+    }
+
+    #[test]
+    fn counts_after_half_of_the_track() {
+        let (mut tracker, mut clock) = started("a.mp3");
+        let mut position = 0.0;
+        assert!(play(&mut tracker, &mut clock, &mut position, 49.0, 100.0).is_none());
+        let entry = play(&mut tracker, &mut clock, &mut position, 2.0, 100.0).unwrap();
+        assert_eq!(entry.path, PathBuf::from("a.mp3"));
+        assert_eq!(entry.played_at, 1000);
+        assert!(entry.listened_secs >= 50.0 && entry.listened_secs < 51.0);
+        // נספר פעם אחת בלבד
+        assert!(play(&mut tracker, &mut clock, &mut position, 30.0, 100.0).is_none());
+    }
+
+    #[test]
+    fn long_tracks_count_after_four_minutes() {
+        let (mut tracker, mut clock) = started("long.flac");
+        let mut position = 0.0;
+        assert!(play(&mut tracker, &mut clock, &mut position, 239.0, 1200.0).is_none());
+        assert!(play(&mut tracker, &mut clock, &mut position, 2.0, 1200.0).is_some());
+    }
+
+    #[test]
+    fn short_tracks_never_count() {
+        let (mut tracker, mut clock) = started("jingle.mp3");
+        let mut position = 0.0;
+        assert!(play(&mut tracker, &mut clock, &mut position, 20.0, 20.0).is_none());
+        // וגם לא נחשבים כדילוג
+        assert_eq!(tracker.start(Path::new("next.mp3"), 2000), None);
+    }
+
+    #[test]
+    fn seeking_forward_is_not_listening() {
+        let (mut tracker, mut clock) = started("a.mp3");
+        let mut position = 0.0;
+        play(&mut tracker, &mut clock, &mut position, 5.0, 300.0);
+        clock += Duration::from_millis(100);
+        position = 200.0;
+        assert!(tracker.update_at(position, 300.0, true, clock).is_none());
+        // אחרי הקפיצה ממשיכים לספור רגיל
+        assert!(play(&mut tracker, &mut clock, &mut position, 140.0, 300.0).is_none());
+        assert!(play(&mut tracker, &mut clock, &mut position, 10.0, 300.0).is_some());
+    }
+
+    #[test]
+    fn seeking_back_does_not_lose_listening_time() {
+        let (mut tracker, mut clock) = started("a.mp3");
+        let mut position = 0.0;
+        play(&mut tracker, &mut clock, &mut position, 30.0, 100.0);
+        clock += Duration::from_millis(100);
+        position = 0.0;
+        tracker.update_at(position, 100.0, true, clock);
+        assert!(play(&mut tracker, &mut clock, &mut position, 21.0, 100.0).is_some());
+    }
+
+    #[test]
+    fn paused_time_is_not_listening() {
+        let (mut tracker, mut clock) = started("a.mp3");
+        let mut position = 0.0;
+        play(&mut tracker, &mut clock, &mut position, 10.0, 300.0);
+        // עשר דקות בהשהיה, ואז קפיצה קדימה - הזמן שעבר לא מכסה על הקפיצה
+        for _ in 0..600 {
+            clock += Duration::from_secs(1);
+            assert!(tracker.update_at(position, 300.0, false, clock).is_none());
+        }
+        clock += Duration::from_millis(100);
+        position = 250.0;
+        assert!(tracker.update_at(position, 300.0, true, clock).is_none());
+        assert!(play(&mut tracker, &mut clock, &mut position, 30.0, 300.0).is_none());
+    }
+
+    #[test]
+    fn faster_playback_still_counts() {
+        let (mut tracker, mut clock) = started("podcast.mp3");
+        let mut position = 0.0;
+        let mut entry = None;
+        for _ in 0..300 {
+            clock += Duration::from_millis(100);
+            position += 0.2; // מהירות x2
+            entry = entry.or(tracker.update_at(position, 100.0, true, clock));
+        }
+        assert!(entry.is_some());
+    }
+
+    #[test]
+    fn leaving_before_the_threshold_is_a_skip() {
+        let (mut tracker, mut clock) = started("a.mp3");
+        let mut position = 0.0;
+        play(&mut tracker, &mut clock, &mut position, 10.0, 200.0);
+        assert_eq!(tracker.start(Path::new("b.mp3"), 2000), Some(PathBuf::from("a.mp3")));
+
+        // שיר שנספר הוא לא דילוג
+        let mut position = 0.0;
+        play(&mut tracker, &mut clock, &mut position, 110.0, 200.0);
+        assert_eq!(tracker.start(Path::new("c.mp3"), 3000), None);
+
+        // שיר שעוד לא דיווח אורך (נכשל בטעינה) הוא לא דילוג
+        assert_eq!(tracker.start(Path::new("d.mp3"), 4000), None);
+    }
+}
@@ -0,0 +1,378 @@
+use crate::audio_engine::StreamInfo;
+use crate::library_db::{LibraryDb, LibraryEntry, collect_audio_files, file_signature};
+use crate::metadata::TrackMetadata;
+use gst_pbutils::prelude::*;
+use gstreamer as gst;
+use gstreamer::glib;
+use gstreamer_pbutils as gst_pbutils;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+/// כל כמה קבצים שנסרקו שומרים את ה-DB לדיסק (בספרייה גדולה הקובץ לא קטן)
+const SAVE_EVERY: usize = 200;
+
+/// שינויים מה-UI (השמעות, דירוגים) נשמרים אחרי שקט כזה בתור - לא בכל לחיצה
+const SAVE_DEBOUNCE: Duration = Duration::from_secs(2);
+
+/// כמה זמן ה-Discoverer מחכה לקובץ אחד לפני שהוא מוותר
+const DISCOVER_TIMEOUT_SECS: u64 = 10;
+
+pub type SharedLibrary = Arc<Mutex<LibraryDb>>;
+
+enum IndexJob {
+    File(PathBuf),
+    /// סריקה מחדש של כל התיקיות הידועות: רק קבצים חדשים/שהשתנו, ומחיקת קבצים שנעלמו
+    Rescan,
+    /// תיקייה שנוספה לספרייה - ה-Rescan ימצא גם תתי-תיקיות חדשות בתוכה
+    AddRoot(PathBuf),
+    /// קבצים או תיקיות שנמחקו מהדיסק
+    Remove(Vec<PathBuf>),
+    /// (מ-, אל-) - קבצים או תיקיות ששינו שם, עם הסטטיסטיקות שלהם
+    Rename(Vec<(PathBuf, PathBuf)>),
+}
+
+/// מה שה-Thread מדווח ל-UI
+pub enum IndexEvent {
+    /// ה-DB נטען מהדיסק - התגיות של כל מה שכבר בספרייה
+    Loaded(Vec<(PathBuf, TrackMetadata)>),
+    Indexed(PathBuf, LibraryEntry),
+    Removed(PathBuf),
+}
+
+pub struct LibraryIndexer {
+    queue_tx: Option<Sender<IndexJob>>,
+    events_rx: Receiver<IndexEvent>,
+    worker: Option<JoinHandle<()>>,
+    pending: Arc<AtomicUsize>,
+    batch_total: Arc<AtomicUsize>, // כמה קבצים נכנסו לתור מאז שהוא היה ריק בפעם האחרונה
+    stop: Arc<AtomicBool>,
+    pub library: SharedLibrary,
+}
+
+impl LibraryIndexer {
+    pub fn new() -> Self {
+        // ה-DB נטען ב-Thread של האינדקס - עד אז ה-UI רואה ספרייה ריקה (is_loaded = false)
+        let library: SharedLibrary = Arc::new(Mutex::new(LibraryDb::default()));
+        let pending = Arc::new(AtomicUsize::new(0));
+        let batch_total = Arc::new(AtomicUsize::new(0));
+        let stop = Arc::new(AtomicBool::new(false));
+        let (queue_tx, queue_rx) = mpsc::channel();
+        let (events_tx, events_rx) = mpsc::channel();
+
+        let worker = {
+            let library = library.clone();
+            let pending = pending.clone();
+            let batch_total = batch_total.clone();
+            let stop = stop.clone();
+            thread::spawn(move || {
+                index_loop(queue_rx, events_tx, library, pending, batch_total, stop)
+            })
+        };
+
+        Self {
+            queue_tx: Some(queue_tx),
+            events_rx,
+            worker: Some(worker),
+            pending,
+            batch_total,
+            stop,
+            library,
+        }
+    }
+
+    /// מוסיף קבצים לתור. קבצים שלא השתנו (אותו mtime ואותו גודל) לא נסרקים שוב -
+    /// הבדיקה נעשית ב-Thread, כדי שה-UI לא יעשה stat לכל קובץ
+    pub fn enqueue<'a>(&self, paths: impl IntoIterator<Item = &'a PathBuf>) {
+        let Some(tx) = &self.queue_tx else {
+            return;
+        };
+        for path in paths {
+            start_job(&self.pending, &self.batch_total);
+            let _ = tx.send(IndexJob::File(path.clone()));
+        }
+    }
+
+    /// בודק מחדש את כל הספרייה ברקע - נקרא בעלייה ומהתפריט
+    pub fn rescan(&self) {
+        if let Some(tx) = &self.queue_tx {
+            start_job(&self.pending, &self.batch_total);
+            let _ = tx.send(IndexJob::Rescan);
+        }
+    }
+
+    /// זוכר תיקייה שנוספה לספרייה (נשלח דרך התור, כדי להגיע אחרי שה-DB נטען)
+    pub fn add_root(&self, root: PathBuf) {
+        if let Some(tx) = &self.queue_tx {
+            start_job(&self.pending, &self.batch_total);
+            let _ = tx.send(IndexJob::AddRoot(root));
+        }
+    }
+
+    /// מוחק מהספרייה קבצים (או תיקיות שלמות) שכבר לא קיימים
+    pub fn remove(&self, paths: Vec<PathBuf>) {
+        if let Some(tx) = &self.queue_tx
+            && !paths.is_empty()
+        {
+            start_job(&self.pending, &self.batch_total);
+            let _ = tx.send(IndexJob::Remove(paths));
+        }
+    }
+
+    /// מעביר שירים לנתיב החדש אחרי שינוי שם, בלי לאבד השמעות ודירוגים.
+    /// נשלח לפני ה-enqueue של הנתיבים החדשים, כדי שהם יימצאו כבר באינדקס
+    pub fn rename(&self, renamed: Vec<(PathBuf, PathBuf)>) {
+        if let Some(tx) = &self.queue_tx
+            && !renamed.is_empty()
+        {
+            start_job(&self.pending, &self.batch_total);
+            let _ = tx.send(IndexJob::Rename(renamed));
+        }
+    }
+
+    /// מה השתנה בספרייה מאז הקריאה הקודמת (נקרא מה-UI בכל פריים)
+    pub fn poll_events(&self) -> Vec<IndexEvent> {
+        let mut events = Vec::new();
+        while let Ok(event) = self.events_rx.try_recv() {
+            events.push(event);
+        }
+        events
+    }
+
+    /// (כמה נסרקו, כמה יש בסבב הנוכחי) - None כשאין סריקה פעילה
+    pub fn progress(&self) -> Option<(usize, usize)> {
+        let pending = self.pending.load(Ordering::SeqCst);
+        if pending == 0 {
+            return None;
+        }
+        let total = self.batch_total.load(Ordering::SeqCst).max(pending);
+        Some((total - pending, total))
+    }
+}
+
+impl Drop for LibraryIndexer {
+    fn drop(&mut self) {
+        // בספרייה גדולה התור יכול להכיל אלפי קבצים - לא מחכים שיתרוקן
+        self.stop.store(true, Ordering::SeqCst);
+        self.queue_tx.take();
+        if let Some(worker) = self.worker.take() {
+            let _ = worker.join();
+        }
+    }
+}
+
+// =========================================================
+// Index Loop - רץ ברקע ומריץ Discoverer על כל קובץ
+// =========================================================
+fn index_loop(
+    queue_rx: Receiver<IndexJob>,
+    events_tx: Sender<IndexEvent>,
+    library: SharedLibrary,
+    pending: Arc<AtomicUsize>,
+    batch_total: Arc<AtomicUsize>,
+    stop: Arc<AtomicBool>,
+) {
+    // קריאת הקובץ (בספרייה גדולה - עשרות MB) לפני הנעילה, ואז החלפה אחת
+    let loaded = LibraryDb::load();
+    let tags = loaded
+        .tracks
+        .values()
+        .map(|track| (track.path.clone(), track.info.metadata.clone()))
+        .collect();
+    if let Ok(mut db) = library.lock() {
+        *db = loaded;
+    }
+    let _ = events_tx.send(IndexEvent::Loaded(tags));
+
+    let discoverer =
+        match gst_pbutils::Discoverer::new(gst::ClockTime::from_seconds(DISCOVER_TIMEOUT_SECS)) {
+            Ok(d) => d,
+            Err(e) => {
+                eprintln!("Failed to create Discoverer: {}", e);
+                return;
+            }
+        };
+    let mut indexed_since_save = 0;
+    // קבצים שה-Rescan מצא - נסרקים לפני העבודה הבאה מהתור
+    let mut rescan_queue: Vec<PathBuf> = Vec::new();
+
+    while !stop.load(Ordering::SeqCst) {
+        let job = match rescan_queue.pop() {
+            Some(path) => IndexJob::File(path),
+            None => match queue_rx.recv_timeout(SAVE_DEBOUNCE) {
+                Ok(job) => job,
+                // התור שקט - זה הזמן לשמור שינויים שהגיעו מה-UI
+                Err(RecvTimeoutError::Timeout) => {
+                    save(&library);
+                    continue;
+                }
+                Err(RecvTimeoutError::Disconnected) => break,
+            },
+        };
+
+        match job {
+            IndexJob::File(path) => {
+                // stat בלי לנעול - ה-UI נועל את הספרייה בכל פריים
+                let known = library.lock().ok().and_then(|db| db.signature(&path));
+                if known.is_none() || known != file_signature(&path) {
+                    match discover_file(&discoverer, &path) {
+                        Ok(entry) => {
+                            if let Ok(mut db) = library.lock() {
+                                db.upsert(path.clone(), entry.clone());
+                            }
+                            let _ = events_tx.send(IndexEvent::Indexed(path, entry));
+                            indexed_since_save += 1;
+                        }
+                        Err(e) => eprintln!("Indexing failed for {:?}: {}", path, e),
+                    }
+                }
+            }
+            IndexJob::Rescan => {
+                let snapshot = library.lock().ok().map(|db| db.snapshot());
+                if let Some((signatures, folders)) = snapshot {
+                    let (vanished, changed) = diff_filesystem(&signatures, &folders);
+                    if !vanished.is_empty()
+                        && let Ok(mut db) = library.lock()
+                    {
+                        for path in db.remove_tracks(&vanished) {
+                            let _ = events_tx.send(IndexEvent::Removed(path));
+                        }
+                    }
+                    rescan_queue = changed;
+                }
+                for _ in &rescan_queue {
+                    start_job(&pending, &batch_total);
+                }
+            }
+            IndexJob::AddRoot(root) => {
+                if let Ok(mut db) = library.lock() {
+                    db.add_root(root);
+                }
+            }
+            IndexJob::Remove(paths) => {
+                if let Ok(mut db) = library.lock() {
+                    for path in db.remove_paths(&paths) {
+                        let _ = events_tx.send(IndexEvent::Removed(path));
+                    }
+                }
+            }
+            IndexJob::Rename(renamed) => {
+                if let Ok(mut db) = library.lock() {
+                    for (from, to) in &renamed {
+                        db.rename(from, to);
+                    }
+                }
+            }
+        }
+
+        let left = pending.fetch_sub(1, Ordering::SeqCst).saturating_sub(1);
+        if indexed_since_save >= SAVE_EVERY || left == 0 {
+            save(&library);
+            indexed_since_save = 0;
+        }
+    }
+
+    // האפליקציה נסגרת - שומרים את מה שכבר נסרק ואת מה שהשתנה מה-UI
+    save(&library);
+}
+
+/// השינויים נאספים תחת הנעילה, אבל ה-JSON והכתיבה לדיסק כבר בלעדיה
+fn save(library: &SharedLibrary) {
+    let pending = library.lock().ok().and_then(|mut db| db.take_changes());
+    if let Some(pending) = pending {
+        pending.write_to_disk();
+    }
+}
+
+/// מה השתנה בדיסק לעומת תמונת המצב של ה-DB: (שירים שנעלמו, קבצים חדשים או ששונו).
+/// התיקיות נסרקות לעומק, כך שגם תתי-תיקיות חדשות נמצאות. רץ בלי לנעול את הספרייה -
+/// בספרייה גדולה זה הרבה stat ו-read_dir
+fn diff_filesystem(
+    signatures: &HashMap<PathBuf, (u64, u64)>,
+    folders: &[PathBuf],
+) -> (Vec<PathBuf>, Vec<PathBuf>) {
+    let vanished = signatures
+        .keys()
+        .filter(|path| !path.exists())
+        .cloned()
+        .collect();
+
+    // אחרי מיון תיקייה באה מיד לפני מה שבתוכה - מה שבתוך תיקייה שכבר נסרקת מדולג
+    let mut sorted: Vec<&PathBuf> = folders.iter().collect();
+    sorted.sort();
+    let mut walk: Vec<&PathBuf> = Vec::new();
+    for folder in sorted {
+        if !walk.last().is_some_and(|root| folder.starts_with(root)) {
+            walk.push(folder);
+        }
+    }
+
+    let changed = walk
+        .into_iter()
+        .flat_map(|root| collect_audio_files(root))
+        .filter(|path| signatures.get(path).copied() != file_signature(path))
+        .collect();
+    (vanished, changed)
+}
+
+/// מעדכן את מוני ההתקדמות לפני שעבודה חדשה נכנסת לתור
+fn start_job(pending: &AtomicUsize, batch_total: &AtomicUsize) {
+    if pending.fetch_add(1, Ordering::SeqCst) == 0 {
+        batch_total.store(0, Ordering::SeqCst);
+    }
+    batch_total.fetch_add(1, Ordering::SeqCst);
+}
+
+fn discover_file(
+    discoverer: &gst_pbutils::Discoverer,
+    path: &Path,
+) -> Result<LibraryEntry, String> {
+    let (mtime, size) = file_signature(path).ok_or("File not found")?;
+    let uri = glib::filename_to_uri(path, None).map_err(|e| e.to_string())?;
+    let info = discoverer
+        .discover_uri(uri.as_str())
+        .map_err(|e| e.to_string())?;
+
+    let mut metadata = TrackMetadata::default();
+    if let Some(tags) = info.tags() {
+        metadata.merge_tags(&tags);
+    }
+    // העטיפה לא נשמרת ב-Cache (ה-serde מדלג עליה), אין טעם להחזיק אותה בזיכרון
+    metadata.cover = None;
+
+    let stream = info.audio_streams().first().map(|audio| {
+        let codec = audio
+            .tags()
+            .and_then(|t| t.get::<gst::tags::AudioCodec>().map(|c| c.get().to_string()))
+            .or_else(|| {
+                audio
+                    .caps()
+                    .map(|caps| gst_pbutils::pb_utils_get_codec_description(&caps).to_string())
+            });
+        let bitrate = [audio.bitrate(), audio.max_bitrate()]
+            .into_iter()
+            .find(|b| *b > 0);
+        StreamInfo {
+            codec,
+            bitrate,
+            sample_rate: audio.sample_rate(),
+            bit_depth: audio.depth(),
+            channels: audio.channels(),
+        }
+    });
+
+    Ok(LibraryEntry {
+        mtime,
+        size,
+        duration_secs: info
+            .duration()
+            .map(|d| d.mseconds() as f64 / 1000.0)
+            .unwrap_or(0.0),
+        metadata,
+        stream,
+    })
+}
@@ -0,0 +1,926 @@
+use crate::audio_engine::StreamInfo;
+use crate::metadata::TrackMetadata;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+const DB_FILENAME: &str = "library.json";
+
+/// שינויים מאז העותק המלא האחרון: שורת JSON לכל שורה בטבלה שהשתנתה, כדי שהשמעה או
+/// דירוג לא יכתבו מחדש את כל הספרייה
+const JOURNAL_FILENAME: &str = "library_changes.jsonl";
+
+/// אחרי כמה שינויים ביומן כותבים עותק מלא ומתחילים יומן חדש
+const COMPACT_AFTER: usize = 5000;
+
+/// הקובץ הישן של האינדקס (נתיב -> רשומה) - מיובא פעם אחת אם עוד אין DB
+const LEGACY_CACHE_FILENAME: &str = "library_cache.json";
+
+const DB_VERSION: u32 = 1;
+
+pub type TrackId = u32;
+pub type AlbumId = u32;
+pub type ArtistId = u32;
+pub type FolderId = u32;
+
+/// מה שה-Discoverer מצא על קובץ אחד
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct LibraryEntry {
+    pub mtime: u64,
+    pub size: u64,
+    pub duration_secs: f64,
+    pub metadata: TrackMetadata,
+    pub stream: Option<StreamInfo>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct TrackRow {
+    pub path: PathBuf,
+    pub folder: FolderId,
+    pub artist: Option<ArtistId>,
+    pub album: Option<AlbumId>,
+    #[serde(flatten)]
+    pub info: LibraryEntry,
+    #[serde(default)]
+    pub added: u64, // מתי השיר נכנס לספרייה (שניות מ-1970)
+    #[serde(default)]
+    pub stats: TrackStats,
+}
+
+/// מה שהמשתמש עשה עם השיר - לא מגיע מהקובץ, ולכן לא נדרס בסריקה חוזרת
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct TrackStats {
+    pub play_count: u32,
+    pub skip_count: u32,
+    pub last_played: Option<u64>,
+    pub rating: u8, // 0 = בלי דירוג, 1-5 כוכבים
+    pub loved: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct AlbumRow {
+    pub title: String,
+    pub artist: Option<ArtistId>, // ה-Album Artist, ואם אין - האמן של השיר הראשון
+    pub year: Option<i32>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ArtistRow {
+    pub name: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct FolderRow {
+    pub path: PathBuf,
+}
+
+/// ספריית המוזיקה: ארבע טבלאות עם מזהים מספריים, נשמרות ל-library.json ול-Journal.
+/// האינדקסים (נתיב -> שיר, שם -> אמן וכו') לא נשמרים - נבנים מחדש בטעינה
+#[derive(Serialize, Deserialize, Default)]
+#[serde(default)]
+pub struct LibraryDb {
+    version: u32,
+    next_id: u32,
+    generation: u64, // עולה בכל עותק מלא - שורות ביומן מעותק קודם לא מוחלות
+    roots: Vec<PathBuf>, // תיקיות שנוספו לספרייה - ה-Rescan סורק אותן לעומק
+    pub tracks: HashMap<TrackId, TrackRow>,
+    pub albums: HashMap<AlbumId, AlbumRow>,
+    pub artists: HashMap<ArtistId, ArtistRow>,
+    pub folders: HashMap<FolderId, FolderRow>,
+
+    #[serde(skip)]
+    track_by_path: HashMap<PathBuf, TrackId>,
+    #[serde(skip)]
+    artist_by_name: HashMap<String, ArtistId>,
+    #[serde(skip)]
+    album_by_key: HashMap<(Option<ArtistId>, String), AlbumId>,
+    #[serde(skip)]
+    folder_by_path: HashMap<PathBuf, FolderId>,
+    #[serde(skip)]
+    dirty: Dirty,
+    #[serde(skip)]
+    journal_len: usize,
+    #[serde(skip)]
+    needs_snapshot: bool, // אין עדיין library.json תקין (DB חדש או ייבוא מה-Cache הישן)
+    #[serde(skip)]
+    revision: u64, // עולה בכל שינוי - ה-UI בונה מחדש את התצוגות רק כשהוא משתנה
+    #[serde(skip)]
+    loaded: bool, // false - ה-Thread של האינדקס עוד קורא את הקובץ מהדיסק
+}
+
+impl LibraryDb {
+    pub fn load() -> Self {
+        let mut db = if let Ok(content) = fs::read_to_string(DB_FILENAME)
+            && let Ok(mut db) = serde_json::from_str::<LibraryDb>(&content)
+            && db.version == DB_VERSION
+        {
+            db.replay_journal();
+            db
+        } else {
+            let mut db = Self::import_legacy_cache();
+            db.needs_snapshot = true;
+            db
+        };
+        db.version = DB_VERSION;
+        // DB מלפני שנשמר תאריך ההוספה - הכי קרוב שיש זה זמן השינוי של הקובץ
+        for track in db.tracks.values_mut() {
+            if track.added == 0 {
+                track.added = track.info.mtime;
+            }
+        }
+        db.rebuild_indexes();
+        db.loaded = true;
+        // שונה מהגרסה של ה-DB הריק שה-UI ראה עד עכשיו, כדי שהתצוגות ייבנו מחדש
+        db.revision = 1;
+        db
+    }
+
+    pub fn is_loaded(&self) -> bool {
+        self.loaded
+    }
+
+    pub fn revision(&self) -> u64 {
+        self.revision
+    }
+
+    fn touch(&mut self) {
+        self.revision += 1;
+    }
+
+    /// מה שהשתנה מאז השמירה הקודמת: השורות שהשתנו ליומן, ופעם בהרבה שינויים עותק
+    /// מלא. רק ההעתקה קורית תחת ה-Mutex - ה-JSON והכתיבה (write_to_disk) אחרי שמשחררים
+    pub fn take_changes(&mut self) -> Option<PendingSave> {
+        if self.dirty.is_empty() && !self.needs_snapshot {
+            return None;
+        }
+        let dirty = std::mem::take(&mut self.dirty);
+        self.journal_len += dirty.len();
+        if self.needs_snapshot || self.journal_len >= COMPACT_AFTER {
+            self.needs_snapshot = false;
+            self.journal_len = 0;
+            self.generation += 1;
+            return Some(PendingSave(SaveKind::Snapshot(Box::new(self.to_snapshot()))));
+        }
+
+        let mut changes = Vec::with_capacity(dirty.len());
+        for id in dirty.folders {
+            changes.push(Change::Folder(id, self.folders.get(&id).cloned()));
+        }
+        for id in dirty.artists {
+            changes.push(Change::Artist(id, self.artists.get(&id).cloned()));
+        }
+        for id in dirty.albums {
+            changes.push(Change::Album(id, self.albums.get(&id).cloned()));
+        }
+        for id in dirty.tracks {
+            changes.push(Change::Track(id, self.tracks.get(&id).cloned().map(Box::new)));
+        }
+        if dirty.roots {
+            changes.push(Change::Roots(self.roots.clone()));
+        }
+        Some(PendingSave(SaveKind::Journal(self.generation, changes)))
+    }
+
+    fn to_snapshot(&self) -> DbSnapshot {
+        DbSnapshot {
+            version: self.version,
+            next_id: self.next_id,
+            generation: self.generation,
+            roots: self.roots.clone(),
+            tracks: self.tracks.clone(),
+            albums: self.albums.clone(),
+            artists: self.artists.clone(),
+            folders: self.folders.clone(),
+        }
+    }
+
+    /// מחיל את מה שנכתב ליומן אחרי העותק המלא שנטען
+    fn replay_journal(&mut self) {
+        let Ok(content) = fs::read_to_string(JOURNAL_FILENAME) else {
+            return;
+        };
+        for line in content.lines() {
+            // שורה חלקית (קריסה באמצע כתיבה) או שורה מלפני העותק המלא - מדלגים
+            if let Ok((generation, change)) = serde_json::from_str::<(u64, Change)>(line)
+                && generation == self.generation
+            {
+                self.apply(change);
+                self.journal_len += 1;
+            }
+        }
+    }
+
+    fn apply(&mut self, change: Change) {
+        match change {
+            Change::Track(id, row) => set_row(&mut self.tracks, id, row.map(|row| *row)),
+            Change::Album(id, row) => set_row(&mut self.albums, id, row),
+            Change::Artist(id, row) => set_row(&mut self.artists, id, row),
+            Change::Folder(id, row) => set_row(&mut self.folders, id, row),
+            Change::Roots(roots) => self.roots = roots,
+        }
+    }
+
+    fn import_legacy_cache() -> Self {
+        let mut db = Self::default();
+        if let Ok(content) = fs::read_to_string(LEGACY_CACHE_FILENAME)
+            && let Ok(map) = serde_json::from_str::<HashMap<PathBuf, LibraryEntry>>(&content)
+        {
+            for (path, entry) in map {
+                db.upsert(path, entry);
+            }
+        }
+        db
+    }
+
+    fn rebuild_indexes(&mut self) {
+        self.track_by_path = self
+            .tracks
+            .iter()
+            .map(|(id, t)| (t.path.clone(), *id))
+            .collect();
+        self.artist_by_name = self
+            .artists
+            .iter()
+            .map(|(id, a)| (a.name.to_lowercase(), *id))
+            .collect();
+        self.album_by_key = self
+            .albums
+            .iter()
+            .map(|(id, a)| ((a.artist, a.title.to_lowercase()), *id))
+            .collect();
+        self.folder_by_path = self
+            .folders
+            .iter()
+            .map(|(id, f)| (f.path.clone(), *id))
+            .collect();
+
+        let max_id = [
+            self.tracks.keys().max(),
+            self.albums.keys().max(),
+            self.artists.keys().max(),
+            self.folders.keys().max(),
+        ]
+        .into_iter()
+        .flatten()
+        .max()
+        .copied()
+        .unwrap_or(0);
+        self.next_id = self.next_id.max(max_id + 1);
+    }
+
+    fn allocate_id(&mut self) -> u32 {
+        let id = self.next_id.max(1);
+        self.next_id = id + 1;
+        id
+    }
+
+    pub fn track(&self, path: &Path) -> Option<&TrackRow> {
+        self.track_by_path.get(path).and_then(|id| self.tracks.get(id))
+    }
+
+    /// (mtime, גודל) של הקובץ כפי שהיה בסריקה האחרונה. ההשוואה מול הדיסק נעשית בלי
+    /// להחזיק את ה-Mutex
+    pub fn signature(&self, path: &Path) -> Option<(u64, u64)> {
+        self.track(path).map(|t| (t.info.mtime, t.info.size))
+    }
+
+    /// כל השירים עם החתימה שלהם, והתיקיות לסרוק (השורשים ותיקיות השירים) - תמונת מצב
+    /// ל-Rescan שרץ בלי נעילה
+    pub fn snapshot(&self) -> (HashMap<PathBuf, (u64, u64)>, Vec<PathBuf>) {
+        let signatures = self
+            .tracks
+            .values()
+            .map(|t| (t.path.clone(), (t.info.mtime, t.info.size)))
+            .collect();
+        let folders = self
+            .roots
+            .iter()
+            .cloned()
+            .chain(self.folders.values().map(|f| f.path.clone()))
+            .collect();
+        (signatures, folders)
+    }
+
+    /// תיקייה שנוספה לספרייה. שורש שבתוך שורש קיים מיותר, ושורשים שבתוך החדש מתאחדים בו
+    pub fn add_root(&mut self, root: PathBuf) {
+        if self.roots.iter().any(|r| root.starts_with(r)) {
+            return;
+        }
+        self.roots.retain(|r| !r.starts_with(&root));
+        self.roots.push(root);
+        self.dirty.roots = true;
+    }
+
+    /// השמעה שנספרה (לפי כללי ה-Scrobbling) - שיר שעוד לא באינדקס פשוט לא נספר
+    pub fn record_play(&mut self, path: &Path, played_at: u64) {
+        if let Some(track) = self.track_mut(path) {
+            track.stats.play_count += 1;
+            track.stats.last_played = Some(played_at);
+            self.touch();
+        }
+    }
+
+    /// עברו לשיר אחר לפני שהשיר הזה נספר
+    pub fn record_skip(&mut self, path: &Path) {
+        if let Some(track) = self.track_mut(path) {
+            track.stats.skip_count += 1;
+            self.touch();
+        }
+    }
+
+    /// false - השיר עוד לא באינדקס ואין איפה לשמור את הדירוג
+    pub fn set_rating(&mut self, path: &Path, rating: u8) -> bool {
+        let Some(track) = self.track_mut(path) else {
+            return false;
+        };
+        track.stats.rating = rating.min(5);
+        self.touch();
+        true
+    }
+
+    pub fn set_loved(&mut self, path: &Path, loved: bool) -> bool {
+        let Some(track) = self.track_mut(path) else {
+            return false;
+        };
+        track.stats.loved = loved;
+        self.touch();
+        true
+    }
+
+    /// השיר לשינוי - מסומן כבר כמשהו שצריך לשמור
+    fn track_mut(&mut self, path: &Path) -> Option<&mut TrackRow> {
+        let id = *self.track_by_path.get(path)?;
+        let track = self.tracks.get_mut(&id)?;
+        self.dirty.tracks.insert(id);
+        Some(track)
+    }
+
+    /// מוסיף שיר או מעדכן שיר קיים (לפי הנתיב) ומקשר אותו לאמן, לאלבום ולתיקייה
+    pub fn upsert(&mut self, path: PathBuf, info: LibraryEntry) -> TrackId {
+        let folder_path = path.parent().map(Path::to_path_buf).unwrap_or_default();
+        let folder = self.folder_id(folder_path);
+
+        let artist = info
+            .metadata
+            .artist
+            .clone()
+            .or_else(|| info.metadata.album_artist.clone())
+            .map(|name| self.artist_id(name));
+        let album = info.metadata.album.clone().map(|title| {
+            let album_artist = info
+                .metadata
+                .album_artist
+                .clone()
+                .map(|name| self.artist_id(name))
+                .or(artist);
+            self.album_id(title, album_artist, info.metadata.year)
+        });
+
+        let id = match self.track_by_path.get(&path) {
+            Some(id) => *id,
+            None => {
+                let id = self.allocate_id();
+                self.track_by_path.insert(path.clone(), id);
+                id
+            }
+        };
+        // סריקה חוזרת מעדכנת את מה שבא מהקובץ, לא את ההיסטוריה של השיר
+        let (added, stats) = match self.tracks.get(&id) {
+            Some(old) => (old.added, old.stats.clone()),
+            None => (now_secs(), TrackStats::default()),
+        };
+        let previous = self.tracks.insert(
+            id,
+            TrackRow {
+                path,
+                folder,
+                artist,
+                album,
+                info,
+                added,
+                stats,
+            },
+        );
+        self.dirty.tracks.insert(id);
+        self.touch();
+
+        // תגיות שהשתנו יכולות להשאיר אמן או אלבום ישן בלי שירים
+        if let Some(old) = previous
+            && (old.artist, old.album, old.folder) != (artist, album, folder)
+        {
+            self.remove_orphans();
+        }
+        id
+    }
+
+    /// קובץ או תיקייה שלמה ששינו שם: השירים עוברים לנתיב החדש עם אותו מזהה, תאריך הוספה
+    /// וסטטיסטיקות. החתימה נשארת, כך שסריקה של הנתיב החדש תקרה רק אם הקובץ עצמו השתנה
+    pub fn rename(&mut self, from: &Path, to: &Path) {
+        let moved: Vec<(TrackId, PathBuf)> = self
+            .tracks
+            .iter()
+            .filter_map(|(id, track)| {
+                let rest = track.path.strip_prefix(from).ok()?;
+                let new_path = if rest.as_os_str().is_empty() {
+                    to.to_path_buf()
+                } else {
+                    to.join(rest)
+                };
+                Some((*id, new_path))
+            })
+            .collect();
+        if moved.is_empty() {
+            return;
+        }
+
+        for (id, new_path) in moved {
+            let folder_path = new_path.parent().map(Path::to_path_buf).unwrap_or_default();
+            let folder = self.folder_id(folder_path);
+            let Some(track) = self.tracks.get_mut(&id) else {
+                continue;
+            };
+            self.track_by_path.remove(&track.path);
+            track.path = new_path.clone();
+            track.folder = folder;
+            self.dirty.tracks.insert(id);
+            // קובץ שנדרס בשינוי השם מפנה את מקומו
+            if let Some(replaced) = self.track_by_path.insert(new_path, id)
+                && replaced != id
+            {
+                self.tracks.remove(&replaced);
+                self.dirty.tracks.insert(replaced);
+            }
+        }
+        self.remove_orphans();
+        self.touch();
+    }
+
+    /// מוחק בדיוק את השירים האלה (למשל קבצים שה-Rescan לא מצא), ומחזיר את מה שנמחק
+    pub fn remove_tracks(&mut self, paths: &[PathBuf]) -> Vec<PathBuf> {
+        let paths: HashSet<&Path> = paths.iter().map(PathBuf::as_path).collect();
+        self.remove_tracks_where(|path| paths.contains(path))
+    }
+
+    /// מוחק את השירים בנתיבים האלה - נתיב של תיקייה מוחק את כל מה שבתוכה
+    pub fn remove_paths(&mut self, roots: &[PathBuf]) -> Vec<PathBuf> {
+        self.remove_tracks_where(|path| roots.iter().any(|root| path.starts_with(root)))
+    }
+
+    fn remove_tracks_where(&mut self, predicate: impl Fn(&Path) -> bool) -> Vec<PathBuf> {
+        let removed: Vec<PathBuf> = self
+            .tracks
+            .values()
+            .filter(|t| predicate(&t.path))
+            .map(|t| t.path.clone())
+            .collect();
+        if removed.is_empty() {
+            return removed;
+        }
+        for path in &removed {
+            if let Some(id) = self.track_by_path.remove(path) {
+                self.tracks.remove(&id);
+                self.dirty.tracks.insert(id);
+            }
+        }
+        self.remove_orphans();
+        self.touch();
+        removed
+    }
+
+    fn artist_id(&mut self, name: String) -> ArtistId {
+        let key = name.to_lowercase();
+        if let Some(id) = self.artist_by_name.get(&key) {
+            return *id;
+        }
+        let id = self.allocate_id();
+        self.artists.insert(id, ArtistRow { name });
+        self.artist_by_name.insert(key, id);
+        self.dirty.artists.insert(id);
+        id
+    }
+
+    fn album_id(&mut self, title: String, artist: Option<ArtistId>, year: Option<i32>) -> AlbumId {
+        let key = (artist, title.to_lowercase());
+        if let Some(id) = self.album_by_key.get(&key) {
+            if let Some(album) = self.albums.get_mut(id)
+                && album.year.is_none()
+                && year.is_some()
+            {
+                album.year = year;
+                self.dirty.albums.insert(*id);
+            }
+            return *id;
+        }
+        let id = self.allocate_id();
+        self.albums.insert(
+            id,
+            AlbumRow {
+                title,
+                artist,
+                year,
+            },
+        );
+        self.album_by_key.insert(key, id);
+        self.dirty.albums.insert(id);
+        id
+    }
+
+    fn folder_id(&mut self, path: PathBuf) -> FolderId {
+        if let Some(id) = self.folder_by_path.get(&path) {
+            return *id;
+        }
+        let id = self.allocate_id();
+        self.folders.insert(id, FolderRow { path: path.clone() });
+        self.folder_by_path.insert(path, id);
+        self.dirty.folders.insert(id);
+        id
+    }
+
+    /// אמנים, אלבומים ותיקיות שאף שיר כבר לא מצביע עליהם
+    fn remove_orphans(&mut self) {
+        let mut used_artists = HashSet::new();
+        let mut used_albums = HashSet::new();
+        let mut used_folders = HashSet::new();
+        for track in self.tracks.values() {
+            used_artists.extend(track.artist);
+            used_albums.extend(track.album);
+            used_folders.insert(track.folder);
+        }
+
+        let dirty = &mut self.dirty;
+        self.albums.retain(|id, _| keep_or_mark(used_albums.contains(id), *id, &mut dirty.albums));
+        self.album_by_key.retain(|_, id| used_albums.contains(id));
+        // אמן שנשאר רק בתור Album Artist של אלבום קיים עדיין בשימוש
+        for album in self.albums.values() {
+            used_artists.extend(album.artist);
+        }
+        self.artists
+            .retain(|id, _| keep_or_mark(used_artists.contains(id), *id, &mut dirty.artists));
+        self.artist_by_name.retain(|_, id| used_artists.contains(id));
+        self.folders
+            .retain(|id, _| keep_or_mark(used_folders.contains(id), *id, &mut dirty.folders));
+        self.folder_by_path.retain(|_, id| used_folders.contains(id));
+    }
+}
+
+/// שורה שנמחקת מטבלה נרשמת כשינוי, כדי שהמחיקה תגיע גם ליומן
+fn keep_or_mark(keep: bool, id: u32, dirty: &mut HashSet<u32>) -> bool {
+    if !keep {
+        dirty.insert(id);
+    }
+    keep
+}
+
+/// המזהים שהשתנו (נוספו, עודכנו או נמחקו) מאז השמירה הקודמת, לפי טבלה
+#[derive(Default)]
+struct Dirty {
+    tracks: HashSet<TrackId>,
+    albums: HashSet<AlbumId>,
+    artists: HashSet<ArtistId>,
+    folders: HashSet<FolderId>,
+    roots: bool,
+}
+
+impl Dirty {
+    fn len(&self) -> usize {
+        self.tracks.len()
+            + self.albums.len()
+            + self.artists.len()
+            + self.folders.len()
+            + usize::from(self.roots)
+    }
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// שורה ביומן: המצב החדש של שורה בטבלה, או None אם היא נמחקה
+#[derive(Serialize, Deserialize)]
+enum Change {
+    Track(TrackId, Option<Box<TrackRow>>),
+    Album(AlbumId, Option<AlbumRow>),
+    Artist(ArtistId, Option<ArtistRow>),
+    Folder(FolderId, Option<FolderRow>),
+    Roots(Vec<PathBuf>),
+}
+
+fn set_row<T>(table: &mut HashMap<u32, T>, id: u32, row: Option<T>) {
+    match row {
+        Some(row) => {
+            table.insert(id, row);
+        }
+        None => {
+            table.remove(&id);
+        }
+    }
+}
+
+/// עותק של הטבלאות, באותו מבנה של library.json
+#[derive(Serialize)]
+struct DbSnapshot {
+    version: u32,
+    next_id: u32,
+    generation: u64,
+    roots: Vec<PathBuf>,
+    tracks: HashMap<TrackId, TrackRow>,
+    albums: HashMap<AlbumId, AlbumRow>,
+    artists: HashMap<ArtistId, ArtistRow>,
+    folders: HashMap<FolderId, FolderRow>,
+}
+
+enum SaveKind {
+    /// (generation, השורות שהשתנו) - נוספות לסוף היומן
+    Journal(u64, Vec<Change>),
+    Snapshot(Box<DbSnapshot>),
+}
+
+/// מה ש-take_changes אסף תחת הנעילה, לכתיבה לדיסק אחרי שמשחררים אותה
+pub struct PendingSave(SaveKind);
+
+impl PendingSave {
+    pub fn write_to_disk(self) {
+        match self.0 {
+            SaveKind::Journal(generation, changes) => {
+                let mut lines = String::new();
+                for change in &changes {
+                    if let Ok(line) = serde_json::to_string(&(generation, change)) {
+                        lines.push_str(&line);
+                        lines.push('\n');
+                    }
+                }
+                if let Ok(mut file) = OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(JOURNAL_FILENAME)
+                {
+                    let _ = file.write_all(lines.as_bytes());
+                }
+            }
+            SaveKind::Snapshot(snapshot) => {
+                let Ok(json) = serde_json::to_string(&snapshot) else {
+                    return;
+                };
+                // קובץ זמני והחלפה - קריסה באמצע הכתיבה לא משאירה DB חצי כתוב.
+                // היומן הישן כבר בתוך העותק, והשורות שלו מדולגות לפי ה-generation
+                let tmp = format!("{}.tmp", DB_FILENAME);
+                if fs::write(&tmp, json).is_ok() && fs::rename(&tmp, DB_FILENAME).is_ok() {
+                    let _ = fs::remove_file(JOURNAL_FILENAME);
+                }
+            }
+        }
+    }
+}
+
+pub const AUDIO_EXTENSIONS: [&str; 6] = ["mp3", "wav", "ogg", "flac", "m4a", "mp4"];
+
+pub fn is_audio_file(path: &Path) -> bool {
+    path.extension()
+        .map(|ext| ext.to_string_lossy().to_lowercase())
+        .map(|ext| AUDIO_EXTENSIONS.contains(&ext.as_str()))
+        .unwrap_or(false)
+}
+
+/// כל קבצי האודיו בתיקייה ובתתי-התיקיות שלה
+pub fn collect_audio_files(root: &Path) -> Vec<PathBuf> {
+    let mut files = Vec::new();
+    let mut folders = vec![root.to_path_buf()];
+    while let Some(folder) = folders.pop() {
+        let Ok(entries) = fs::read_dir(&folder) else {
+            continue;
+        };
+        for entry in entries.flatten() {
+            let path = entry.path();
+            if path.is_dir() {
+                folders.push(path);
+            } else if is_audio_file(&path) {
+                files.push(path);
+            }
+        }
+    }
+    files
+}
+
+pub fn now_secs() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+/// (mtime בשניות, גודל בבתים) - החתימה שלפיה יודעים אם קובץ השתנה מאז הסריקה
+pub fn file_signature(path: &Path) -> Option<(u64, u64)> {
+    let meta = fs::metadata(path).ok()?;
+    let mtime = meta
+        .modified()
+        .ok()?
+        .duration_since(std::time::UNIX_EPOCH)
+        .ok()?
+        .as_secs();
+    Some((mtime, meta.len()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(
+        artist: Option<&str>,
+        album: Option<&str>,
+        album_artist: Option<&str>,
+    ) -> LibraryEntry {
+        LibraryEntry {
+            mtime: 1,
+            size: 2,
+            duration_secs: 180.0,
+            metadata: TrackMetadata {
+                artist: artist.map(str::to_string),
+                album: album.map(str::to_string),
+                album_artist: album_artist.map(str::to_string),
+                year: Some(1999),
+                ..Default::default()
+            },
+            stream: None,
+        }
+    }
+
+    /// מה שנשמר לדיסק, בלי תלות בסדר של ה-HashMap
+    fn tables(db: &LibraryDb) -> serde_json::Value {
+        serde_json::to_value(db.to_snapshot()).unwrap()
+    }
+
+    /// כמו load: JSON של עותק מלא ובניית האינדקסים מחדש
+    fn reload(db: &LibraryDb) -> LibraryDb {
+        let json = serde_json::to_string(&db.to_snapshot()).unwrap();
+        let mut loaded: LibraryDb = serde_json::from_str(&json).unwrap();
+        loaded.rebuild_indexes();
+        loaded
+    }
+
+    fn path(p: &str) -> PathBuf {
+        PathBuf::from(p)
+    }
+
+    #[test]
+    fn upsert_links_artist_album_and_folder() {
+        let mut db = LibraryDb::default();
+        let a = db.upsert(path("/music/x/1.mp3"), entry(Some("Band"), Some("Debut"), None));
+        let b = db.upsert(path("/music/x/2.mp3"), entry(Some("band"), Some("DEBUT"), None));
+        let c = db.upsert(
+            path("/music/y/3.mp3"),
+            entry(Some("Guest"), Some("Debut"), Some("Band")),
+        );
+
+        let (ta, tb, tc) = (&db.tracks[&a], &db.tracks[&b], &db.tracks[&c]);
+        // שמות בלי תלות ברישיות, ו-Album Artist קובע לאיזה אלבום השיר שייך
+        assert_eq!(ta.artist, tb.artist);
+        assert_eq!(ta.album, tb.album);
+        assert_eq!(ta.album, tc.album);
+        assert_ne!(ta.artist, tc.artist);
+        assert_eq!(ta.folder, tb.folder);
+        assert_ne!(ta.folder, tc.folder);
+        assert_eq!((db.artists.len(), db.albums.len(), db.folders.len()), (2, 1, 2));
+        assert_eq!(db.albums[&ta.album.unwrap()].artist, ta.artist);
+        assert_eq!(db.track(Path::new("/music/y/3.mp3")).map(|t| t.artist), Some(tc.artist));
+    }
+
+    #[test]
+    fn upsert_keeps_stats_and_drops_orphans() {
+        let mut db = LibraryDb::default();
+        let id = db.upsert(path("/music/1.mp3"), entry(Some("Old"), Some("First"), None));
+        db.record_play(Path::new("/music/1.mp3"), 100);
+        assert!(db.set_rating(Path::new("/music/1.mp3"), 4));
+        let added = db.tracks[&id].added;
+
+        // סריקה חוזרת עם תגיות אחרות: אותו מזהה, אותן סטטיסטיקות
+        let again = db.upsert(path("/music/1.mp3"), entry(Some("New"), Some("Second"), None));
+        assert_eq!(again, id);
+        let track = &db.tracks[&id];
+        assert_eq!((track.stats.play_count, track.stats.rating), (1, 4));
+        assert_eq!(track.stats.last_played, Some(100));
+        assert_eq!(track.added, added);
+        let names: Vec<&str> = db.artists.values().map(|a| a.name.as_str()).collect();
+        assert_eq!(names, ["New"]);
+        assert_eq!(db.albums.len(), 1);
+        assert!(!db.set_rating(Path::new("/music/missing.mp3"), 3));
+    }
+
+    #[test]
+    fn remove_tracks_and_folders() {
+        let mut db = LibraryDb::default();
+        db.upsert(path("/music/a/1.mp3"), entry(Some("A"), Some("One"), None));
+        db.upsert(path("/music/a/sub/2.mp3"), entry(Some("B"), None, None));
+        db.upsert(path("/music/ab/3.mp3"), entry(Some("A"), Some("One"), None));
+
+        let removed = db.remove_tracks(&[path("/music/ab/3.mp3"), path("/music/none.mp3")]);
+        assert_eq!(removed, [path("/music/ab/3.mp3")]);
+        assert_eq!(db.artists.len(), 2);
+
+        // תיקייה מוחקת את מה שבתוכה, לא תיקייה אחרת שרק מתחילה באותן אותיות
+        db.upsert(path("/music/ab/3.mp3"), entry(Some("C"), None, None));
+        let mut removed = db.remove_paths(&[path("/music/a")]);
+        removed.sort();
+        assert_eq!(removed, [path("/music/a/1.mp3"), path("/music/a/sub/2.mp3")]);
+        assert_eq!(db.tracks.len(), 1);
+        assert!(db.track(Path::new("/music/ab/3.mp3")).is_some());
+        let names: Vec<&str> = db.artists.values().map(|a| a.name.as_str()).collect();
+        assert_eq!(names, ["C"]);
+        assert!(db.albums.is_empty());
+        assert_eq!(db.folders.len(), 1);
+    }
+
+    #[test]
+    fn rename_keeps_ids_and_stats() {
+        let mut db = LibraryDb::default();
+        let song = db.upsert(path("/music/old/1.mp3"), entry(Some("A"), None, None));
+        let other = db.upsert(path("/music/old/2.mp3"), entry(Some("A"), None, None));
+        db.record_play(Path::new("/music/old/1.mp3"), 50);
+
+        db.rename(Path::new("/music/old"), Path::new("/music/new"));
+        assert!(db.track(Path::new("/music/old/1.mp3")).is_none());
+        let moved = db.track(Path::new("/music/new/1.mp3")).unwrap();
+        assert_eq!(moved.stats.play_count, 1);
+        assert_eq!(db.track_by_path[Path::new("/music/new/2.mp3")], other);
+        assert_eq!(db.folders.len(), 1);
+        assert_eq!(db.folders[&moved.folder].path, path("/music/new"));
+
+        // שינוי שם שדורס קובץ אחר: השיר שנדרס יוצא מהספרייה
+        db.rename(Path::new("/music/new/1.mp3"), Path::new("/music/new/2.mp3"));
+        assert_eq!(db.tracks.len(), 1);
+        assert_eq!(db.track_by_path[Path::new("/music/new/2.mp3")], song);
+        assert_eq!(db.tracks[&song].stats.play_count, 1);
+    }
+
+    #[test]
+    fn indexes_are_rebuilt_after_load() {
+        let mut db = LibraryDb::default();
+        let id = db.upsert(path("/music/1.mp3"), entry(Some("Band"), Some("Debut"), None));
+        let track = db.tracks[&id].clone();
+
+        let mut loaded = reload(&db);
+        assert_eq!(loaded.track(Path::new("/music/1.mp3")).map(|t| t.artist), Some(track.artist));
+        // אותו אמן, אלבום ותיקייה נמצאים דרך האינדקסים - לא נוצרות שורות כפולות
+        let next = loaded.upsert(path("/music/2.mp3"), entry(Some("BAND"), Some("debut"), None));
+        assert!(next > id);
+        assert_eq!(loaded.tracks[&next].artist, track.artist);
+        assert_eq!(loaded.tracks[&next].album, track.album);
+        assert_eq!(loaded.tracks[&next].folder, track.folder);
+        assert_eq!((loaded.artists.len(), loaded.albums.len()), (1, 1));
+    }
+
+    #[test]
+    fn journal_replays_onto_the_last_snapshot() {
+        let mut db = LibraryDb::default();
+        db.upsert(path("/music/a/1.mp3"), entry(Some("A"), Some("One"), None));
+        db.upsert(path("/music/b/2.mp3"), entry(Some("B"), Some("Two"), None));
+        db.needs_snapshot = true;
+        assert!(matches!(db.take_changes(), Some(PendingSave(SaveKind::Snapshot(_)))));
+        assert!(db.take_changes().is_none());
+        let mut on_disk = reload(&db);
+
+        db.set_loved(Path::new("/music/a/1.mp3"), true);
+        db.remove_paths(&[path("/music/b")]);
+        db.upsert(path("/music/c/3.mp3"), entry(Some("C"), Some("Three"), None));
+        db.add_root(path("/music"));
+        let Some(PendingSave(SaveKind::Journal(generation, changes))) = db.take_changes() else {
+            panic!("expected a journal save");
+        };
+        assert_eq!(generation, db.generation);
+
+        for change in &changes {
+            let line = serde_json::to_string(&(generation, change)).unwrap();
+            let (_, change): (u64, Change) = serde_json::from_str(&line).unwrap();
+            on_disk.apply(change);
+        }
+        on_disk.rebuild_indexes();
+        assert_eq!(tables(&on_disk), tables(&db));
+    }
+
+    #[test]
+    fn long_journal_is_compacted() {
+        let mut db = LibraryDb::default();
+        db.upsert(path("/music/1.mp3"), entry(None, None, None));
+        db.journal_len = COMPACT_AFTER - 1;
+        assert!(matches!(db.take_changes(), Some(PendingSave(SaveKind::Snapshot(_)))));
+        assert_eq!((db.journal_len, db.generation), (0, 1));
+
+        db.record_skip(Path::new("/music/1.mp3"));
+        assert!(matches!(db.take_changes(), Some(PendingSave(SaveKind::Journal(1, _)))));
+        assert_eq!(db.journal_len, 1);
+    }
+
+    #[test]
+    fn roots_are_merged() {
+        let mut db = LibraryDb::default();
+        db.add_root(path("/music/a"));
+        db.add_root(path("/music/b"));
+        db.add_root(path("/music/a/sub"));
+        assert_eq!(db.roots, [path("/music/a"), path("/music/b")]);
+        db.add_root(path("/music"));
+        assert_eq!(db.roots, [path("/music")]);
+        assert_eq!(db.snapshot().1, [path("/music")]);
+    }
+}
@@ -0,0 +1,399 @@
+use gst::prelude::*;
+use gstreamer as gst;
+use gstreamer::glib;
+use serde::{Deserialize, Serialize};
+use crate::library_db::file_signature;
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+
+const CACHE_FILENAME: &str = "loudness_cache.json";
+
+/// כל כמה קבצים סרוקים שומרים את ה-Cache לדיסק
+const SAVE_EVERY: usize = 20;
+
+/// ה-Reference של rganalysis (ReplayGain 1): הגברה של 0dB שווה לעוצמה של 89dB.
+/// זה לא EBU R128, אז העוצמה נשמרת ביחידות של ReplayGain ולא ב-LUFS
+const RG_REFERENCE_DB: f64 = 89.0;
+
+/// True Peak לפי BS.1770: מודדים את ה-Peak אחרי Oversampling של פי 4 לפחות (גם ל-48kHz)
+const TRUE_PEAK_RATE: i32 = 192_000;
+
+/// תוצאת מדידה של קובץ אחד
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct LoudnessInfo {
+    pub mtime: u64,
+    pub size: u64,
+    pub duration_secs: f64,
+    // True Peak (לינארי, 1.0 = 0dBFS). None - נמדד בגרסה ישנה שמדדה רק Sample Peak
+    #[serde(default)]
+    pub true_peak: Option<f64>,
+    pub track_gain_db: f64,
+    pub album_gain_db: Option<f64>, // מחושב לכל השירים באותה תיקייה
+}
+
+impl LoudnessInfo {
+    /// הקובץ לא השתנה מאז שנמדד (והמדידה שלמה)?
+    pub fn is_fresh(&self, path: &Path) -> bool {
+        self.true_peak.is_some() && file_signature(path) == Some((self.mtime, self.size))
+    }
+
+    /// העוצמה של השיר בסולם של ReplayGain 1 (dB), נגזרת מההגברה שנמדדה
+    pub fn rg_loudness_db(&self) -> f64 {
+        RG_REFERENCE_DB - self.track_gain_db
+    }
+}
+
+/// התוצאות משותפות בין הסורק, המנוע (שמזין מהן את ה-fallback של rgvolume) וה-UI
+pub type LoudnessMap = Arc<Mutex<HashMap<PathBuf, LoudnessInfo>>>;
+
+pub struct LoudnessScanner {
+    queue_tx: Option<Sender<PathBuf>>,
+    worker: Option<JoinHandle<()>>,
+    pending: Arc<AtomicUsize>,
+    stop: Arc<AtomicBool>,
+    pub results: LoudnessMap,
+}
+
+impl LoudnessScanner {
+    pub fn new() -> Self {
+        let results: LoudnessMap = Arc::new(Mutex::new(load_cache()));
+        let pending = Arc::new(AtomicUsize::new(0));
+        let stop = Arc::new(AtomicBool::new(false));
+        let (queue_tx, queue_rx) = mpsc::channel();
+
+        let worker = {
+            let results = results.clone();
+            let pending = pending.clone();
+            let stop = stop.clone();
+            thread::spawn(move || scan_loop(queue_rx, results, pending, stop))
+        };
+
+        Self {
+            queue_tx: Some(queue_tx),
+            worker: Some(worker),
+            pending,
+            stop,
+            results,
+        }
+    }
+
+    /// מוסיף קבצים לתור הסריקה. קבצים שלא השתנו מאז המדידה האחרונה מדולגים -
+    /// הבדיקה (stat לכל קובץ) נעשית ב-Thread, לא כאן ב-UI
+    pub fn enqueue<'a>(&self, paths: impl IntoIterator<Item = &'a PathBuf>) {
+        let Some(tx) = &self.queue_tx else {
+            return;
+        };
+        for path in paths {
+            self.pending.fetch_add(1, Ordering::SeqCst);
+            let _ = tx.send(path.clone());
+        }
+    }
+
+    /// הקובץ נכתב מחדש בלי שהאודיו השתנה (תגית הדירוג) - המדידה עוברת לחתימה החדשה,
+    /// אבל רק אם היא הייתה עדכנית לקובץ שלפני הכתיבה
+    pub fn carry_over(&self, path: &Path, before: (u64, u64), after: (u64, u64)) {
+        if let Ok(mut map) = self.results.lock()
+            && let Some(info) = map.get_mut(path)
+            && (info.mtime, info.size) == before
+        {
+            (info.mtime, info.size) = after;
+        }
+    }
+
+    /// כמה קבצים עוד מחכים למדידה
+    pub fn pending(&self) -> usize {
+        self.pending.load(Ordering::SeqCst)
+    }
+}
+
+impl Drop for LoudnessScanner {
+    fn drop(&mut self) {
+        // ה-Thread בודק את הדגל לפני כל קובץ, כך שהוא מסיים אחרי הקובץ הנוכחי ולא
+        // ממשיך לרוקן את התור. סגירת הערוץ מעירה אותו אם הוא מחכה לעבודה
+        self.stop.store(true, Ordering::SeqCst);
+        self.queue_tx.take();
+        if let Some(worker) = self.worker.take() {
+            let _ = worker.join();
+        }
+    }
+}
+
+// =========================================================
+// Scan Loop - רץ ברקע ומודד קובץ אחרי קובץ
+// =========================================================
+fn scan_loop(
+    queue_rx: Receiver<PathBuf>,
+    results: LoudnessMap,
+    pending: Arc<AtomicUsize>,
+    stop: Arc<AtomicBool>,
+) {
+    let mut scanned_since_save = 0;
+    // התיקיות שנמדד בהן משהו מאז החישוב האחרון של Album Gain
+    let mut dirty_folders: HashSet<PathBuf> = HashSet::new();
+
+    while let Ok(path) = queue_rx.recv() {
+        if stop.load(Ordering::SeqCst) {
+            break;
+        }
+
+        // ה-stat נעשה בלי לנעול את המפה - ה-UI וה-Audio Thread קוראים ממנה
+        let known = results
+            .lock()
+            .ok()
+            .and_then(|map| map.get(&path).map(|info| (info.mtime, info.size, info.true_peak)));
+        let fresh = known.is_some_and(|(mtime, size, true_peak)| {
+            true_peak.is_some() && file_signature(&path) == Some((mtime, size))
+        });
+
+        if !fresh {
+            match analyze_file(&path) {
+                Ok(info) => {
+                    if let Ok(mut map) = results.lock() {
+                        map.insert(path.clone(), info);
+                    }
+                    if let Some(folder) = path.parent() {
+                        dirty_folders.insert(folder.to_path_buf());
+                    }
+                    scanned_since_save += 1;
+                }
+                Err(e) => eprintln!("Loudness scan failed for {:?}: {}", path, e),
+            }
+        }
+
+        let left = pending.fetch_sub(1, Ordering::SeqCst).saturating_sub(1);
+        if scanned_since_save >= SAVE_EVERY || (left == 0 && !dirty_folders.is_empty()) {
+            // הכתיבה לדיסק קורית אחרי שחרור המנעול - ה-Audio Thread קורא מהמפה
+            let json = results.lock().ok().and_then(|mut map| {
+                // בסוף סבב - Album Gain לכל התיקיות שהשתנו, במעבר אחד על המפה
+                if left == 0 {
+                    update_album_gains(&mut map, &dirty_folders);
+                    dirty_folders.clear();
+                }
+                serde_json::to_string(&*map).ok()
+            });
+            save_cache(json);
+            scanned_since_save = 0;
+        }
+    }
+
+    // נסגרים באמצע סבב - שומרים את מה שכבר נמדד
+    if !dirty_folders.is_empty() {
+        let json = results.lock().ok().and_then(|mut map| {
+            update_album_gains(&mut map, &dirty_folders);
+            serde_json::to_string(&*map).ok()
+        });
+        save_cache(json);
+    }
+}
+
+/// מפענח את הקובץ דרך rganalysis ומחזיר את ההגברה וה-True Peak שלו
+fn analyze_file(path: &Path) -> Result<LoudnessInfo, String> {
+    let (mtime, size) = file_signature(path).ok_or("File not found")?;
+    let uri = glib::filename_to_uri(path, None).map_err(|e| e.to_string())?;
+
+    let pipeline = build_analysis_pipeline(uri.as_str()).map_err(|e| e.to_string())?;
+    let bus = pipeline.bus().ok_or("Pipeline has no bus")?;
+
+    pipeline
+        .set_state(gst::State::Playing)
+        .map_err(|e| e.to_string())?;
+
+    let mut track_gain = None;
+    let mut peak_db: Option<f64> = None;
+    let mut failure = None;
+
+    // התוצאה של rganalysis מגיעה בתור תגית רגע לפני ה-EOS, אז לוקחים את הערך האחרון
+    for msg in bus.iter_timed(gst::ClockTime::NONE) {
+        use gst::MessageView;
+        match msg.view() {
+            MessageView::Tag(t) => {
+                let tags = t.tags();
+                if let Some(gain) = tags.get::<gst::tags::TrackGain>() {
+                    track_gain = Some(gain.get());
+                }
+            }
+            // level מדווח את ה-Peak של כל חלון זמן (לכל ערוץ, ב-dB) - שומרים את המקסימום
+            MessageView::Element(e) => {
+                if let Some(window_peak) = e.structure().and_then(parse_level_peak) {
+                    peak_db = Some(peak_db.map_or(window_peak, |p| p.max(window_peak)));
+                }
+            }
+            MessageView::Eos(..) => break,
+            MessageView::Error(e) => {
+                failure = Some(e.error().to_string());
+                break;
+            }
+            _ => {}
+        }
+    }
+
+    let duration_secs = pipeline
+        .query_duration::<gst::ClockTime>()
+        .map(|d| d.mseconds() as f64 / 1000.0)
+        .unwrap_or(0.0);
+    let _ = pipeline.set_state(gst::State::Null);
+
+    if let Some(e) = failure {
+        return Err(e);
+    }
+    let track_gain_db = track_gain.ok_or("rganalysis returned no gain")?;
+
+    Ok(LoudnessInfo {
+        mtime,
+        size,
+        duration_secs,
+        true_peak: Some(peak_db.map_or(1.0, |db| 10f64.powf(db / 20.0))),
+        track_gain_db,
+        album_gain_db: None,
+    })
+}
+
+/// uridecodebin ! audioconvert ! tee
+///     tee. ! queue ! audioresample ! rganalysis ! fakesink
+///     tee. ! queue ! audioresample ! audio/x-raw,rate=192000 ! level ! fakesink
+fn build_analysis_pipeline(uri: &str) -> Result<gst::Pipeline, glib::BoolError> {
+    let pipeline = gst::Pipeline::builder().name("loudness-scan").build();
+
+    let decodebin = gst::ElementFactory::make("uridecodebin")
+        .property("uri", uri)
+        .build()?;
+    let convert = gst::ElementFactory::make("audioconvert").build()?;
+    let tee = gst::ElementFactory::make("tee").build()?;
+    let fakesink = || {
+        gst::ElementFactory::make("fakesink")
+            .property("sync", false)
+            .build()
+    };
+
+    let gain_queue = gst::ElementFactory::make("queue").build()?;
+    let gain_resample = gst::ElementFactory::make("audioresample").build()?;
+    let rganalysis = gst::ElementFactory::make("rganalysis").build()?;
+    let gain_sink = fakesink()?;
+
+    // ענף ה-True Peak: Oversampling ואז level מוצא את ה-Peak בין הדגימות המקוריות
+    let peak_queue = gst::ElementFactory::make("queue").build()?;
+    let peak_resample = gst::ElementFactory::make("audioresample").build()?;
+    let oversampled = gst::ElementFactory::make("capsfilter")
+        .property(
+            "caps",
+            gst::Caps::builder("audio/x-raw")
+                .field("rate", TRUE_PEAK_RATE)
+                .build(),
+        )
+        .build()?;
+    let level = gst::ElementFactory::make("level")
+        .property("post-messages", true)
+        .build()?;
+    let peak_sink = fakesink()?;
+
+    pipeline.add_many([
+        &decodebin,
+        &convert,
+        &tee,
+        &gain_queue,
+        &gain_resample,
+        &rganalysis,
+        &gain_sink,
+        &peak_queue,
+        &peak_resample,
+        &oversampled,
+        &level,
+        &peak_sink,
+    ])?;
+    gst::Element::link_many([&convert, &tee])?;
+    gst::Element::link_many([&tee, &gain_queue, &gain_resample, &rganalysis, &gain_sink])?;
+    gst::Element::link_many([
+        &tee,
+        &peak_queue,
+        &peak_resample,
+        &oversampled,
+        &level,
+        &peak_sink,
+    ])?;
+
+    // ה-Pad של האודיו נוצר רק אחרי שה-decoder מזהה את הפורמט
+    let convert_weak = convert.downgrade();
+    decodebin.connect_pad_added(move |_, pad| {
+        let Some(convert) = convert_weak.upgrade() else {
+            return;
+        };
+        let Some(sink_pad) = convert.static_pad("sink") else {
+            return;
+        };
+        let is_audio = pad
+            .current_caps()
+            .and_then(|caps| caps.structure(0).map(|s| s.name().starts_with("audio/")))
+            .unwrap_or(false);
+        if is_audio && !sink_pad.is_linked() {
+            let _ = pad.link(&sink_pad);
+        }
+    });
+
+    Ok(pipeline)
+}
+
+/// Album Gain לכל השירים בתיקיות שהשתנו: ממוצע אנרגיה משוקלל לפי אורך השיר.
+/// מעבר אחד שמקבץ לפי תיקייה, ועוד אחד שמעדכן - לא מעבר על כל המפה לכל קובץ
+fn update_album_gains(map: &mut HashMap<PathBuf, LoudnessInfo>, folders: &HashSet<PathBuf>) {
+    if folders.is_empty() {
+        return;
+    }
+    // תיקייה -> (אנרגיה משוקללת, סך השניות)
+    let mut albums: HashMap<&Path, (f64, f64)> = HashMap::new();
+    for (path, info) in map.iter() {
+        if let Some(folder) = path.parent()
+            && folders.contains(folder)
+        {
+            let secs = info.duration_secs.max(1.0);
+            let album = albums.entry(folder).or_insert((0.0, 0.0));
+            album.0 += secs * 10f64.powf(info.rg_loudness_db() / 10.0);
+            album.1 += secs;
+        }
+    }
+    let gains: HashMap<PathBuf, f64> = albums
+        .into_iter()
+        .map(|(folder, (energy, secs))| {
+            let album_loudness = 10.0 * (energy / secs).log10();
+            (folder.to_path_buf(), RG_REFERENCE_DB - album_loudness)
+        })
+        .collect();
+
+    for (path, info) in map.iter_mut() {
+        if let Some(gain) = path.parent().and_then(|folder| gains.get(folder)) {
+            info.album_gain_db = Some(*gain);
+        }
+    }
+}
+
+/// הודעת element מאלמנט level: ה-Peak הגבוה מבין הערוצים (dB)
+fn parse_level_peak(structure: &gst::StructureRef) -> Option<f64> {
+    if structure.name() != "level" {
+        return None;
+    }
+    let peaks = structure.get::<glib::ValueArray>("peak").ok()?;
+    peaks
+        .iter()
+        .filter_map(|v| v.get::<f64>().ok())
+        .reduce(f64::max)
+}
+
+fn load_cache() -> HashMap<PathBuf, LoudnessInfo> {
+    if let Ok(content) = fs::read_to_string(CACHE_FILENAME)
+        && let Ok(map) = serde_json::from_str(&content)
+    {
+        return map;
+    }
+    HashMap::new()
+}
+
+/// מקבל את ה-JSON מוכן, כדי שהכתיבה לא תקרה בזמן שהמפה נעולה
+fn save_cache(json: Option<String>) {
+    if let Some(json) = json {
+        let _ = fs::write(CACHE_FILENAME, json);
+    }
+}
@@ -3,12 +3,45 @@ use eframe::egui::{self, Color32, RichText};
 mod app_state;
 mod audio_engine;
 mod components;
-use audio_engine::{AudioEngine, PlayerState};
+use audio_engine::{AudioEngine, FadeCurve, PlayerState, ReplayGainMode, SpectrumSettings};
 mod color_config;
 mod theme_manager;
 use app_state::AppState;
 use theme_manager::ThemeManager;
 mod equalizer;
+mod loudness;
+use loudness::LoudnessScanner;
+mod library;
+mod library_db;
+use library::{IndexEvent, LibraryIndexer};
+mod history;
+use history::{ListenTracker, ListeningHistory};
+use rating_tags::RatingWriter;
+mod metadata;
+use metadata::TrackMetadata;
+mod playlist;
+use playlist::Playlist;
+use smart_playlist::SmartRules;
+mod playlist_io;
+mod rating_tags;
+mod search;
+mod smart_playlist;
+mod track_table;
+mod play_order;
+use play_order::{PlayOrder, RepeatMode, ShuffleMode};
+mod watcher;
+use watcher::{FolderWatcher, WatchBatch};
+mod browser;
+mod covers;
+use browser::{BrowserAction, LibraryBrowser};
+use covers::CoverCache;
+use std::collections::{HashMap, HashSet};
+use std::time::{Duration, Instant};
+
+const RECENTLY_PLAYED: &str = "Recently Played";
+
+/// כמה זמן אחרי שדירוג נכתב לקובץ ה-Watcher מתעלם מהשינוי בו (הוא כבר טופל)
+const OWN_WRITE_GRACE: Duration = Duration::from_secs(5);
 
 // =========================================================
 // מבנה האפליקציה
@@ -17,8 +50,43 @@ struct MusicApp {
     volume: f32,
     eq: [f32; 10],
     engine: AudioEngine,
-    playlist: Vec<std::path::PathBuf>,
-    selected_track: Option<usize>,
+    loudness_scanner: LoudnessScanner,
+    library_indexer: LibraryIndexer,
+    folder_watcher: FolderWatcher,
+    watched_roots: Vec<std::path::PathBuf>,
+    browser: LibraryBrowser,
+    covers: CoverCache,
+    show_browser: bool,
+    playlists: Vec<Playlist>,
+    active_playlist: usize,  // הרשימה שמוצגת
+    playing_playlist: usize, // הרשימה שממנה מתנגנים השירים
+    renaming_playlist: Option<(usize, String)>,
+    selection: components::TrackSelection,
+    search_query: String,
+    table_config: track_table::TableConfig,
+    smart_editor: Option<components::SmartEditor>,
+    smart_refreshed: Option<(u64, u64)>, // (גרסת הספרייה, שעה) של החישוב האחרון
+    play_order: PlayOrder,
+    repeat_mode: RepeatMode,
+    shuffle_mode: ShuffleMode,
+    listen_tracker: ListenTracker,
+    pending_listen: Option<std::path::PathBuf>, // נטען במנוע ועוד לא התחיל לנגן
+    history: ListeningHistory,
+    show_history: bool,
+    write_rating_tags: bool, // דירוגים נכתבים גם לקובץ (POPM / FMPS_RATING)
+    rating_writer: RatingWriter,
+    rating_write_errors: Vec<(std::path::PathBuf, String)>, // מוצגות בשורת הסטטוס
+    rating_written: HashMap<std::path::PathBuf, Instant>, // קבצים שאנחנו כתבנו, ומתי
+    track_tags: HashMap<std::path::PathBuf, TrackMetadata>, // תגיות מהאינדקס ומהשיר שמתנגן
+    gapless_next: Option<std::path::PathBuf>, // השיר שכבר הוכן במנוע למעבר Gapless
+    crossfade_secs: f32,
+    crossfade_curve: FadeCurve,
+    replaygain_mode: ReplayGainMode,
+    replaygain_preamp: f32,
+    replaygain_fallback: f32,
+    playback_rate: f32,
+    spectrum: SpectrumSettings,
+    output_device: Option<String>, // ההתקן שהמשתמש בחר (גם אם הוא מנותק כרגע)
     //is_dark_mode: bool,
     theme_manager: ThemeManager, // המנהל החדש
     time_for_animation: f32,
@@ -38,13 +106,48 @@ impl MusicApp {
         let saved_state = AppState::load();
 
         // שמנו mut כדי שנוכל לשנות את הווליום ולטעון שיר בהמשך
-        let app = Self {
+        let mut app = Self {
             volume: saved_state.volume,
             eq: [0.0; 10],
-            engine: AudioEngine::new(ctx), // עכשיו זה עובד פרפקט!
-
-            playlist: saved_state.playlist,
-            selected_track: saved_state.last_played_index,
+            engine: AudioEngine::new(ctx.clone()), // עכשיו זה עובד פרפקט!
+            loudness_scanner: LoudnessScanner::new(),
+            library_indexer: LibraryIndexer::new(),
+            folder_watcher: FolderWatcher::new(),
+            watched_roots: saved_state.watched_roots,
+            browser: LibraryBrowser::default(),
+            covers: CoverCache::new(ctx),
+            show_browser: saved_state.show_browser,
+
+            active_playlist: saved_state.active_playlist,
+            playing_playlist: saved_state.active_playlist,
+            playlists: saved_state.playlists,
+            renaming_playlist: None,
+            selection: components::TrackSelection::default(),
+            search_query: String::new(),
+            table_config: saved_state.table_config,
+            smart_editor: None,
+            smart_refreshed: None,
+            play_order: PlayOrder::default(),
+            repeat_mode: saved_state.repeat_mode,
+            shuffle_mode: saved_state.shuffle_mode,
+            listen_tracker: ListenTracker::default(),
+            pending_listen: None,
+            history: ListeningHistory::load(),
+            show_history: false,
+            write_rating_tags: saved_state.write_rating_tags,
+            rating_writer: RatingWriter::new(),
+            rating_write_errors: Vec::new(),
+            rating_written: HashMap::new(),
+            track_tags: HashMap::new(),
+            gapless_next: None,
+            crossfade_secs: saved_state.crossfade_secs,
+            crossfade_curve: saved_state.crossfade_curve,
+            replaygain_mode: saved_state.replaygain_mode,
+            replaygain_preamp: saved_state.replaygain_preamp,
+            replaygain_fallback: saved_state.replaygain_fallback,
+            playback_rate: 1.0,
+            spectrum: saved_state.spectrum,
+            output_device: saved_state.output_device,
             theme_manager: ThemeManager::new(),
             time_for_animation: 0.0,
             is_theme_window_open: false,
@@ -55,16 +158,44 @@ impl MusicApp {
             btn_prev: None,
         };
 
-        // 2. עדכון המנוע בווליום השמור
+        // 2. עדכון המנוע בווליום ובהגדרות ה-Crossfade וה-ReplayGain השמורים
         app.engine.set_volume(app.volume);
+        app.engine.set_crossfade_duration(app.crossfade_secs);
+        app.engine.set_crossfade_curve(app.crossfade_curve);
+        app.engine.set_replaygain_mode(app.replaygain_mode);
+        app.engine.set_replaygain_preamp(app.replaygain_preamp);
+        app.engine.set_replaygain_fallback(app.replaygain_fallback);
+        app.engine.set_spectrum(app.spectrum);
+        if app.output_device.is_some() {
+            app.engine.set_output_device(app.output_device.clone());
+        }
+
+        // מדידת עוצמה ברקע לכל מה שעוד לא נמדד (מה שכבר ב-Cache מדולג)
+        app.engine.set_loudness_source(app.loudness_scanner.results.clone());
+        for playlist in &app.playlists {
+            app.loudness_scanner.enqueue(&playlist.items);
+        }
+
+        // הספרייה נטענת ברקע (התגיות מגיעות ב-IndexEvent::Loaded), ואחריה מטופל
+        // כל מה שחדש, השתנה או נעלם
+        app.library_indexer.rescan();
+        for playlist in &app.playlists {
+            app.library_indexer.enqueue(&playlist.items);
+        }
+
+        // תיקיות נצפות: מה שהשתנה בזמן שהאפליקציה הייתה סגורה, ואז האזנה לשינויים חיים
+        for root in app.watched_roots.clone() {
+            app.library_indexer.add_root(root.clone());
+            app.reconcile_root(&root);
+            app.folder_watcher.watch(&root);
+        }
 
         // 3. טעינת השיר האחרון - תיקנו פה את שגיאת ה-let chains לסוגריים מקוננים!
-        if let Some(idx) = app.selected_track {
-            if let Some(path) = app.playlist.get(idx) {
-                if let Some(s) = path.to_str() {
-                    app.engine.load(s);
-                }
+        if let Some(path) = app.playing().get_current().cloned() {
+            if let Some(s) = path.to_str() {
+                app.engine.load(s);
             }
+            app.pending_listen = Some(path);
         }
 
         app
@@ -101,7 +232,9 @@ impl MusicApp {
         for (i, &db_value) in data.iter().enumerate() {
             // הוספת f32 מפורש כדי שהקומפיילר לא יתבלבל בטיפוסים
             let val: f32 = db_value;
-            let height_factor = ((val + 60.0) / 60.0).clamp(0.05, 1.0);
+            // הסף הוא ה"רצפה" של הגרף: סף -60 dB = פס ריק ב-60- ומלא ב-0
+            let floor = self.spectrum.threshold_db.min(-1) as f32;
+            let height_factor = ((val - floor) / -floor).clamp(0.05, 1.0);
             let bar_height = height_factor * rect.height();
 
             let x = rect.min.x + (i as f32 * bar_width);
@@ -120,23 +253,8 @@ impl MusicApp {
     }
 
     fn scan_folder_recursive(&mut self, path: &std::path::Path) {
-        if let Ok(entries) = std::fs::read_dir(path) {
-            for entry in entries.flatten() {
-                let p = entry.path();
-                if p.is_dir() {
-                    // תת-תיקייה? סרוק אותה רקורסיבית!
-                    self.scan_folder_recursive(&p);
-                } else if let Some(ext) = p.extension() {
-                    let ext_str = ext.to_string_lossy().to_lowercase();
-                    // תומך בכל הפורמטים שרצית
-                    if ["mp3", "wav", "ogg", "flac", "m4a", "mp4"].contains(&ext_str.as_str())
-                        && !self.playlist.contains(&p)
-                    {
-                        self.playlist.push(p);
-                    }
-                }
-            }
-        }
+        self.library_indexer.add_root(path.to_path_buf());
+        self.add_tracks(library_db::collect_audio_files(path));
     }
 
     // הפונקציה שתופעל מהכפתור
@@ -144,7 +262,385 @@ impl MusicApp {
         if let Some(path) = rfd::FileDialog::new().pick_folder() {
             println!("📂 Scanning folder: {:?}", path);
             self.scan_folder_recursive(&path);
-            println!("✅ Scan complete. Total tracks: {}", self.playlist.len());
+            println!("✅ Scan complete. Total tracks: {}", self.shown().items.len());
+        }
+    }
+
+    fn watch_folder_dialog(&mut self) {
+        if let Some(root) = rfd::FileDialog::new().pick_folder()
+            && !self.watched_roots.contains(&root)
+        {
+            self.library_indexer.add_root(root.clone());
+            self.reconcile_root(&root);
+            self.folder_watcher.watch(&root);
+            self.watched_roots.push(root);
+        }
+    }
+
+    fn unwatch_folder(&mut self, root: &std::path::Path) {
+        self.folder_watcher.unwatch(root);
+        self.watched_roots.retain(|r| r != root);
+    }
+
+    /// משווה את הרשימות לדיסק ברקע: קבצים חדשים נוספים, קבצים שנמחקו יוצאים
+    /// (התוצאה חוזרת כ-WatchBatch דרך apply_watch_batch)
+    fn reconcile_root(&mut self, root: &std::path::Path) {
+        let known = self
+            .playlists
+            .iter()
+            .flat_map(|pl| pl.items.iter())
+            .filter(|p| p.starts_with(root))
+            .cloned()
+            .collect();
+        self.folder_watcher.reconcile(root, known);
+    }
+
+    /// מוציא שירים מכל הרשימות (קבצים שנמחקו מהדיסק שייכים לכולן)
+    fn remove_from_playlists(&mut self, should_remove: impl Fn(&std::path::Path) -> bool) {
+        for playlist in self.playlists.iter_mut() {
+            playlist.remove_where(&should_remove);
+        }
+    }
+
+    fn apply_watch_batch(&mut self, mut batch: WatchBatch) {
+        // השינוי מכתיבת דירוג לקובץ כבר טופל ב-apply_rating_writes
+        let now = Instant::now();
+        self.rating_written.retain(|_, at| now.duration_since(*at) < OWN_WRITE_GRACE);
+        batch.changed.retain(|path| !self.rating_written.contains_key(path));
+
+        // שינוי שם (גם של תיקייה שלמה) - השיר נשאר באותו מקום ברשימה, ובספרייה עם
+        // ההשמעות והדירוג שלו. ה-enqueue שאחרי סורק אותו רק אם הקובץ עצמו השתנה
+        self.library_indexer.rename(batch.renamed.clone());
+        for (from, to) in &batch.renamed {
+            let mut moved = Vec::new();
+            let items = self.playlists.iter_mut().flat_map(|pl| pl.items.iter_mut());
+            for path in items {
+                if let Ok(rest) = path.strip_prefix(from) {
+                    let new_path = if rest.as_os_str().is_empty() {
+                        to.clone()
+                    } else {
+                        to.join(rest)
+                    };
+                    if let Some(meta) = self.track_tags.remove(path) {
+                        self.track_tags.insert(new_path.clone(), meta);
+                    }
+                    *path = new_path.clone();
+                    moved.push(new_path);
+                }
+            }
+            self.library_indexer.enqueue(&moved);
+            self.loudness_scanner.enqueue(&moved);
+            if to.is_dir() {
+                // קבצים שעוד לא היו ברשימה (למשל תיקייה שהועברה מבחוץ)
+                self.scan_folder_recursive(to);
+            }
+        }
+
+        if !batch.changed.is_empty() {
+            let known: HashSet<&std::path::PathBuf> =
+                self.playlists.iter().flat_map(|pl| pl.items.iter()).collect();
+            let mut updated = Vec::new();
+            let mut added = Vec::new();
+            for path in batch.changed {
+                if path.is_dir() {
+                    added.extend(library_db::collect_audio_files(&path));
+                } else if known.contains(&path) {
+                    // הקובץ השתנה (למשל תגיות נערכו) - האינדקס והמדידה מתעדכנים
+                    updated.push(path);
+                } else if library_db::is_audio_file(&path) {
+                    added.push(path);
+                }
+            }
+            self.library_indexer.enqueue(&updated);
+            self.loudness_scanner.enqueue(&updated);
+            self.add_tracks(added);
+        }
+
+        if !batch.removed.is_empty() {
+            // קובץ שנמחק, או כל מה שמתחת לתיקייה שנמחקה
+            let removed: HashSet<std::path::PathBuf> = batch.removed.iter().cloned().collect();
+            self.remove_from_playlists(|p| p.ancestors().any(|a| removed.contains(a)));
+            self.library_indexer.remove(batch.removed);
+        }
+    }
+
+    fn apply_browser_action(&mut self, action: BrowserAction) {
+        let (paths, start) = match action {
+            BrowserAction::Play(paths, start) => (paths, Some(start)),
+            BrowserAction::Append(paths) => (paths, None),
+        };
+        let start = start.and_then(|idx| paths.get(idx)).cloned();
+        self.add_tracks(paths);
+
+        let preferred = self.import_target().unwrap_or(self.active_playlist);
+        if let Some(path) = start
+            && let Some((list, idx)) = locate_track(&self.playlists, preferred, &path)
+        {
+            self.playing_playlist = list;
+            self.playing_mut().select(idx);
+            self.play_current();
+        }
+    }
+
+    /// בונה מחדש את הפלייליסטים החכמים כשהספרייה משתנה, ופעם בשעה בגלל כללים של
+    /// "ב-7 הימים האחרונים". force - אחרי עריכה של כללים
+    fn refresh_smart_playlists(&mut self, force: bool) {
+        let Ok(db) = self.library_indexer.library.lock() else {
+            return;
+        };
+        // לפני שהספרייה נטענה כל פלייליסט חכם היה מתרוקן
+        if !db.is_loaded() {
+            return;
+        }
+        let now = library_db::now_secs();
+        let key = (db.revision(), now / 3600);
+        if !force && self.smart_refreshed == Some(key) {
+            return;
+        }
+        self.smart_refreshed = Some(key);
+        for playlist in &mut self.playlists {
+            if let Some(rules) = &playlist.rules {
+                let items = rules.evaluate(&db, now);
+                playlist.replace_items(items);
+            }
+        }
+    }
+
+    fn duplicate_playlist(&mut self, idx: usize) {
+        let mut copy = self.playlists[idx].clone();
+        let name = format!("{} (copy)", copy.name);
+        copy.name = components::unique_playlist_name(&self.playlists, &name);
+        self.playlists.insert(idx + 1, copy);
+
+        let shift = |i: usize| if i > idx { i + 1 } else { i };
+        self.playing_playlist = shift(self.playing_playlist);
+        if let Some(editor) = &mut self.smart_editor {
+            editor.playlist = editor.playlist.map(shift);
+        }
+        self.play_order.playlist_inserted(idx + 1);
+        self.active_playlist = idx + 1;
+    }
+
+    fn delete_playlist(&mut self, idx: usize) {
+        // תמיד נשאר לפחות פלייליסט אחד
+        if self.playlists.len() <= 1 {
+            return;
+        }
+        let removed = self.playlists.remove(idx);
+        self.play_order.playlist_removed(idx);
+        self.renaming_playlist = None;
+
+        let shift = |i: usize| if i > idx { i - 1 } else { i };
+        self.active_playlist = shift(self.active_playlist).min(self.playlists.len() - 1);
+        if let Some(editor) = &mut self.smart_editor {
+            // עורך הכללים של פלייליסט שנמחק ישמור אותו כפלייליסט חדש
+            editor.playlist = editor.playlist.filter(|&i| i != idx).map(shift);
+        }
+        if self.playing_playlist != idx {
+            self.playing_playlist = shift(self.playing_playlist);
+            return;
+        }
+
+        // הרשימה שמתנגנת נמחקה: ממשיכים מרשימה אחרת שיש בה את אותו שיר, אם יש
+        let found = removed
+            .get_current()
+            .and_then(|path| locate_track(&self.playlists, self.active_playlist, path));
+        self.playing_playlist = match found {
+            Some((list, track)) => {
+                self.playlists[list].current_index = Some(track);
+                list
+            }
+            None => self.active_playlist,
+        };
+    }
+
+    fn open_smart_editor(&mut self, request: components::SmartPlaylistRequest) {
+        self.smart_editor = Some(match request {
+            components::SmartPlaylistRequest::New => components::SmartEditor {
+                playlist: None,
+                name: "Smart Playlist".to_string(),
+                rules: SmartRules::default(),
+            },
+            components::SmartPlaylistRequest::Edit(idx) => components::SmartEditor {
+                playlist: Some(idx),
+                name: self.playlists[idx].name.clone(),
+                rules: self.playlists[idx].rules.clone().unwrap_or_default(),
+            },
+        });
+    }
+
+    fn apply_smart_editor_action(&mut self, action: components::SmartEditorAction) {
+        let Some(editor) = &mut self.smart_editor else {
+            return;
+        };
+        match action {
+            components::SmartEditorAction::Save => {
+                let name = editor.name.trim().to_string();
+                let rules = editor.rules.clone();
+                // הפלייליסט אולי נמחק בזמן שהחלון היה פתוח - אז נוצר חדש
+                let existing = editor
+                    .playlist
+                    .filter(|&idx| self.playlists.get(idx).is_some_and(|p| p.rules.is_some()));
+                let idx = existing.unwrap_or_else(|| {
+                    self.playlists.push(Playlist::new(name.clone()));
+                    self.playlists.len() - 1
+                });
+                let playlist = &mut self.playlists[idx];
+                if !name.is_empty() {
+                    playlist.name = name;
+                }
+                playlist.rules = Some(rules);
+                self.active_playlist = idx;
+                self.smart_editor = None;
+                self.refresh_smart_playlists(true);
+            }
+            components::SmartEditorAction::Cancel => self.smart_editor = None,
+            components::SmartEditorAction::Import => {
+                if let Some(path) = rfd::FileDialog::new()
+                    .add_filter("Smart Playlist", &["json"])
+                    .pick_file()
+                {
+                    match smart_playlist::import(&path) {
+                        Ok((name, rules)) => {
+                            editor.name = name;
+                            editor.rules = rules;
+                        }
+                        Err(e) => eprintln!("Failed to import smart playlist {:?}: {}", path, e),
+                    }
+                }
+            }
+            components::SmartEditorAction::Export => {
+                if let Some(path) = rfd::FileDialog::new()
+                    .add_filter("Smart Playlist", &["json"])
+                    .set_file_name(format!("{}.json", editor.name))
+                    .save_file()
+                    && let Err(e) = smart_playlist::export(&editor.name, &editor.rules, &path)
+                {
+                    eprintln!("Failed to export smart playlist {:?}: {}", path, e);
+                }
+            }
+        }
+    }
+
+    /// פלייליסט חכם של מה שהתנגן לאחרונה - נוצר בפעם הראשונה, ואחר כך פשוט נפתח
+    fn open_recently_played(&mut self) {
+        let existing = self
+            .playlists
+            .iter()
+            .position(|p| p.rules.is_some() && p.name == RECENTLY_PLAYED);
+        self.active_playlist = existing.unwrap_or_else(|| {
+            let mut playlist = Playlist::new(RECENTLY_PLAYED.to_string());
+            playlist.rules = Some(SmartRules {
+                rules: vec![smart_playlist::Rule {
+                    field: smart_playlist::RuleField::LastPlayed,
+                    op: smart_playlist::RuleOp::InLastDays,
+                    value: "30".to_string(),
+                }],
+                order: smart_playlist::SmartOrder::RecentlyPlayed,
+                limit: Some(100),
+                ..Default::default()
+            });
+            self.playlists.push(playlist);
+            self.playlists.len() - 1
+        });
+        self.refresh_smart_playlists(true);
+    }
+
+    fn apply_history_action(&mut self, action: components::HistoryAction) {
+        match action {
+            components::HistoryAction::Play(path) => {
+                let mut found = locate_track(&self.playlists, self.active_playlist, &path);
+                if found.is_none() {
+                    self.add_tracks(vec![path.clone()]);
+                    found = self
+                        .import_target()
+                        .and_then(|list| locate_track(&self.playlists, list, &path));
+                }
+                // כל הרשימות חכמות - השיר נכנס רק לספרייה
+                let Some((list, idx)) = found else {
+                    return;
+                };
+                self.playing_playlist = list;
+                self.playing_mut().select(idx);
+                self.play_current();
+            }
+            components::HistoryAction::RecentlyPlayed => self.open_recently_played(),
+        }
+    }
+
+    fn import_playlist_dialog(&mut self) {
+        let Some(path) = rfd::FileDialog::new()
+            .add_filter("Playlists", &playlist_io::PLAYLIST_EXTENSIONS)
+            .pick_file()
+        else {
+            return;
+        };
+        match playlist_io::import(&path) {
+            Ok((mut playlist, entries)) => {
+                println!("📥 Imported {} tracks from {:?}", playlist.items.len(), path);
+                self.remember_entry_info(&playlist.items, entries);
+                self.loudness_scanner.enqueue(&playlist.items);
+                self.library_indexer.enqueue(&playlist.items);
+                playlist.current_index = None;
+                self.playlists.push(playlist);
+                self.active_playlist = self.playlists.len() - 1;
+            }
+            Err(e) => eprintln!("Failed to import playlist {:?}: {}", path, e),
+        }
+    }
+
+    /// השם והאמן שהפלייליסט סיפר על שירים שעוד אין להם תגיות, עד שהאינדקס יגיע אליהם
+    fn remember_entry_info(
+        &mut self,
+        items: &[std::path::PathBuf],
+        entries: Vec<playlist_io::EntryInfo>,
+    ) {
+        for (path, info) in items.iter().zip(entries) {
+            if info.title.is_some() && !self.track_tags.contains_key(path) {
+                let meta = TrackMetadata {
+                    title: info.title,
+                    artist: info.artist,
+                    album: info.album,
+                    ..Default::default()
+                };
+                self.track_tags.insert(path.clone(), meta);
+            }
+        }
+    }
+
+    fn export_playlist_dialog(&self) {
+        let playlist = self.shown();
+        let Some(mut path) = rfd::FileDialog::new()
+            .add_filter("M3U8", &["m3u8"])
+            .add_filter("M3U", &["m3u"])
+            .add_filter("PLS", &["pls"])
+            .add_filter("XSPF", &["xspf"])
+            .set_file_name(format!("{}.m3u8", playlist.name))
+            .save_file()
+        else {
+            return;
+        };
+        if playlist_io::PlaylistFormat::from_path(&path).is_none() {
+            path.set_extension("m3u8");
+        }
+
+        let library = self.library_indexer.library.lock().ok();
+        let describe = |track: &std::path::Path| {
+            let meta = self.track_tags.get(track);
+            playlist_io::EntryInfo {
+                title: meta.and_then(|m| m.title.clone()),
+                artist: meta.and_then(|m| m.artist.clone()),
+                album: meta.and_then(|m| m.album.clone()),
+                duration_secs: library
+                    .as_ref()
+                    .and_then(|db| db.track(track))
+                    .map(|t| t.info.duration_secs)
+                    .filter(|secs| *secs > 0.0),
+            }
+        };
+        match playlist_io::export(playlist, &path, describe) {
+            Ok(()) => println!("📤 Playlist exported to {:?}", path),
+            Err(e) => eprintln!("Failed to export playlist {:?}: {}", path, e),
         }
     }
 
@@ -153,44 +649,286 @@ impl MusicApp {
             .add_filter("Audio Files", &["mp3", "wav", "ogg", "flac", "m4a"])
             .pick_files()
         {
-            for path in paths {
-                if !self.playlist.contains(&path) {
-                    self.playlist.push(path);
+            self.add_tracks(paths);
+        }
+    }
+
+    /// לאן נכנסים שירים חדשים: לרשימה המוצגת, ואם היא חכמה (נבנית מהכללים) -
+    /// לרשימה שמתנגנת או לרשימה הרגילה הראשונה. None = כל הרשימות חכמות
+    fn import_target(&self) -> Option<usize> {
+        [self.active_playlist, self.playing_playlist]
+            .into_iter()
+            .chain(0..self.playlists.len())
+            .find(|&idx| self.playlists[idx].rules.is_none())
+    }
+
+    /// מוסיף שירים לסוף ה-import_target (מה שלא כבר בו) ושולח אותם לסריקות.
+    /// השייכות נבדקת ב-HashSet ולא ב-contains לכל קובץ
+    fn add_tracks(&mut self, paths: Vec<std::path::PathBuf>) {
+        let Some(target) = self.import_target() else {
+            // אין רשימה רגילה - השירים נכנסים לספרייה, ומשם לרשימות החכמות שמתאימות
+            self.loudness_scanner.enqueue(&paths);
+            self.library_indexer.enqueue(&paths);
+            return;
+        };
+        let playlist = &mut self.playlists[target];
+        let mut known: HashSet<std::path::PathBuf> = playlist.items.iter().cloned().collect();
+        let added: Vec<_> = paths.into_iter().filter(|p| known.insert(p.clone())).collect();
+        self.loudness_scanner.enqueue(&added);
+        self.library_indexer.enqueue(&added);
+        for path in added {
+            playlist.add(path);
+        }
+    }
+
+    /// קבצים שנגררו לחלון: שירים, תיקיות (רקורסיבית) ופלייליסטים, שנכנסים לפני השיר at
+    fn add_dropped(&mut self, paths: Vec<std::path::PathBuf>, at: usize) {
+        let mut tracks = Vec::new();
+        for path in paths {
+            if path.is_dir() {
+                tracks.extend(library_db::collect_audio_files(&path));
+                self.library_indexer.add_root(path);
+            } else if playlist_io::PlaylistFormat::from_path(&path).is_some() {
+                match playlist_io::import(&path) {
+                    Ok((playlist, entries)) => {
+                        self.remember_entry_info(&playlist.items, entries);
+                        tracks.extend(playlist.items);
+                    }
+                    Err(e) => eprintln!("Failed to import playlist {:?}: {}", path, e),
                 }
+            } else if library_db::is_audio_file(&path) {
+                tracks.push(path);
             }
         }
+        let start = self.shown().items.len();
+        self.add_tracks(tracks);
+        // הכל נוסף בסוף - מזיזים למקום שבו שוחרר העכבר. רשימה חכמה לא מקבלת גרירה,
+        // ואז השירים נשארים בסוף הרשימה הרגילה שקיבלה אותם
+        if self.import_target() == Some(self.active_playlist) {
+            self.shown_mut().move_tail_to(start, at);
+        }
     }
 
-    fn get_track_info(&self) -> (String, String) {
-        if let Some(idx) = self.selected_track
-            && let Some(path) = self.playlist.get(idx)
+    /// הרשימה שמוצגת במרכז
+    fn shown(&self) -> &Playlist {
+        &self.playlists[self.active_playlist]
+    }
+
+    fn shown_mut(&mut self) -> &mut Playlist {
+        &mut self.playlists[self.active_playlist]
+    }
+
+    /// הרשימה שממנה מתנגנים השירים (הבא / הקודם / Gapless) - לא בהכרח זו שמוצגת
+    fn playing(&self) -> &Playlist {
+        &self.playlists[self.playing_playlist]
+    }
+
+    fn playing_mut(&mut self) -> &mut Playlist {
+        &mut self.playlists[self.playing_playlist]
+    }
+
+    /// טוען ומנגן את השיר הנוכחי של הרשימה המתנגנת
+    fn play_current(&mut self) {
+        if let Some(path) = self.playing().get_current().cloned()
+            && let Some(path_str) = path.to_str()
         {
-            let filename = path
-                .file_stem()
-                .unwrap_or_default()
-                .to_string_lossy()
-                .to_string();
-            return (filename, "Unknown Artist".to_string());
+            self.engine.load(path_str);
+            self.engine.play();
+            self.track_started(&path);
         }
-        ("No Track Selected".to_string(), "".to_string())
     }
 
-    fn play_next(&mut self) {
-        if let Some(current_idx) = self.selected_track
-            && current_idx < self.playlist.len() - 1
+    /// כל שיר שמתחיל לנגן עובר כאן: סדר הניגון, ומונה ההאזנה של השיר הקודם
+    fn track_started(&mut self, path: &std::path::Path) {
+        self.pending_listen = None;
+        self.play_order.started(path);
+        if let Some(skipped) = self.listen_tracker.start(path, library_db::now_secs())
+            && let Ok(mut db) = self.library_indexer.library.lock()
         {
-            let next_idx = current_idx + 1;
-            self.selected_track = Some(next_idx);
-            if let Some(path) = self.playlist.get(next_idx)
-                && let Some(path_str) = path.to_str()
+            db.record_skip(&skipped);
+        }
+    }
+
+    /// השיר שמתנגן נשמע מספיק זמן כדי להיחשב כהשמעה - נכנס לספרייה וליומן
+    fn update_listening(&mut self) {
+        let playing = self.engine.current_state == PlayerState::Playing;
+        // שיר שנטען בלי לנגן (השיר האחרון בהפעלה) נספר מהרגע שהוא באמת מתחיל -
+        // ה-Play בהדר קורא למנוע ישירות, אז בודקים כאן את המצב שלו
+        if playing && let Some(path) = self.pending_listen.take() {
+            self.track_started(&path);
+        }
+        let Some(entry) = self.listen_tracker.update(
+            self.engine.current_position,
+            self.engine.current_duration,
+            playing,
+        ) else {
+            return;
+        };
+        if let Ok(mut db) = self.library_indexer.library.lock() {
+            db.record_play(&entry.path, entry.played_at);
+        }
+        self.history.append(entry);
+    }
+
+    /// דירוג נשמר בספרייה, ואם המשתמש ביקש - גם בתגיות של הקובץ עצמו
+    fn rate_tracks(&mut self, paths: Vec<std::path::PathBuf>, rating: u8) {
+        let rated: Vec<std::path::PathBuf> = match self.library_indexer.library.lock() {
+            Ok(mut db) => paths
+                .into_iter()
+                .filter(|path| db.set_rating(path, rating))
+                .collect(),
+            Err(_) => return,
+        };
+        if !self.write_rating_tags {
+            return;
+        }
+        for path in rated.into_iter().filter(|p| rating_tags::supports(p)) {
+            self.rating_writer.write(path, rating);
+        }
+    }
+
+    /// רק תגית הדירוג השתנתה: האינדקס מתעדכן (עם ההשמעות והדירוג שבספרייה) והמדידה
+    /// של העוצמה נשארת, במקום שה-Watcher יסרוק ויימדוד את הקובץ מחדש
+    fn apply_rating_writes(&mut self) {
+        self.rating_write_errors.extend(self.rating_writer.poll_failures());
+        for written in self.rating_writer.poll_written() {
+            if let (Some(before), Some(after)) = (written.before, written.after) {
+                self.loudness_scanner.carry_over(&written.path, before, after);
+            }
+            self.library_indexer.enqueue(std::slice::from_ref(&written.path));
+            self.rating_written.insert(written.path, Instant::now());
+        }
+    }
+
+    fn love_tracks(&mut self, paths: Vec<std::path::PathBuf>, loved: bool) {
+        if let Ok(mut db) = self.library_indexer.library.lock() {
+            for path in &paths {
+                db.set_loved(path, loved);
+            }
+        }
+    }
+
+    fn get_track_info(&self) -> (String, String) {
+        if let Some(path) = self.playing().get_current() {
+            let meta = self.track_tags.get(path).cloned().unwrap_or_default();
+            return (meta.display_title(path), meta.display_artist());
+        }
+        ("No Track Selected".to_string(), "".to_string())
+    }
+
+    /// השיר הבא לפי מצבי החזרה והערבוב, בלי להתקדם אליו.
+    /// manual = המשתמש לחץ "הבא" (ב-Repeat One זה עובר שיר ולא מנגן שוב)
+    fn next_index(&mut self, manual: bool) -> Option<usize> {
+        let playlist = &self.playlists[self.playing_playlist];
+        let track_tags = &self.track_tags;
+        self.play_order.peek_next(
+            self.playing_playlist,
+            playlist,
+            self.repeat_mode,
+            self.shuffle_mode,
+            manual,
+            |path| track_tags.get(path).and_then(|m| m.artist.clone()),
+        )
+    }
+
+    /// השיר הבא כ-(פלייליסט, אינדקס): קודם התור, ואחריו סדר הניגון של הרשימה המתנגנת.
+    /// ב-Repeat One סוף שיר עדיין מנגן אותו שוב - התור מחכה ללחיצה על "הבא"
+    fn next_track(&mut self, manual: bool) -> Option<(usize, usize)> {
+        if self.repeat_mode != RepeatMode::One || manual {
+            let playlists = &self.playlists;
+            let playing = self.playing_playlist;
+            let queued = self
+                .play_order
+                .peek_queue(|path| locate_track(playlists, playing, path).is_some());
+            if let Some(path) = queued {
+                return locate_track(&self.playlists, self.playing_playlist, &path);
+            }
+        }
+        self.next_index(manual).map(|idx| (self.playing_playlist, idx))
+    }
+
+    /// מוודא שהמנוע מחזיק את השיר הבא הנכון, כדי שהמעבר אליו יהיה בלי רווח
+    fn sync_gapless_next(&mut self) {
+        let next = self
+            .next_track(false)
+            .and_then(|(list, idx)| self.playlists[list].items.get(idx).cloned());
+        if next != self.gapless_next {
+            self.engine.set_next(next.as_ref().and_then(|p| p.to_str()));
+            self.gapless_next = next;
+        }
+    }
+
+    /// המנוע עבר לבד לשיר הבא - מעדכנים את הבחירה בלי לטעון מחדש
+    fn on_gapless_track_changed(&mut self) {
+        if let Some(next) = self.gapless_next.take() {
+            // שיר מהתור יכול להיות מפלייליסט אחר - ואז הוא הופך לרשימה המתנגנת
+            if let Some((list, idx)) = locate_track(&self.playlists, self.playing_playlist, &next)
             {
-                self.engine.load(path_str);
-                self.engine.play();
+                self.playing_playlist = list;
+                self.playing_mut().select(idx);
+            }
+            self.track_started(&next);
+        }
+    }
+
+    /// סוף שיר
+    fn play_next(&mut self) {
+        if let Some((list, idx)) = self.next_track(false) {
+            self.playing_playlist = list;
+            self.playing_mut().select(idx);
+            self.play_current();
+        }
+    }
+
+    /// כפתור "הבא"
+    fn skip_next(&mut self) {
+        if let Some((list, idx)) = self.next_track(true) {
+            self.playing_playlist = list;
+            self.playing_mut().select(idx);
+            self.play_current();
+        }
+    }
+
+    /// כפתור "הקודם": באמצע שיר חוזר להתחלה, אחרת לשיר שבאמת התנגן לפניו
+    fn play_previous(&mut self) {
+        if self.engine.current_position > 3.0 {
+            self.engine.seek(0.0);
+            return;
+        }
+        let previous = self
+            .play_order
+            .previous(&self.playlists[self.playing_playlist]);
+        match previous {
+            Some(idx) => {
+                self.playing_mut().select(idx);
+                self.play_current();
+            }
+            // אין היסטוריה (למשל מיד אחרי הפעלה) - פשוט שיר אחד אחורה
+            None => {
+                if self.playing_mut().previous().is_some() {
+                    self.play_current();
+                }
             }
         }
     }
 }
 
+/// איפה השיר נמצא: קודם ברשימה המועדפת (המתנגנת), ואחר כך בשאר הפלייליסטים
+fn locate_track(
+    playlists: &[Playlist],
+    preferred: usize,
+    path: &std::path::Path,
+) -> Option<(usize, usize)> {
+    let position = |list: usize| {
+        playlists[list]
+            .items
+            .iter()
+            .position(|p| p == path)
+            .map(|idx| (list, idx))
+    };
+    position(preferred).or_else(|| (0..playlists.len()).find_map(position))
+}
+
 // =========================================================
 // Main Entry Point
 // =========================================================
@@ -253,47 +991,70 @@ impl eframe::App for MusicApp {
     fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
         // --- 1. Logic & Transitions ---
 
+        // כשמקלידים בשדה טקסט (חיפוש, שינוי שם) החצים ו-Enter שייכים לו
+        let typing = ctx.wants_keyboard_input();
         ctx.input(|i| {
-            if !self.playlist.is_empty() {
+            if !typing && !self.shown().items.is_empty() {
                 // חץ למטה - רד שיר אחד
                 if i.key_pressed(egui::Key::ArrowDown) {
-                    let current = self.selected_track.unwrap_or(0);
-                    if current < self.playlist.len() - 1 {
-                        self.selected_track = Some(current + 1);
+                    let playlist = self.shown_mut();
+                    let current = playlist.current_index.unwrap_or(0);
+                    if current < playlist.items.len() - 1 {
+                        playlist.current_index = Some(current + 1);
                     }
                 }
                 // חץ למעלה - עלה שיר אחד
                 if i.key_pressed(egui::Key::ArrowUp) {
-                    let current = self.selected_track.unwrap_or(0);
+                    let playlist = self.shown_mut();
+                    let current = playlist.current_index.unwrap_or(0);
                     if current > 0 {
-                        self.selected_track = Some(current - 1);
+                        playlist.current_index = Some(current - 1);
                     }
                 }
-                // אינטר - נגן את השיר הנבחר
+                // אינטר - נגן את השיר הנבחר (והרשימה הזו הופכת לרשימה המתנגנת)
                 if i.key_pressed(egui::Key::Enter) {
-                    if let Some(idx) = self.selected_track {
-                        if let Some(path) = self.playlist.get(idx) {
-                            if let Some(s) = path.to_str() {
-                                self.engine.load(s);
-                                self.engine.play();
-                            }
-                        }
-                    }
+                    self.playing_playlist = self.active_playlist;
+                    self.play_current();
                 }
             }
         });
 
         let engine_eos = self.engine.update();
-        let time_is_up = self.engine.current_state == PlayerState::Playing
-            && self.engine.current_duration > 0.0
-            && self.engine.current_position >= (self.engine.current_duration - 0.5);
+        if self.engine.take_track_change().is_some() {
+            self.on_gapless_track_changed();
+        }
+        self.update_listening();
+        // התגיות שייכות לשיר שמתנגן עכשיו
+        if let Some(meta) = self.engine.take_metadata()
+            && let Some(path) = self.playing().get_current().cloned()
+        {
+            self.track_tags.insert(path, meta);
+        }
+        // לפני ה-Watcher: אם שניהם הגיעו באותו פריים, הכתיבה שלנו כבר ידועה
+        self.apply_rating_writes();
+        for batch in self.folder_watcher.poll() {
+            self.apply_watch_batch(batch);
+        }
+        for event in self.library_indexer.poll_events() {
+            match event {
+                IndexEvent::Loaded(tags) => self.track_tags.extend(tags),
+                IndexEvent::Indexed(path, entry) => {
+                    self.track_tags.insert(path, entry.metadata);
+                }
+                IndexEvent::Removed(path) => {
+                    self.track_tags.remove(&path);
+                }
+            }
+        }
+        self.refresh_smart_playlists(false);
 
         self.theme_manager.apply_theme(ctx);
         self.time_for_animation = ctx.input(|i| i.time as f32);
 
-        if engine_eos || time_is_up {
+        if engine_eos {
             self.play_next();
         }
+        self.sync_gapless_next();
 
         // --- 2. Top Menu Bar ---
         egui::TopBottomPanel::top("top_menu").show(ctx, |ui: &mut egui::Ui| {
@@ -314,12 +1075,93 @@ impl eframe::App for MusicApp {
                         ui.close();
                     }
 
+                    ui.separator();
+                    if ui.button("📥 Import Playlist...").clicked() {
+                        self.import_playlist_dialog();
+                        ui.close();
+                    }
+                    if ui.button("📤 Export Playlist...").clicked() {
+                        self.export_playlist_dialog();
+                        ui.close();
+                    }
+                    ui.separator();
+
+                    if ui.button("👁 Watch Folder...").clicked() {
+                        self.watch_folder_dialog();
+                        ui.close();
+                    }
+
+                    if !self.watched_roots.is_empty() {
+                        ui.menu_button("📁 Watched Folders", |ui: &mut egui::Ui| {
+                            let mut to_unwatch = None;
+                            for root in &self.watched_roots {
+                                ui.horizontal(|ui| {
+                                    let stop = ui.small_button("✖").on_hover_text("Stop watching");
+                                    if stop.clicked() {
+                                        to_unwatch = Some(root.clone());
+                                    }
+                                    ui.label(root.to_string_lossy());
+                                });
+                            }
+                            if let Some(root) = to_unwatch {
+                                self.unwatch_folder(&root);
+                            }
+                        });
+                    }
+
+                    if ui.button("🔄 Rescan Library").clicked() {
+                        self.library_indexer.rescan();
+                        for root in self.watched_roots.clone() {
+                            self.reconcile_root(&root);
+                        }
+                        ui.close();
+                    }
+                    ui.checkbox(&mut self.write_rating_tags, "🏷 Write Ratings to File Tags")
+                        .on_hover_text(
+                            "MP3 (POPM), FLAC, Ogg Vorbis and Opus (FMPS_RATING) files.\n\
+                             Other formats keep the rating in the library only",
+                        );
+
                     if ui.button("❌ Quit").clicked() {
                         ctx.send_viewport_cmd(egui::ViewportCommand::Close);
                     }
                 });
 
                 ui.menu_button("View", |ui: &mut egui::Ui| {
+                    ui.checkbox(&mut self.show_browser, "📚 Library Browser");
+                    ui.checkbox(&mut self.show_history, "🕘 Listening History");
+                    ui.menu_button("📊 Visualizer", |ui: &mut egui::Ui| {
+                        let mut changed = false;
+                        ui.label("Bands:");
+                        changed |= ui
+                            .add(egui::Slider::new(&mut self.spectrum.bands, 8..=128))
+                            .changed();
+                        ui.label("Update Interval:");
+                        changed |= ui
+                            .add(
+                                egui::Slider::new(&mut self.spectrum.interval_ms, 16..=500)
+                                    .suffix(" ms"),
+                            )
+                            .changed();
+                        ui.label("Noise Threshold:");
+                        changed |= ui
+                            .add(
+                                egui::Slider::new(&mut self.spectrum.threshold_db, -100..=-20)
+                                    .suffix(" dB"),
+                            )
+                            .changed();
+                        if ui.button("Reset").clicked() {
+                            self.spectrum = SpectrumSettings::default();
+                            changed = true;
+                        }
+                        if changed {
+                            self.engine.set_spectrum(self.spectrum);
+                        }
+                    });
+                    if ui.button("✨ Recently Played").clicked() {
+                        self.open_recently_played();
+                        ui.close();
+                    }
                     ui.menu_button("Theme", |ui: &mut egui::Ui| {
                         if ui.button("Dark Mode").clicked() {
                             self.theme_manager.activate_dark_mode(true);
@@ -344,6 +1186,120 @@ impl eframe::App for MusicApp {
                         }
                     });
                 });
+                ui.menu_button("Playback", |ui: &mut egui::Ui| {
+                    ui.menu_button("🔈 Output Device", |ui: &mut egui::Ui| {
+                        if ui
+                            .radio(self.output_device.is_none(), "System Default")
+                            .clicked()
+                        {
+                            self.output_device = None;
+                            self.engine.set_output_device(None);
+                            ui.close();
+                        }
+                        for device in self.engine.output_devices.clone() {
+                            let selected = self.output_device.as_ref() == Some(&device.id);
+                            if ui.radio(selected, &device.name).clicked() {
+                                self.output_device = Some(device.id.clone());
+                                self.engine.set_output_device(Some(device.id));
+                                ui.close();
+                            }
+                        }
+                    });
+
+                    ui.separator();
+                    for mode in [RepeatMode::Off, RepeatMode::All, RepeatMode::One] {
+                        ui.radio_value(&mut self.repeat_mode, mode, mode.label());
+                    }
+                    ui.separator();
+                    for mode in [ShuffleMode::Off, ShuffleMode::On, ShuffleMode::Smart] {
+                        ui.radio_value(&mut self.shuffle_mode, mode, mode.label());
+                    }
+
+                    ui.separator();
+                    ui.label("Speed:");
+                    ui.horizontal(|ui| {
+                        if ui
+                            .add(
+                                egui::Slider::new(
+                                    &mut self.playback_rate,
+                                    audio_engine::MIN_RATE as f32..=audio_engine::MAX_RATE as f32,
+                                )
+                                .suffix("x")
+                                .step_by(0.05),
+                            )
+                            .changed()
+                        {
+                            self.engine.set_rate(self.playback_rate);
+                        }
+                        if ui.button("1x").clicked() {
+                            self.playback_rate = 1.0;
+                            self.engine.set_rate(self.playback_rate);
+                        }
+                    });
+
+                    ui.separator();
+                    ui.label("Crossfade:");
+                    if ui
+                        .add(
+                            egui::Slider::new(
+                                &mut self.crossfade_secs,
+                                0.0..=audio_engine::MAX_CROSSFADE_SECS as f32,
+                            )
+                            .suffix(" s")
+                            .step_by(0.5),
+                        )
+                        .changed()
+                    {
+                        self.engine.set_crossfade_duration(self.crossfade_secs);
+                    }
+
+                    ui.add_enabled_ui(self.crossfade_secs > 0.0, |ui| {
+                        ui.label("Fade Curve:");
+                        for curve in FadeCurve::all() {
+                            if ui
+                                .radio_value(&mut self.crossfade_curve, curve, curve.label())
+                                .changed()
+                            {
+                                self.engine.set_crossfade_curve(curve);
+                            }
+                        }
+                    });
+
+                    ui.separator();
+                    ui.label("ReplayGain:");
+                    for mode in ReplayGainMode::all() {
+                        if ui
+                            .radio_value(&mut self.replaygain_mode, mode, mode.label())
+                            .changed()
+                        {
+                            self.engine.set_replaygain_mode(mode);
+                        }
+                    }
+
+                    ui.add_enabled_ui(self.replaygain_mode != ReplayGainMode::Off, |ui| {
+                        ui.label("Pre-amp:");
+                        if ui
+                            .add(
+                                egui::Slider::new(&mut self.replaygain_preamp, -15.0..=15.0)
+                                    .suffix(" dB"),
+                            )
+                            .changed()
+                        {
+                            self.engine.set_replaygain_preamp(self.replaygain_preamp);
+                        }
+
+                        ui.label("Fallback Gain (untagged files):");
+                        if ui
+                            .add(
+                                egui::Slider::new(&mut self.replaygain_fallback, -15.0..=15.0)
+                                    .suffix(" dB"),
+                            )
+                            .changed()
+                        {
+                            self.engine.set_replaygain_fallback(self.replaygain_fallback);
+                        }
+                    });
+                });
                 ui.menu_button("Help", |ui| {
                     if ui.button("ℹ About").clicked() {
                         self.show_about = true;
@@ -424,15 +1380,22 @@ impl eframe::App for MusicApp {
                     .stroke(egui::Stroke::new(1.0, Color32::from_white_alpha(5))),
             )
             .show(ctx, |ui: &mut egui::Ui| {
-                let (title, _artist) = self.get_track_info();
+                let (title, artist) = self.get_track_info();
                 let accent = self.theme_manager.get_current_accent_color();
+                let current = self.playing().get_current().cloned();
+                let stats = current.as_ref().and_then(|path| {
+                    let db = self.library_indexer.library.lock().ok()?;
+                    db.track(path).map(|track| track.stats.clone())
+                });
 
-                components::draw_compact_header(
+                let action = components::draw_compact_header(
                     ui,
                     &mut self.engine,
-                    &self.playlist,
-                    &mut self.selected_track,
+                    &mut self.repeat_mode,
+                    &mut self.shuffle_mode,
                     &title,
+                    &artist,
+                    stats.as_ref(),
                     accent,
                     // משתמשים ב-as_ref() כדי להפוך Option<T> ל-Option<&T>
                     self.btn_play.as_ref(),
@@ -440,6 +1403,17 @@ impl eframe::App for MusicApp {
                     self.btn_next.as_ref(),
                     self.btn_prev.as_ref(),
                 );
+                match (action, current) {
+                    (Some(components::HeaderAction::Previous), _) => self.play_previous(),
+                    (Some(components::HeaderAction::Next), _) => self.skip_next(),
+                    (Some(components::HeaderAction::Rate(rating)), Some(path)) => {
+                        self.rate_tracks(vec![path], rating);
+                    }
+                    (Some(components::HeaderAction::Love(loved)), Some(path)) => {
+                        self.love_tracks(vec![path], loved);
+                    }
+                    _ => {}
+                }
 
                 ui.add_space(8.0);
                 self.render_visualizer(ui);
@@ -494,27 +1468,99 @@ impl eframe::App for MusicApp {
                         }
                     };
 
+                    if (self.playback_rate - 1.0).abs() > f32::EPSILON {
+                        ui.label(
+                            RichText::new(format!("{:.2}x", self.playback_rate))
+                                .color(Color32::from_rgb(255, 180, 0))
+                                .size(11.0)
+                                .strong(),
+                        );
+                    }
+
                     ui.add_space(10.0);
                     ui.separator();
                     ui.add_space(10.0);
 
-                    ui.label(
-                        RichText::new("UHD/4K Mode")
-                            .color(Color32::from_rgb(255, 50, 200))
-                            .size(15.0),
-                    );
-                    ui.label(RichText::new("|").color(Color32::from_white_alpha(50)));
-                    ui.label(
-                        RichText::new("325.1kHz")
+                    // הפורמט האמיתי של מה שמתנגן (מגיע מה-caps והתגיות של GStreamer)
+                    if let Some(info) = &self.engine.stream_info {
+                        ui.label(
+                            RichText::new(info.codec.as_deref().unwrap_or("Unknown Codec"))
+                                .color(Color32::from_rgb(255, 50, 200))
+                                .size(15.0),
+                        );
+                        ui.label(RichText::new("|").color(Color32::from_white_alpha(50)));
+                        ui.label(
+                            RichText::new(format!(
+                                "{} / {}-bit / {}",
+                                info.sample_rate_label(),
+                                info.bit_depth,
+                                info.channels_label()
+                            ))
                             .color(Color32::from_rgb(0, 255, 255))
                             .size(15.0),
-                    );
-                    ui.label(
-                        RichText::new("GstremerBASS")
-                            .color(Color32::from_rgb(50, 255, 50))
-                            .size(15.0),
-                    );
+                        );
+                        if let Some(bitrate) = info.bitrate {
+                            ui.label(
+                                RichText::new(format!("{} kbps", bitrate / 1000))
+                                    .color(Color32::from_rgb(50, 255, 50))
+                                    .size(15.0),
+                            );
+                        }
+                    } else {
+                        ui.label(
+                            RichText::new("No Stream")
+                                .color(Color32::from_white_alpha(80))
+                                .size(15.0),
+                        );
+                    }
                     ui.label(RichText::new("|").color(Color32::from_white_alpha(50)));
+
+                    if let Some((done, total)) = self.library_indexer.progress() {
+                        ui.label(
+                            RichText::new(format!("🔎 Indexing library: {}/{}", done, total))
+                                .color(Color32::LIGHT_BLUE)
+                                .size(12.0),
+                        );
+                        ui.add(
+                            egui::ProgressBar::new(done as f32 / total as f32)
+                                .desired_width(80.0)
+                                .desired_height(6.0),
+                        );
+                    }
+
+                    let pending_scans = self.loudness_scanner.pending();
+                    if pending_scans > 0 {
+                        ui.label(
+                            RichText::new(format!("📊 Analyzing loudness: {} left", pending_scans))
+                                .color(Color32::LIGHT_BLUE)
+                                .size(12.0),
+                        );
+                    }
+
+                    // דירוגים שלא נכתבו לקובץ (קובץ לקריאה בלבד, תגית לא נתמכת...)
+                    if !self.rating_write_errors.is_empty() {
+                        let details: Vec<String> = self
+                            .rating_write_errors
+                            .iter()
+                            .map(|(path, e)| format!("{}: {}", path.display(), e))
+                            .collect();
+                        let text = format!(
+                            "⚠ Rating not written to {} file(s)",
+                            self.rating_write_errors.len()
+                        );
+                        let response = ui
+                            .add(
+                                egui::Label::new(
+                                    RichText::new(text).color(Color32::YELLOW).size(12.0),
+                                )
+                                .sense(egui::Sense::click()),
+                            )
+                            .on_hover_text(format!("{}\n\nClick to dismiss", details.join("\n")));
+                        if response.clicked() {
+                            self.rating_write_errors.clear();
+                        }
+                    }
+
                     ui.with_layout(
                         egui::Layout::right_to_left(egui::Align::Center),
                         |ui: &mut egui::Ui| {
@@ -545,19 +1591,134 @@ impl eframe::App for MusicApp {
                 components::draw_equalizer(ui, &mut self.eq, &mut self.volume, &mut self.engine);
             });
 
-        // --- 6. Central Panel (Playlist) ---
+        // --- 6. Playlists Sidebar & Library Browser ---
+        egui::SidePanel::left("playlists_sidebar")
+            .resizable(true)
+            .default_width(170.0)
+            .show(ctx, |ui: &mut egui::Ui| {
+                let sidebar_action = components::draw_playlist_sidebar(
+                    ui,
+                    &mut self.playlists,
+                    &mut self.active_playlist,
+                    self.playing_playlist,
+                    &mut self.renaming_playlist,
+                    self.theme_manager.get_current_accent_color(),
+                );
+                match sidebar_action {
+                    Some(components::SidebarAction::Duplicate(idx)) => self.duplicate_playlist(idx),
+                    Some(components::SidebarAction::Delete(idx)) => self.delete_playlist(idx),
+                    Some(components::SidebarAction::Smart(request)) => {
+                        self.open_smart_editor(request)
+                    }
+                    None => {}
+                }
+
+                if !self.play_order.queue().is_empty() {
+                    ui.separator();
+                    components::draw_queue(
+                        ui,
+                        self.play_order.queue_mut(),
+                        &self.track_tags,
+                        self.theme_manager.get_current_accent_color(),
+                    );
+                }
+            });
+
+        if self.show_browser {
+            let current_accent = self.theme_manager.get_current_accent_color();
+            let mut action = None;
+            egui::SidePanel::left("library_browser")
+                .resizable(true)
+                .default_width(300.0)
+                .show(ctx, |ui: &mut egui::Ui| {
+                    if let Ok(db) = self.library_indexer.library.lock() {
+                        action = self.browser.show(ui, &db, &mut self.covers, current_accent);
+                    }
+                });
+            if let Some(action) = action {
+                self.apply_browser_action(action);
+            }
+        }
+
+        // --- 7. Central Panel (Playlist) ---
+        let mut drop_at = None;
         egui::CentralPanel::default().show(ctx, |ui: &mut egui::Ui| {
             let current_accent = self.theme_manager.get_current_accent_color();
             
             // 2. עכשיו מעבירים אותו לפונקציה בתור הארגומנט ה-5!
-            components::draw_playlist(
+            // "Add to Playlist" - רק לרשימות רגילות (לחכמות לא מוסיפים ידנית)
+            let add_targets: Vec<(usize, String)> = self
+                .playlists
+                .iter()
+                .enumerate()
+                .filter(|(idx, p)| *idx != self.active_playlist && p.rules.is_none())
+                .map(|(idx, p)| (idx, p.name.clone()))
+                .collect();
+            let library = self.library_indexer.library.lock().ok();
+            let view = components::PlaylistView {
+                selection: &mut self.selection,
+                search: &mut self.search_query,
+                table: &mut self.table_config,
+                add_targets: &add_targets,
+                track_tags: &self.track_tags,
+                library: library.as_deref(),
+            };
+            let action = components::draw_playlist(
                 ui,
-                &mut self.playlist,
-                &mut self.selected_track,
+                &mut self.playlists[self.active_playlist],
+                view,
                 &mut self.engine,
                 current_accent, // <--- זה מה שהיה חסר לקומפיילר!
             );
+            drop(library);
+            match action {
+                Some(components::PlaylistAction::Started) => {
+                    self.playing_playlist = self.active_playlist;
+                    if let Some(path) = self.shown().get_current().cloned() {
+                        self.track_started(&path);
+                    }
+                }
+                Some(components::PlaylistAction::PlayNext(paths)) => {
+                    // בסדר הפוך, כדי שיתנגנו בסדר שבו הם מופיעים ברשימה
+                    for path in paths.into_iter().rev() {
+                        self.play_order.queue_mut().push_front(path);
+                    }
+                }
+                Some(components::PlaylistAction::AddToQueue(paths)) => {
+                    self.play_order.queue_mut().extend(paths);
+                }
+                Some(components::PlaylistAction::AddToPlaylist(paths, list)) => {
+                    let target = &mut self.playlists[list];
+                    for path in paths {
+                        if !target.contains(&path) {
+                            target.add(path);
+                        }
+                    }
+                }
+                Some(components::PlaylistAction::DropAt(idx)) => drop_at = Some(idx),
+                Some(components::PlaylistAction::AddFiles(paths)) => self.add_tracks(paths),
+                Some(components::PlaylistAction::Rate(paths, rating)) => {
+                    self.rate_tracks(paths, rating);
+                }
+                Some(components::PlaylistAction::Love(paths, loved)) => {
+                    self.love_tracks(paths, loved);
+                }
+                None => {}
+            }
+        });
+
+        // קבצים שנגררו מבחוץ. מחוץ לרשימה (למשל מעל הסרגל הצדדי) - לסוף הרשימה
+        let dropped: Vec<std::path::PathBuf> = ctx.input(|i| {
+            i.raw
+                .dropped_files
+                .iter()
+                .filter_map(|file| file.path.clone())
+                .collect()
         });
+        if !dropped.is_empty() {
+            let at = drop_at.unwrap_or(self.shown().items.len());
+            self.add_dropped(dropped, at);
+        }
                 
 
 
@@ -568,7 +1729,7 @@ impl eframe::App for MusicApp {
 
         
 
-        // --- 7. Floating Theme Studio Window ---
+        // --- 8. Floating Theme Studio Window ---
         if self.is_theme_window_open {
             egui::Window::new("Appearance Studio")
                 .open(&mut self.is_theme_window_open)
@@ -643,6 +1804,51 @@ impl eframe::App for MusicApp {
                 });
         }
 
+        // --- 9. Smart Playlist Editor ---
+        if let Some(editor) = &mut self.smart_editor {
+            let matching = self
+                .library_indexer
+                .library
+                .lock()
+                .map(|db| editor.rules.evaluate(&db, library_db::now_secs()).len())
+                .unwrap_or(0);
+            let mut open = true;
+            let mut action = None;
+            egui::Window::new("✨ Smart Playlist")
+                .open(&mut open)
+                .resizable(false)
+                .collapsible(false)
+                .show(ctx, |ui| {
+                    action = components::draw_smart_editor(ui, editor, matching);
+                });
+            if !open {
+                action = Some(components::SmartEditorAction::Cancel);
+            }
+            if let Some(action) = action {
+                self.apply_smart_editor_action(action);
+            }
+        }
+
+        // --- 10. Listening History ---
+        if self.show_history {
+            let mut action = None;
+            egui::Window::new("🕘 Listening History")
+                .open(&mut self.show_history)
+                .default_size([420.0, 480.0])
+                .show(ctx, |ui| {
+                    action = components::draw_history(
+                        ui,
+                        &self.history.entries,
+                        &self.track_tags,
+                        library_db::now_secs(),
+                        self.theme_manager.get_current_accent_color(),
+                    );
+                });
+            if let Some(action) = action {
+                self.apply_history_action(action);
+            }
+        }
+
         if matches!(
             self.engine.current_state,
             PlayerState::Playing | PlayerState::Loading
@@ -661,10 +1867,24 @@ impl Drop for MusicApp {
 
         let state = AppState {
             volume: self.volume,
-            playlist: self.playlist.clone(),
-            last_played_index: self.selected_track,
+            playlists: self.playlists.clone(),
+            active_playlist: self.playing_playlist,
             is_dark_mode: self.theme_manager.is_dark_mode_active(),
             accent_color: accent_array,
+            crossfade_secs: self.crossfade_secs,
+            crossfade_curve: self.crossfade_curve,
+            replaygain_mode: self.replaygain_mode,
+            replaygain_preamp: self.replaygain_preamp,
+            replaygain_fallback: self.replaygain_fallback,
+            output_device: self.output_device.clone(),
+            watched_roots: self.watched_roots.clone(),
+            show_browser: self.show_browser,
+            table_config: self.table_config.clone(),
+            repeat_mode: self.repeat_mode,
+            shuffle_mode: self.shuffle_mode,
+            write_rating_tags: self.write_rating_tags,
+            spectrum: self.spectrum,
+            ..Default::default()
         };
 
         state.save();
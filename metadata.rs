@@ -0,0 +1,78 @@
+use gstreamer as gst;
+use serde::{Deserialize, Serialize};
+
+/// המידע על שיר כפי שהוא מופיע בתגיות של הקובץ
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(default)]
+pub struct TrackMetadata {
+    pub title: Option<String>,
+    pub artist: Option<String>,
+    pub album: Option<String>,
+    pub album_artist: Option<String>,
+    pub track_number: Option<u32>,
+    pub year: Option<i32>,
+    pub genre: Option<String>,
+    // תמונת העטיפה המוטמעת (PNG/JPEG כמו שהיא בקובץ) - לא נשמרת ל-JSON, כבדה מדי
+    #[serde(skip)]
+    pub cover: Option<Vec<u8>>,
+}
+
+impl TrackMetadata {
+    /// ממזג TagList לתוך המידע הקיים - תגית חדשה דורסת, תגית חסרה לא מוחקת
+    pub fn merge_tags(&mut self, tags: &gst::TagListRef) {
+        if let Some(v) = tags.get::<gst::tags::Title>() {
+            self.title = Some(v.get().to_string());
+        }
+        if let Some(v) = tags.get::<gst::tags::Artist>() {
+            self.artist = Some(v.get().to_string());
+        }
+        if let Some(v) = tags.get::<gst::tags::Album>() {
+            self.album = Some(v.get().to_string());
+        }
+        if let Some(v) = tags.get::<gst::tags::AlbumArtist>() {
+            self.album_artist = Some(v.get().to_string());
+        }
+        if let Some(v) = tags.get::<gst::tags::TrackNumber>() {
+            self.track_number = Some(v.get());
+        }
+        if let Some(v) = tags.get::<gst::tags::DateTime>() {
+            self.year = Some(v.get().year());
+        } else if let Some(v) = tags.get::<gst::tags::Date>() {
+            self.year = Some(v.get().year() as i32);
+        }
+        if let Some(v) = tags.get::<gst::tags::Genre>() {
+            self.genre = Some(v.get().to_string());
+        }
+
+        // עטיפה: קודם Image, ואם אין - PreviewImage
+        let sample = tags
+            .get::<gst::tags::Image>()
+            .map(|v| v.get())
+            .or_else(|| tags.get::<gst::tags::PreviewImage>().map(|v| v.get()));
+        if let Some(bytes) = sample
+            .as_ref()
+            .and_then(|s| s.buffer())
+            .and_then(|b| b.map_readable().ok())
+            .map(|map| map.as_slice().to_vec())
+        {
+            self.cover = Some(bytes);
+        }
+    }
+
+    /// הכותרת לתצוגה: שם השיר מהתגיות, ואם אין - שם הקובץ
+    pub fn display_title(&self, path: &std::path::Path) -> String {
+        self.title.clone().unwrap_or_else(|| {
+            path.file_stem()
+                .unwrap_or_default()
+                .to_string_lossy()
+                .to_string()
+        })
+    }
+
+    pub fn display_artist(&self) -> String {
+        self.artist
+            .clone()
+            .or_else(|| self.album_artist.clone())
+            .unwrap_or_else(|| "Unknown Artist".to_string())
+    }
+}
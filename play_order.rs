@@ -0,0 +1,575 @@
+use crate::playlist::Playlist;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::path::{Path, PathBuf};
+
+/// כמה שירים אחורה זוכרים בשביל כפתור "הקודם"
+const MAX_HISTORY: usize = 500;
+
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+pub enum RepeatMode {
+    #[default]
+    Off,
+    All,
+    One,
+}
+
+impl RepeatMode {
+    /// הסדר שבו הכפתור בהדר מחליף מצבים
+    pub fn cycle(&self) -> RepeatMode {
+        match self {
+            RepeatMode::Off => RepeatMode::All,
+            RepeatMode::All => RepeatMode::One,
+            RepeatMode::One => RepeatMode::Off,
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            RepeatMode::Off => "Repeat Off",
+            RepeatMode::All => "Repeat All",
+            RepeatMode::One => "Repeat One",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+pub enum ShuffleMode {
+    #[default]
+    Off,
+    On,
+    Smart, // בלי אותו אמן פעמיים ברצף
+}
+
+impl ShuffleMode {
+    pub fn cycle(&self) -> ShuffleMode {
+        match self {
+            ShuffleMode::Off => ShuffleMode::On,
+            ShuffleMode::On => ShuffleMode::Smart,
+            ShuffleMode::Smart => ShuffleMode::Off,
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            ShuffleMode::Off => "Shuffle Off",
+            ShuffleMode::On => "Shuffle",
+            ShuffleMode::Smart => "Smart Shuffle",
+        }
+    }
+}
+
+/// סדר הניגון: מה בא אחרי השיר הנוכחי, ומה באמת התנגן לפניו.
+/// הכל שמור לפי נתיב ולא לפי אינדקס, כדי ששינויים ברשימה לא ישבשו את הסדר
+#[derive(Default)]
+pub struct PlayOrder {
+    current: Option<PathBuf>,
+    history: Vec<PathBuf>,
+    // הפרמוטציה של הערבוב - None כשעוד לא נוצרה (או שצריך ליצור מחדש)
+    upcoming: Option<VecDeque<PathBuf>>,
+    shuffled_for: Option<(usize, ShuffleMode)>, // (איזה פלייליסט, איזה מצב ערבוב)
+    // כל מה שהסבב כבר כולל (התנגן או מחכה), ואורך הרשימה כשזה נבדק לאחרונה
+    shuffled: HashSet<PathBuf>,
+    shuffled_len: usize,
+    rng: u64,
+    // "Play Next" / "Add to Queue" - קודם לכל סדר אחר, ולא נשמר בין הפעלות
+    queue: VecDeque<PathBuf>,
+}
+
+impl PlayOrder {
+    /// שיר התחיל להתנגן (מכל סיבה שהיא) - השיר הקודם נכנס להיסטוריה
+    pub fn started(&mut self, path: &Path) {
+        if let Some(current) = self.current.take()
+            && current != path
+        {
+            self.history.push(current);
+            if self.history.len() > MAX_HISTORY {
+                self.history.remove(0);
+            }
+        }
+        self.current = Some(path.to_path_buf());
+        if let Some(upcoming) = &mut self.upcoming {
+            upcoming.retain(|p| p != path);
+        }
+        // שיר מהתור שהתנגן (גם אם המשתמש בחר אותו ידנית) יוצא מהתור
+        if let Some(pos) = self.queue.iter().position(|p| p == path) {
+            self.queue.remove(pos);
+        }
+    }
+
+    pub fn queue(&self) -> &VecDeque<PathBuf> {
+        &self.queue
+    }
+
+    pub fn queue_mut(&mut self) -> &mut VecDeque<PathBuf> {
+        &mut self.queue
+    }
+
+    /// פלייליסט נמחק: הערבוב שלו נזרק, ואם הערבוב של פלייליסט שאחריו - הוא זז אחורה
+    pub fn playlist_removed(&mut self, idx: usize) {
+        match self.shuffled_for {
+            Some((id, _)) if id == idx => {
+                self.shuffled_for = None;
+                self.upcoming = None;
+            }
+            Some((id, mode)) if id > idx => self.shuffled_for = Some((id - 1, mode)),
+            _ => {}
+        }
+    }
+
+    /// פלייליסט נוסף במקום idx - מה שהיה שם ואחריו זז קדימה
+    pub fn playlist_inserted(&mut self, idx: usize) {
+        if let Some((id, mode)) = self.shuffled_for
+            && id >= idx
+        {
+            self.shuffled_for = Some((id + 1, mode));
+        }
+    }
+
+    /// השיר הראשון בתור שעדיין קיים באחד הפלייליסטים. שירים שהוסרו מאז נזרקים מהתור
+    pub fn peek_queue(&mut self, exists: impl Fn(&Path) -> bool) -> Option<PathBuf> {
+        while let Some(path) = self.queue.front() {
+            if exists(path) {
+                return Some(path.clone());
+            }
+            self.queue.pop_front();
+        }
+        None
+    }
+
+    /// האינדקס של השיר הבא בלי להתקדם אליו. manual = המשתמש לחץ "הבא"
+    /// (ב-Repeat One זה עובר שיר, וסוף שיר מנגן אותו שוב)
+    pub fn peek_next(
+        &mut self,
+        playlist_id: usize,
+        playlist: &Playlist,
+        repeat: RepeatMode,
+        shuffle: ShuffleMode,
+        manual: bool,
+        artist_of: impl Fn(&Path) -> Option<String>,
+    ) -> Option<usize> {
+        let len = playlist.items.len();
+        if len == 0 {
+            return None;
+        }
+        let current = playlist.current_index.filter(|i| *i < len);
+
+        if repeat == RepeatMode::One && !manual && current.is_some() {
+            return current;
+        }
+
+        if shuffle == ShuffleMode::Off {
+            return match current {
+                None => Some(0),
+                Some(idx) if idx + 1 < len => Some(idx + 1),
+                Some(_) if repeat != RepeatMode::Off => Some(0),
+                Some(_) => None,
+            };
+        }
+
+        if self.shuffled_for != Some((playlist_id, shuffle)) || self.upcoming.is_none() {
+            self.reshuffle(playlist, shuffle, &artist_of);
+            self.shuffled_for = Some((playlist_id, shuffle));
+        } else if playlist.items.len() != self.shuffled_len {
+            self.add_new_items(playlist);
+        }
+        if let Some(idx) = self.front_index(playlist) {
+            return Some(idx);
+        }
+        // הסבב נגמר: ב-Repeat All מערבבים סבב חדש, אחרת עוצרים
+        if repeat == RepeatMode::Off {
+            return None;
+        }
+        self.reshuffle(playlist, shuffle, &artist_of);
+        self.front_index(playlist)
+    }
+
+    /// השיר שבאמת התנגן לפני הנוכחי. None = אין היסטוריה (ואז פשוט אינדקס אחד אחורה)
+    pub fn previous(&mut self, playlist: &Playlist) -> Option<usize> {
+        while let Some(path) = self.history.pop() {
+            if let Some(idx) = playlist.items.iter().position(|p| *p == path) {
+                // בערבוב השיר שעזבנו חוזר להיות הבא בתור
+                if let Some(current) = self.current.replace(path)
+                    && let Some(upcoming) = &mut self.upcoming
+                {
+                    upcoming.push_front(current);
+                }
+                return Some(idx);
+            }
+        }
+        None
+    }
+
+    fn front_index(&mut self, playlist: &Playlist) -> Option<usize> {
+        let upcoming = self.upcoming.as_mut()?;
+        while let Some(path) = upcoming.front() {
+            if let Some(idx) = playlist.items.iter().position(|p| p == path) {
+                return Some(idx);
+            }
+            // השיר הוסר מהרשימה מאז הערבוב
+            upcoming.pop_front();
+        }
+        None
+    }
+
+    /// שירים שנוספו לרשימה אחרי הערבוב נכנסים למקום אקראי בהמשך הסבב
+    fn add_new_items(&mut self, playlist: &Playlist) {
+        self.shuffled_len = playlist.items.len();
+        let new_items: Vec<PathBuf> = playlist
+            .items
+            .iter()
+            .filter(|p| !self.shuffled.contains(*p))
+            .cloned()
+            .collect();
+        for path in new_items {
+            self.shuffled.insert(path.clone());
+            let len = self.upcoming.as_ref().map_or(0, VecDeque::len);
+            let pos = (self.next_random() % (len as u64 + 1)) as usize;
+            if let Some(upcoming) = &mut self.upcoming {
+                upcoming.insert(pos, path);
+            }
+        }
+    }
+
+    /// פרמוטציה חדשה של כל הרשימה, בלי השיר שמתנגן עכשיו
+    fn reshuffle(
+        &mut self,
+        playlist: &Playlist,
+        shuffle: ShuffleMode,
+        artist_of: &impl Fn(&Path) -> Option<String>,
+    ) {
+        let mut order: Vec<PathBuf> = playlist
+            .items
+            .iter()
+            .filter(|p| Some(*p) != self.current.as_ref())
+            .cloned()
+            .collect();
+
+        // Fisher-Yates
+        for i in (1..order.len()).rev() {
+            let j = (self.next_random() % (i as u64 + 1)) as usize;
+            order.swap(i, j);
+        }
+
+        if shuffle == ShuffleMode::Smart {
+            let artists: Vec<Option<String>> = order.iter().map(|p| artist_of(p)).collect();
+            let current_artist = self.current.as_deref().and_then(artist_of);
+            order = separate_artists(order, &artists, current_artist.as_deref());
+        }
+        self.upcoming = Some(order.into());
+        self.shuffled = playlist.items.iter().cloned().collect();
+        self.shuffled_len = playlist.items.len();
+    }
+
+    /// xorshift64 - מספיק לערבוב, ולא צריך בשביל זה עוד תלות
+    fn next_random(&mut self) -> u64 {
+        if self.rng == 0 {
+            self.rng = std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .map(|d| d.as_nanos() as u64)
+                .unwrap_or(0x9E37_79B9_7F4A_7C15)
+                | 1;
+        }
+        self.rng ^= self.rng << 13;
+        self.rng ^= self.rng >> 7;
+        self.rng ^= self.rng << 17;
+        self.rng
+    }
+}
+
+/// בונה את הסדר מחדש בלי שני שירים צמודים של אותו אמן, כשזה אפשרי. בכל צעד לוקחים
+/// את השיר הראשון (בסדר המעורבב) של אמן אחר מהקודם - אלא אם לאמן אחד נשארו יותר
+/// מחצי מהשירים, ואז חייבים לקחת ממנו עכשיו כדי שבהמשך יהיה מה לשים ביניהם.
+/// כשאין ברירה (למשל רוב הרשימה של אמן אחד) השירים נשארים צמודים
+fn separate_artists(
+    order: Vec<PathBuf>,
+    artists: &[Option<String>],
+    current_artist: Option<&str>,
+) -> Vec<PathBuf> {
+    let mut counts: HashMap<&str, usize> = HashMap::new();
+    for artist in artists.iter().flatten() {
+        *counts.entry(artist).or_default() += 1;
+    }
+
+    let mut remaining: VecDeque<usize> = (0..order.len()).collect();
+    let mut picked = Vec::with_capacity(order.len());
+    let mut previous = current_artist;
+    while !remaining.is_empty() {
+        let left = remaining.len();
+        let critical = counts
+            .iter()
+            .find(|&(_, &count)| count > left / 2)
+            .map(|(&artist, _)| artist)
+            .filter(|&artist| Some(artist) != previous);
+        let pos = match critical {
+            Some(critical) => remaining
+                .iter()
+                .position(|&i| artists[i].as_deref() == Some(critical)),
+            None => remaining.iter().position(|&i| {
+                let artist = artists[i].as_deref();
+                artist.is_none() || artist != previous
+            }),
+        };
+        let Some(i) = remaining.remove(pos.unwrap_or(0)) else {
+            break;
+        };
+        previous = artists[i].as_deref();
+        if let Some(count) = previous.and_then(|artist| counts.get_mut(artist)) {
+            *count -= 1;
+        }
+        picked.push(i);
+    }
+
+    let mut order: Vec<Option<PathBuf>> = order.into_iter().map(Some).collect();
+    picked.into_iter().filter_map(|i| order[i].take()).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn playlist(names: &[&str]) -> Playlist {
+        let mut playlist = Playlist::new("test");
+        for name in names {
+            playlist.add(PathBuf::from(name));
+        }
+        playlist
+    }
+
+    /// "artist-title" -> artist
+    fn artist_of(path: &Path) -> Option<String> {
+        let name = path.to_str()?;
+        name.split_once('-').map(|(artist, _)| artist.to_string())
+    }
+
+    fn seeded(seed: u64) -> PlayOrder {
+        PlayOrder {
+            rng: seed,
+            ..Default::default()
+        }
+    }
+
+    /// מנגן את מה ש-peek_next מחזיר עד שהוא עוצר (או עד limit שירים)
+    fn play_through(
+        order: &mut PlayOrder,
+        playlist: &mut Playlist,
+        repeat: RepeatMode,
+        shuffle: ShuffleMode,
+        limit: usize,
+    ) -> Vec<PathBuf> {
+        let mut played = Vec::new();
+        while played.len() < limit {
+            let Some(idx) = order.peek_next(0, playlist, repeat, shuffle, false, artist_of) else {
+                break;
+            };
+            let path = playlist.select(idx).unwrap();
+            order.started(&path);
+            played.push(path);
+        }
+        played
+    }
+
+    #[test]
+    fn sequential_order_follows_repeat_mode() {
+        let mut order = seeded(1);
+        let mut list = playlist(&["a", "b", "c"]);
+        let mut next = |list: &Playlist, repeat, manual| {
+            order.peek_next(0, list, repeat, ShuffleMode::Off, manual, artist_of)
+        };
+
+        list.current_index = None;
+        assert_eq!(next(&list, RepeatMode::Off, false), Some(0));
+        list.current_index = Some(1);
+        assert_eq!(next(&list, RepeatMode::Off, false), Some(2));
+        list.current_index = Some(2);
+        assert_eq!(next(&list, RepeatMode::Off, false), None);
+        assert_eq!(next(&list, RepeatMode::All, false), Some(0));
+        // Repeat One: סוף שיר מנגן אותו שוב, "הבא" עובר שיר
+        assert_eq!(next(&list, RepeatMode::One, false), Some(2));
+        assert_eq!(next(&list, RepeatMode::One, true), Some(0));
+        list.current_index = Some(0);
+        assert_eq!(next(&list, RepeatMode::One, true), Some(1));
+        assert_eq!(next(&playlist(&[]), RepeatMode::All, false), None);
+    }
+
+    #[test]
+    fn shuffle_plays_every_track_once_per_round() {
+        let names = ["a", "b", "c", "d", "e", "f", "g", "h"];
+        for shuffle in [ShuffleMode::On, ShuffleMode::Smart] {
+            let mut order = seeded(7);
+            let mut list = playlist(&names);
+            order.started(Path::new("a"));
+
+            let mut played = play_through(&mut order, &mut list, RepeatMode::Off, shuffle, 100);
+            assert_eq!(played.len(), names.len() - 1);
+            played.sort();
+            let rest: Vec<PathBuf> = names[1..].iter().map(PathBuf::from).collect();
+            assert_eq!(played, rest);
+        }
+    }
+
+    #[test]
+    fn repeat_all_starts_a_new_round() {
+        let mut order = seeded(3);
+        let mut list = playlist(&["a", "b", "c"]);
+        order.started(Path::new("a"));
+
+        let played = play_through(&mut order, &mut list, RepeatMode::All, ShuffleMode::On, 8);
+        assert_eq!(played.len(), 8);
+        // כל סבב הוא פרמוטציה של הרשימה בלי השיר שהתנגן כשהוא התחיל
+        for pair in played.windows(2) {
+            assert_ne!(pair[0], pair[1]);
+        }
+    }
+
+    #[test]
+    fn repeat_one_replays_in_shuffle() {
+        let mut order = seeded(5);
+        let list = playlist(&["a", "b", "c"]);
+        let next = order.peek_next(0, &list, RepeatMode::One, ShuffleMode::On, false, artist_of);
+        assert_eq!(next, Some(0));
+    }
+
+    #[test]
+    fn previous_walks_back_and_requeues() {
+        let mut order = seeded(11);
+        let mut list = playlist(&["a", "b", "c", "d", "e"]);
+        order.started(Path::new("a"));
+        let played = play_through(&mut order, &mut list, RepeatMode::Off, ShuffleMode::On, 2);
+
+        // חזרה לשיר הראשון בסבב, ואז "הבא" מחזיר את השיר שעזבנו
+        assert_eq!(order.previous(&list), list.items.iter().position(|p| *p == played[0]));
+        list.select(order.previous(&list).unwrap());
+        assert_eq!(list.get_current().map(PathBuf::as_path), Some(Path::new("a")));
+        let next = order.peek_next(0, &list, RepeatMode::Off, ShuffleMode::On, true, artist_of);
+        assert_eq!(next.map(|idx| &list.items[idx]), Some(&played[0]));
+        assert_eq!(order.previous(&list), None);
+    }
+
+    #[test]
+    fn previous_skips_removed_tracks() {
+        let mut order = seeded(2);
+        order.started(Path::new("a"));
+        order.started(Path::new("b"));
+        order.started(Path::new("c"));
+        let list = playlist(&["a", "c"]);
+        assert_eq!(order.previous(&list), Some(0));
+        assert_eq!(order.previous(&list), None);
+    }
+
+    #[test]
+    fn tracks_added_later_join_the_round() {
+        let mut order = seeded(13);
+        let mut list = playlist(&["a", "b", "c"]);
+        order.started(Path::new("a"));
+        let mut played = play_through(&mut order, &mut list, RepeatMode::Off, ShuffleMode::On, 1);
+
+        list.add(PathBuf::from("d"));
+        list.add(PathBuf::from("e"));
+        played.extend(play_through(&mut order, &mut list, RepeatMode::Off, ShuffleMode::On, 100));
+        played.sort();
+        let expected: Vec<PathBuf> = ["b", "c", "d", "e"].iter().map(PathBuf::from).collect();
+        assert_eq!(played, expected);
+    }
+
+    #[test]
+    fn removed_tracks_are_skipped() {
+        let mut order = seeded(17);
+        let mut list = playlist(&["a", "b", "c", "d"]);
+        order.started(Path::new("a"));
+        let mut played = play_through(&mut order, &mut list, RepeatMode::Off, ShuffleMode::On, 1);
+
+        let gone = ["b", "c", "d"]
+            .iter()
+            .map(PathBuf::from)
+            .find(|p| !played.contains(p))
+            .unwrap();
+        list.remove_where(|p| p == gone);
+        played.extend(play_through(&mut order, &mut list, RepeatMode::Off, ShuffleMode::On, 100));
+        assert_eq!(played.len(), 2);
+        assert!(!played.contains(&gone));
+    }
+
+    #[test]
+    fn playlist_indices_follow_inserts_and_removals() {
+        let mut order = seeded(19);
+        let list = playlist(&["a", "b", "c", "d"]);
+        order.peek_next(2, &list, RepeatMode::Off, ShuffleMode::On, false, artist_of);
+        let upcoming = order.upcoming.clone();
+
+        order.playlist_inserted(3);
+        assert_eq!(order.shuffled_for, Some((2, ShuffleMode::On)));
+        order.playlist_inserted(1);
+        assert_eq!(order.shuffled_for, Some((3, ShuffleMode::On)));
+        order.playlist_removed(0);
+        order.playlist_removed(4);
+        assert_eq!(order.shuffled_for, Some((2, ShuffleMode::On)));
+        // אותו פלייליסט באינדקס החדש - הסבב ממשיך ולא מתערבב מחדש
+        order.peek_next(2, &list, RepeatMode::Off, ShuffleMode::On, false, artist_of);
+        assert_eq!(order.upcoming, upcoming);
+
+        order.playlist_removed(2);
+        assert_eq!(order.shuffled_for, None);
+        assert_eq!(order.upcoming, None);
+    }
+
+    #[test]
+    fn queue_comes_first_and_drops_missing_tracks() {
+        let mut order = seeded(23);
+        order.queue_mut().extend([PathBuf::from("gone"), PathBuf::from("b")]);
+        let exists = |p: &Path| p != Path::new("gone");
+        assert_eq!(order.peek_queue(exists), Some(PathBuf::from("b")));
+        assert_eq!(order.queue().len(), 1);
+        order.started(Path::new("b"));
+        assert!(order.queue().is_empty());
+        assert_eq!(order.peek_queue(exists), None);
+    }
+
+    #[test]
+    fn smart_shuffle_keeps_artists_apart() {
+        let names = [
+            "x-1", "x-2", "x-3", "x-4", "y-1", "y-2", "y-3", "z-1", "z-2", "z-3", "w-1",
+        ];
+        for seed in 1..200 {
+            let mut order = seeded(seed);
+            let mut list = playlist(&names);
+            order.started(Path::new("x-1"));
+            let mut played = vec![PathBuf::from("x-1")];
+            played.extend(play_through(
+                &mut order,
+                &mut list,
+                RepeatMode::Off,
+                ShuffleMode::Smart,
+                100,
+            ));
+            assert_eq!(played.len(), names.len());
+            for pair in played.windows(2) {
+                assert_ne!(artist_of(&pair[0]), artist_of(&pair[1]), "seed {}: {:?}", seed, played);
+            }
+        }
+    }
+
+    #[test]
+    fn separate_artists_leaves_unknown_and_impossible_orders() {
+        let paths = |names: &[&str]| -> Vec<PathBuf> { names.iter().map(PathBuf::from).collect() };
+        let x = || Some("x".to_string());
+
+        // אמן לא ידוע לא נחשב "אותו אמן"
+        let order = separate_artists(paths(&["a", "b", "x-1"]), &[None, None, x()], None);
+        assert_eq!(order, paths(&["a", "b", "x-1"]));
+
+        // הכל של אותו אמן - אין ברירה, הסדר לא משתנה
+        let order = separate_artists(paths(&["x-1", "x-2", "x-3"]), &[x(), x(), x()], Some("x"));
+        assert_eq!(order, paths(&["x-1", "x-2", "x-3"]));
+
+        // גם השיר שמתנגן עכשיו נחשב
+        let order = separate_artists(paths(&["x-1", "y-1"]), &[x(), Some("y".into())], Some("x"));
+        assert_eq!(order, paths(&["y-1", "x-1"]));
+
+        // לאמן עם יותר מחצי מהשירים שומרים מקום מההתחלה
+        let names = ["y-1", "x-1", "x-2", "x-3"];
+        let artists: Vec<Option<String>> = names.iter().map(|n| artist_of(Path::new(n))).collect();
+        let order = separate_artists(paths(&names[..]), &artists[..], None);
+        assert_eq!(order, paths(&["x-1", "y-1", "x-2", "x-3"]));
+    }
+}
@@ -1,18 +1,29 @@
+use crate::smart_playlist::SmartRules;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Playlist {
-    pub items: Vec<String>,
+    pub name: String,
+    pub items: Vec<PathBuf>,
     pub current_index: Option<usize>,
+    // פלייליסט חכם: השירים נבנים מחדש מהספרייה לפי הכללים בכל פעם שהיא משתנה
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rules: Option<SmartRules>,
 }
 
 impl Playlist {
-    pub fn new() -> Self {
+    pub fn new(name: impl Into<String>) -> Self {
         Self {
+            name: name.into(),
             items: Vec::new(),
             current_index: None,
+            rules: None,
         }
     }
 
     /// הוספת שיר לרשימה
-    pub fn add(&mut self, path: String) {
+    pub fn add(&mut self, path: PathBuf) {
         self.items.push(path);
         // אם זה השיר הראשון, נגדיר אותו כנוכחי
         if self.current_index.is_none() {
@@ -20,35 +31,28 @@ impl Playlist {
         }
     }
 
-    /// קבלת הנתיב של השיר הנוכחי
-    pub fn get_current(&self) -> Option<&String> {
-        self.current_index.and_then(|idx| self.items.get(idx))
+    pub fn contains(&self, path: &Path) -> bool {
+        self.items.iter().any(|p| p == path)
     }
 
-    /// מעבר לשיר הבא (מחזיר את הנתיב אם קיים)
-    pub fn next(&mut self) -> Option<String> {
-        if let Some(idx) = self.current_index {
-            if idx + 1 < self.items.len() {
-                self.current_index = Some(idx + 1);
-                return self.get_current().cloned();
-            }
-        }
-        None
+    /// קבלת הנתיב של השיר הנוכחי
+    pub fn get_current(&self) -> Option<&PathBuf> {
+        self.current_index.and_then(|idx| self.items.get(idx))
     }
 
     /// מעבר לשיר הקודם
-    pub fn previous(&mut self) -> Option<String> {
-        if let Some(idx) = self.current_index {
-            if idx > 0 {
-                self.current_index = Some(idx - 1);
-                return self.get_current().cloned();
-            }
+    pub fn previous(&mut self) -> Option<PathBuf> {
+        if let Some(idx) = self.current_index
+            && idx > 0
+        {
+            self.current_index = Some(idx - 1);
+            return self.get_current().cloned();
         }
         None
     }
 
     /// בחירת שיר ספציפי לפי אינדקס
-    pub fn select(&mut self, index: usize) -> Option<String> {
+    pub fn select(&mut self, index: usize) -> Option<PathBuf> {
         if index < self.items.len() {
             self.current_index = Some(index);
             return self.get_current().cloned();
@@ -56,18 +60,232 @@ impl Playlist {
         None
     }
 
+    /// מחליף את כל השירים (פלייליסט חכם שחושב מחדש). השיר הנוכחי נשמר אם הוא עדיין ברשימה
+    pub fn replace_items(&mut self, items: Vec<PathBuf>) {
+        let current = self.get_current().cloned();
+        self.items = items;
+        self.current_index = current.and_then(|path| self.items.iter().position(|p| *p == path));
+    }
+
+    /// מעביר את השירים מ-start ועד הסוף כך שיתחילו ב-at (למשל קבצים שנגררו לאמצע הרשימה)
+    pub fn move_tail_to(&mut self, start: usize, at: usize) {
+        if at >= start || start >= self.items.len() {
+            return;
+        }
+        let count = self.items.len() - start;
+        self.items[at..].rotate_right(count);
+        if let Some(curr) = self.current_index {
+            if curr >= start {
+                self.current_index = Some(at + (curr - start));
+            } else if curr >= at {
+                self.current_index = Some(curr + count);
+            }
+        }
+    }
+
+    /// מעביר קבוצת שירים (לא בהכרח רצופה) כך שיתחילו לפני השיר at, בסדר המקורי שלהם.
+    /// השיר הנוכחי נשאר נוכחי גם אם הוא זז
+    pub fn move_indices(&mut self, indices: &[usize], at: usize) {
+        let len = self.items.len();
+        let mut is_moving = vec![false; len];
+        for &idx in indices.iter().filter(|&&idx| idx < len) {
+            is_moving[idx] = true;
+        }
+        let (moving, mut order): (Vec<usize>, Vec<usize>) = (0..len).partition(|&i| is_moving[i]);
+        if moving.is_empty() {
+            return;
+        }
+        let at = at.min(len);
+        let insert_at = at - moving.iter().filter(|&&i| i < at).count();
+        order.splice(insert_at..insert_at, moving);
+
+        let mut old: Vec<Option<PathBuf>> = std::mem::take(&mut self.items)
+            .into_iter()
+            .map(Some)
+            .collect();
+        self.items = order.iter().filter_map(|&i| old[i].take()).collect();
+        self.current_index = self
+            .current_index
+            .and_then(|curr| order.iter().position(|&i| i == curr));
+    }
+
+    /// הסרה של כמה שירים בבת אחת
+    pub fn remove_indices(&mut self, indices: &[usize]) {
+        let len = self.items.len();
+        let mut removing = vec![false; len];
+        for &idx in indices.iter().filter(|&&idx| idx < len) {
+            removing[idx] = true;
+        }
+        self.retain_by(|idx, _| !removing[idx]);
+    }
+
     /// הסרת שיר מהרשימה
     pub fn remove(&mut self, index: usize) {
         if index < self.items.len() {
             self.items.remove(index);
-            // תיקון האינדקס הנוכחי אם צריך
+            // תיקון האינדקס הנוכחי: שיר שהוסר לפניו מזיז אותו אחורה
             if let Some(curr) = self.current_index {
-                if curr >= self.items.len() && !self.items.is_empty() {
-                    self.current_index = Some(self.items.len() - 1);
-                } else if self.items.is_empty() {
+                if self.items.is_empty() {
                     self.current_index = None;
+                } else if index < curr || curr >= self.items.len() {
+                    self.current_index = Some(curr.saturating_sub(1));
                 }
             }
         }
     }
-}
\ No newline at end of file
+
+    /// מסיר כל שיר שעונה על התנאי (למשל קבצים שנמחקו מהדיסק)
+    pub fn remove_where(&mut self, should_remove: impl Fn(&Path) -> bool) {
+        self.retain_by(|_, path| !should_remove(path));
+    }
+
+    /// מעבר אחד על הרשימה (ולא remove לכל שיר). השיר הנוכחי נשאר, ואם הוא הוסר -
+    /// השיר שאחריו הופך לנוכחי, בדיוק כמו ב-remove
+    fn retain_by(&mut self, mut keep: impl FnMut(usize, &Path) -> bool) {
+        let current = self.current_index;
+        let mut kept_before_current = 0;
+        let mut idx = 0;
+        self.items.retain(|path| {
+            let kept = keep(idx, path);
+            if kept && current.is_some_and(|curr| idx < curr) {
+                kept_before_current += 1;
+            }
+            idx += 1;
+            kept
+        });
+        self.current_index = current
+            .filter(|_| !self.items.is_empty())
+            .map(|_| kept_before_current.min(self.items.len() - 1));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn playlist(names: &str, current: Option<usize>) -> Playlist {
+        let mut playlist = Playlist::new("test");
+        playlist.items = names.chars().map(|c| PathBuf::from(c.to_string())).collect();
+        playlist.current_index = current;
+        playlist
+    }
+
+    /// השירים כמחרוזת אחת ("abc") - קל להשוות
+    fn names(playlist: &Playlist) -> String {
+        playlist.items.iter().map(|p| p.to_string_lossy()).collect()
+    }
+
+    fn current(playlist: &Playlist) -> Option<String> {
+        playlist.get_current().map(|p| p.to_string_lossy().to_string())
+    }
+
+    #[test]
+    fn move_block_down_and_up() {
+        let mut list = playlist("abcdef", Some(1));
+        list.move_indices(&[1, 2], 5);
+        assert_eq!(names(&list), "adebcf");
+        assert_eq!(current(&list).as_deref(), Some("b"));
+
+        list.move_indices(&[3, 4], 0);
+        assert_eq!(names(&list), "bcadef");
+        assert_eq!(list.current_index, Some(0));
+
+        // at מעבר לסוף - לסוף הרשימה
+        list.move_indices(&[0], 100);
+        assert_eq!(names(&list), "cadefb");
+    }
+
+    #[test]
+    fn move_non_contiguous_selection_keeps_its_order() {
+        let mut list = playlist("abcdef", Some(3));
+        list.move_indices(&[4, 0, 2], 2);
+        assert_eq!(names(&list), "bacedf");
+        assert_eq!(current(&list).as_deref(), Some("d"));
+
+        let mut list = playlist("abcdef", Some(4));
+        list.move_indices(&[1, 4], 6);
+        assert_eq!(names(&list), "acdfbe");
+        assert_eq!(current(&list).as_deref(), Some("e"));
+    }
+
+    #[test]
+    fn move_onto_the_block_itself() {
+        // שחרור בתוך הקבוצה שנגררת לא משנה כלום
+        let mut list = playlist("abcdef", Some(2));
+        list.move_indices(&[1, 2, 3], 2);
+        assert_eq!(names(&list), "abcdef");
+        list.move_indices(&[1, 2, 3], 4);
+        assert_eq!(names(&list), "abcdef");
+        assert_eq!(list.current_index, Some(2));
+
+        // קבוצה לא רצופה שמשוחררת על אחד מהשירים שלה מתאחדת סביבו
+        list.move_indices(&[1, 3], 3);
+        assert_eq!(names(&list), "acbdef");
+    }
+
+    #[test]
+    fn move_ignores_empty_and_invalid_input() {
+        let mut list = playlist("abc", Some(1));
+        list.move_indices(&[], 0);
+        list.move_indices(&[7], 0);
+        assert_eq!(names(&list), "abc");
+        assert_eq!(list.current_index, Some(1));
+
+        let mut empty = playlist("", None);
+        empty.move_indices(&[0], 0);
+        empty.remove_indices(&[0]);
+        assert!(empty.items.is_empty());
+        assert_eq!(empty.current_index, None);
+    }
+
+    #[test]
+    fn remove_indices_fixes_current() {
+        let mut list = playlist("abcdef", Some(3));
+        list.remove_indices(&[0, 5, 2, 9]);
+        assert_eq!(names(&list), "bde");
+        assert_eq!(current(&list).as_deref(), Some("d"));
+
+        // השיר הנוכחי הוסר - השיר שאחריו הופך לנוכחי
+        list.remove_indices(&[1]);
+        assert_eq!(names(&list), "be");
+        assert_eq!(current(&list).as_deref(), Some("e"));
+
+        // הוסר השיר האחרון והוא היה הנוכחי - נשארים על מה שלפניו
+        list.remove_indices(&[1]);
+        assert_eq!(current(&list).as_deref(), Some("b"));
+
+        list.remove_indices(&[0, 0]);
+        assert!(list.items.is_empty());
+        assert_eq!(list.current_index, None);
+    }
+
+    #[test]
+    fn remove_where_matches_remove() {
+        let mut bulk = playlist("abcdef", Some(2));
+        let mut single = playlist("abcdef", Some(2));
+        bulk.remove_where(|p| p == Path::new("c"));
+        single.remove(2);
+        assert_eq!(names(&bulk), names(&single));
+        assert_eq!(bulk.current_index, single.current_index);
+    }
+
+    #[test]
+    fn move_tail_to_inserts_dropped_tracks() {
+        let mut list = playlist("abcdXY", Some(4));
+        list.move_tail_to(4, 1);
+        assert_eq!(names(&list), "aXYbcd");
+        assert_eq!(current(&list).as_deref(), Some("X"));
+
+        let mut list = playlist("abcdXY", Some(2));
+        list.move_tail_to(4, 1);
+        assert_eq!(current(&list).as_deref(), Some("c"));
+        list.move_tail_to(4, 0);
+        assert_eq!(names(&list), "cdaXYb");
+
+        // בלי שירים חדשים, או מקום אחרי הסוף - כלום לא זז
+        let mut list = playlist("abc", Some(0));
+        list.move_tail_to(3, 0);
+        list.move_tail_to(1, 2);
+        assert_eq!(names(&list), "abc");
+    }
+}
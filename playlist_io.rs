@@ -0,0 +1,617 @@
+use crate::playlist::Playlist;
+use gstreamer::glib;
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+
+/// הסיומות שאפשר לייבא ולייצא (לדיאלוגים של הקבצים)
+pub const PLAYLIST_EXTENSIONS: [&str; 4] = ["m3u", "m3u8", "pls", "xspf"];
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum PlaylistFormat {
+    M3u, // גם M3U8 - אותו פורמט, פשוט תמיד UTF-8
+    Pls,
+    Xspf,
+}
+
+impl PlaylistFormat {
+    pub fn from_path(path: &Path) -> Option<Self> {
+        let ext = path.extension()?.to_string_lossy().to_lowercase();
+        match ext.as_str() {
+            "m3u" | "m3u8" => Some(Self::M3u),
+            "pls" => Some(Self::Pls),
+            "xspf" => Some(Self::Xspf),
+            _ => None,
+        }
+    }
+}
+
+/// מה שנכתב על כל שיר מעבר לנתיב (#EXTINF, Title ב-PLS, <title> ב-XSPF)
+#[derive(Default, Debug, Clone, PartialEq)]
+pub struct EntryInfo {
+    pub title: Option<String>,
+    pub artist: Option<String>,
+    pub album: Option<String>,
+    pub duration_secs: Option<f64>,
+}
+
+// =========================================================
+// ייבוא
+// =========================================================
+
+/// קורא קובץ פלייליסט. נתיבים יחסיים נפתרים ביחס לתיקייה של הקובץ.
+/// מחזיר גם את מה שהקובץ סיפר על כל שיר, באותו סדר כמו playlist.items
+pub fn import(path: &Path) -> Result<(Playlist, Vec<EntryInfo>), String> {
+    let format = PlaylistFormat::from_path(path).ok_or("Unknown playlist format")?;
+    let bytes = fs::read(path).map_err(|e| e.to_string())?;
+    // M3U ישן יכול להיות ב-Latin-1 - עדיף תו שבור מאשר כישלון של כל הקובץ
+    let content = String::from_utf8_lossy(&bytes);
+    let content = content.trim_start_matches('\u{feff}');
+    let base = path.parent().unwrap_or(Path::new("."));
+
+    let entries = match format {
+        PlaylistFormat::M3u => parse_m3u(content),
+        PlaylistFormat::Pls => parse_pls(content),
+        PlaylistFormat::Xspf => parse_xspf(content),
+    };
+
+    let name = path
+        .file_stem()
+        .map(|s| s.to_string_lossy().to_string())
+        .unwrap_or_else(|| "Imported".to_string());
+    let mut playlist = Playlist::new(name);
+    let mut infos = Vec::new();
+    for (location, info) in entries {
+        if let Some(track) = resolve_location(&location, base, format) {
+            playlist.add(track);
+            infos.push(info);
+        }
+    }
+    Ok((playlist, infos))
+}
+
+fn parse_m3u(content: &str) -> Vec<(String, EntryInfo)> {
+    // #EXTINF שייך לשורת הקובץ שאחריו. שאר ההערות מדולגות
+    let mut entries = Vec::new();
+    let mut pending = EntryInfo::default();
+    for line in content.lines().map(str::trim) {
+        if let Some(extinf) = line.strip_prefix("#EXTINF:") {
+            pending = parse_extinf(extinf);
+        } else if !line.is_empty() && !line.starts_with('#') {
+            entries.push((line.to_string(), std::mem::take(&mut pending)));
+        }
+    }
+    entries
+}
+
+/// האורך עד הרווח הראשון (-1 = לא ידוע), ואחרי הפסיק "Artist - Title".
+/// בין השניים יכולים להיות מאפיינים (tvg-id="..." וכו') - הם לא מעניינים אותנו
+fn parse_extinf(extinf: &str) -> EntryInfo {
+    let (head, name) = extinf.split_once(',').unwrap_or((extinf, ""));
+    let mut info = split_display_name(name);
+    info.duration_secs = head.split_whitespace().next().and_then(parse_duration);
+    info
+}
+
+fn parse_pls(content: &str) -> Vec<(String, EntryInfo)> {
+    // FileN, TitleN ו-LengthN יכולים להופיע בכל סדר - מקבצים לפי המספר
+    let mut entries: BTreeMap<u32, (Option<String>, EntryInfo)> = BTreeMap::new();
+    for line in content.lines() {
+        let Some((key, value)) = line.trim().split_once('=') else {
+            continue;
+        };
+        let (key, value) = (key.trim(), value.trim());
+        let number = |prefix: &str| key.strip_prefix(prefix)?.parse::<u32>().ok();
+        if let Some(n) = number("File") {
+            entries.entry(n).or_default().0 = Some(value.to_string());
+        } else if let Some(n) = number("Title") {
+            let parsed = split_display_name(value);
+            let info = &mut entries.entry(n).or_default().1;
+            info.title = parsed.title;
+            info.artist = parsed.artist;
+        } else if let Some(n) = number("Length") {
+            entries.entry(n).or_default().1.duration_secs = parse_duration(value);
+        }
+    }
+    entries
+        .into_values()
+        .filter_map(|(file, info)| Some((file?, info)))
+        .collect()
+}
+
+fn parse_duration(secs: &str) -> Option<f64> {
+    secs.parse::<f64>().ok().filter(|secs| *secs >= 0.0)
+}
+
+/// ההפך של display_name: "Artist - Title" -> אמן ושם. בלי " - " הכל הוא השם
+fn split_display_name(name: &str) -> EntryInfo {
+    let name = name.trim();
+    let (artist, title) = match name.split_once(" - ") {
+        Some((artist, title)) => (Some(artist.trim()), title.trim()),
+        None => (None, name),
+    };
+    EntryInfo {
+        title: (!title.is_empty()).then(|| title.to_string()),
+        artist: artist.filter(|a| !a.is_empty()).map(str::to_string),
+        ..Default::default()
+    }
+}
+
+fn parse_xspf(content: &str) -> Vec<(String, EntryInfo)> {
+    // סריקה של תגיות בלי Parser מלא של XML. מיקום יחסי נפתר מול ה-xml:base
+    // של השיר או של הפלייליסט, אם יש
+    let playlist_base = next_element(content, "playlist")
+        .and_then(|(attrs, _, _)| xml_attribute(attrs, "xml:base"));
+    let mut entries = Vec::new();
+    let mut rest = content;
+    while let Some((attrs, track, after)) = next_element(rest, "track") {
+        rest = after;
+        let Some(location) = element_text(track, "location") else {
+            continue;
+        };
+        let base = match (xml_attribute(attrs, "xml:base"), &playlist_base) {
+            (Some(base), Some(outer)) => Some(join_uri(outer, &base)),
+            (base, outer) => base.or_else(|| outer.clone()),
+        };
+        let info = EntryInfo {
+            title: element_text(track, "title"),
+            artist: element_text(track, "creator"),
+            album: element_text(track, "album"),
+            // ב-XSPF האורך במילישניות
+            duration_secs: element_text(track, "duration")
+                .and_then(|ms| parse_duration(&ms))
+                .map(|ms| ms / 1000.0),
+        };
+        let location = match base {
+            Some(base) => join_uri(&base, &location),
+            None => location,
+        };
+        entries.push((location, info));
+    }
+    entries
+}
+
+/// האלמנט הבא בשם tag: (המאפיינים, התוכן, מה שאחריו). מזהה גם <tag attr="..."> ו-<tag/>,
+/// ולא מתבלבל עם תגיות שרק מתחילות באותו שם (<trackList>)
+fn next_element<'a>(xml: &'a str, tag: &str) -> Option<(&'a str, &'a str, &'a str)> {
+    let open = format!("<{}", tag);
+    let mut from = 0;
+    let (name_end, tag_end) = loop {
+        let name_end = from + xml[from..].find(&open)? + open.len();
+        if xml[name_end..].starts_with(|c: char| c == '>' || c == '/' || c.is_whitespace()) {
+            break (name_end, name_end + xml[name_end..].find('>')?);
+        }
+        from = name_end;
+    };
+    if xml[..tag_end].ends_with('/') {
+        return Some((&xml[name_end..tag_end - 1], "", &xml[tag_end + 1..]));
+    }
+
+    let body = &xml[tag_end + 1..];
+    let close = format!("</{}", tag);
+    let end = body.find(&close).unwrap_or(body.len());
+    let after = body[end..].find('>').map_or("", |i| &body[end + i + 1..]);
+    Some((&xml[name_end..tag_end], &body[..end], after))
+}
+
+fn element_text(xml: &str, tag: &str) -> Option<String> {
+    let (_, text, _) = next_element(xml, tag)?;
+    let text = text.trim();
+    let text = match text.strip_prefix("<![CDATA[").and_then(|t| t.strip_suffix("]]>")) {
+        Some(raw) => raw.to_string(),
+        None => xml_unescape(text),
+    };
+    (!text.is_empty()).then_some(text)
+}
+
+/// הערך של מאפיין בתגית פתיחה (במרכאות כפולות או בודדות)
+fn xml_attribute(attrs: &str, name: &str) -> Option<String> {
+    let mut rest = attrs;
+    while let Some(eq) = rest.find('=') {
+        let key = rest[..eq].trim();
+        let value = rest[eq + 1..].trim_start();
+        let quote = value.chars().next().filter(|c| *c == '"' || *c == '\'')?;
+        let end = 1 + value[1..].find(quote)?;
+        if key == name {
+            return Some(xml_unescape(&value[1..end]));
+        }
+        rest = &value[end + 1..];
+    }
+    None
+}
+
+/// מיקום יחסי ביחס ל-URI בסיס: החלק האחרון של הבסיס מוחלף, כמו בדפדפן.
+/// מיקום שהוא כבר URI מלא או נתיב מוחלט לא משתנה
+fn join_uri(base: &str, location: &str) -> String {
+    if location.contains("://") || location.starts_with('/') {
+        return location.to_string();
+    }
+    let dir = &base[..base.rfind('/').map_or(0, |i| i + 1)];
+    format!("{}{}", dir, location.trim_start_matches("./"))
+}
+
+/// שורה מהפלייליסט -> נתיב מלא. URI (file://) או נתיב, מוחלט או יחסי
+fn resolve_location(location: &str, base: &Path, format: PlaylistFormat) -> Option<PathBuf> {
+    if let Some(rest) = location.strip_prefix("file://") {
+        if let Ok((path, _)) = glib::filename_from_uri(location) {
+            return Some(path);
+        }
+        // URI לא תקני (רווחים, "file://C:/...") - מפענחים את האחוזים בעצמנו
+        let rest = rest.strip_prefix("localhost").unwrap_or(rest);
+        let decoded = glib::Uri::unescape_string(rest, None::<&str>)?;
+        return Some(PathBuf::from(decoded.as_str()));
+    }
+    if location.contains("://") {
+        return None; // סטרימים מהרשת לא נכנסים לפלייליסט של קבצים
+    }
+
+    // ב-XSPF גם נתיב יחסי הוא URI, כלומר עם קידוד אחוזים (%20)
+    let decoded = match format {
+        PlaylistFormat::Xspf => glib::Uri::unescape_string(location, None::<&str>)
+            .map(|s| s.to_string())
+            .unwrap_or_else(|| location.to_string()),
+        _ => location.to_string(),
+    };
+    let mut path = PathBuf::from(&decoded);
+    // פלייליסט שנוצר ב-Windows
+    if !path.is_absolute() && decoded.contains('\\') && !base.join(&path).exists() {
+        path = PathBuf::from(decoded.replace('\\', "/"));
+    }
+    if path.is_absolute() {
+        Some(path)
+    } else {
+        Some(normalize(&base.join(path)))
+    }
+}
+
+/// מוריד "." ו-".." בלי לגשת לדיסק (הקובץ אולי בכלל לא קיים כרגע)
+fn normalize(path: &Path) -> PathBuf {
+    let mut result = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                result.pop();
+            }
+            other => result.push(other.as_os_str()),
+        }
+    }
+    result
+}
+
+// =========================================================
+// ייצוא
+// =========================================================
+
+/// כותב את הפלייליסט בפורמט לפי הסיומת. שירים שנמצאים מתחת לתיקייה של הקובץ נכתבים
+/// בנתיב יחסי, כדי שאפשר יהיה להעביר את כל התיקייה למחשב אחר
+pub fn export(
+    playlist: &Playlist,
+    path: &Path,
+    describe: impl Fn(&Path) -> EntryInfo,
+) -> Result<(), String> {
+    let format = PlaylistFormat::from_path(path).ok_or("Unknown playlist format")?;
+    let base = path.parent().unwrap_or(Path::new("."));
+    let entries: Vec<(String, EntryInfo)> = playlist
+        .items
+        .iter()
+        .map(|track| (relative_location(track, base), describe(track)))
+        .collect();
+
+    let content = match format {
+        PlaylistFormat::M3u => write_m3u(&entries),
+        PlaylistFormat::Pls => write_pls(&entries),
+        PlaylistFormat::Xspf => write_xspf(&playlist.name, &entries),
+    };
+    fs::write(path, content).map_err(|e| e.to_string())
+}
+
+fn write_m3u(entries: &[(String, EntryInfo)]) -> String {
+    let mut out = String::from("#EXTM3U\n");
+    for (location, info) in entries {
+        let secs = info.duration_secs.map(|d| d.round() as i64).unwrap_or(-1);
+        let _ = writeln!(out, "#EXTINF:{},{}", secs, display_name(location, info));
+        let _ = writeln!(out, "{}", location);
+    }
+    out
+}
+
+fn write_pls(entries: &[(String, EntryInfo)]) -> String {
+    let mut out = String::from("[playlist]\n");
+    for (idx, (location, info)) in entries.iter().enumerate() {
+        let n = idx + 1;
+        let secs = info.duration_secs.map(|d| d.round() as i64).unwrap_or(-1);
+        let _ = writeln!(out, "File{}={}", n, location);
+        let _ = writeln!(out, "Title{}={}", n, display_name(location, info));
+        let _ = writeln!(out, "Length{}={}", n, secs);
+    }
+    let _ = writeln!(out, "NumberOfEntries={}", entries.len());
+    let _ = writeln!(out, "Version=2");
+    out
+}
+
+fn write_xspf(name: &str, entries: &[(String, EntryInfo)]) -> String {
+    let mut out = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    out.push_str("<playlist version=\"1\" xmlns=\"http://xspf.org/ns/0/\">\n");
+    let _ = writeln!(out, "  <title>{}</title>", xml_escape(name));
+    out.push_str("  <trackList>\n");
+    for (location, info) in entries {
+        out.push_str("    <track>\n");
+        // ב-XSPF המיקום הוא URI - גם כשהוא יחסי
+        let uri = if Path::new(location).is_absolute() {
+            glib::filename_to_uri(location, None)
+                .map(|u| u.to_string())
+                .unwrap_or_else(|_| location.clone())
+        } else {
+            glib::Uri::escape_string(location, Some("/"), true).to_string()
+        };
+        let _ = writeln!(out, "      <location>{}</location>", xml_escape(&uri));
+        if let Some(title) = &info.title {
+            let _ = writeln!(out, "      <title>{}</title>", xml_escape(title));
+        }
+        if let Some(artist) = &info.artist {
+            let _ = writeln!(out, "      <creator>{}</creator>", xml_escape(artist));
+        }
+        if let Some(album) = &info.album {
+            let _ = writeln!(out, "      <album>{}</album>", xml_escape(album));
+        }
+        if let Some(secs) = info.duration_secs {
+            // ב-XSPF האורך במילישניות
+            let _ = writeln!(out, "      <duration>{}</duration>", (secs * 1000.0).round() as u64);
+        }
+        out.push_str("    </track>\n");
+    }
+    out.push_str("  </trackList>\n</playlist>\n");
+    out
+}
+
+/// "Artist - Title" כמו שנגנים אחרים מציגים, ואם אין תגיות - שם הקובץ
+fn display_name(location: &str, info: &EntryInfo) -> String {
+    let title = info.title.clone().unwrap_or_else(|| {
+        Path::new(location)
+            .file_stem()
+            .unwrap_or_default()
+            .to_string_lossy()
+            .to_string()
+    });
+    match &info.artist {
+        Some(artist) => format!("{} - {}", artist, title),
+        None => title,
+    }
+}
+
+/// נתיב יחסי לתיקייה של הפלייליסט אם השיר בתוכה (או בתת-תיקייה), אחרת נתיב מלא
+fn relative_location(track: &Path, base: &Path) -> String {
+    match track.strip_prefix(base) {
+        Ok(relative) if !base.as_os_str().is_empty() => relative.to_string_lossy().to_string(),
+        _ => track.to_string_lossy().to_string(),
+    }
+}
+
+fn xml_escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+/// הישויות הקבועות של XML ותווים מספריים (&#233; / &#xE9;). "&" שלא פותח ישות נשאר כמו שהוא
+fn xml_unescape(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(amp) = rest.find('&') {
+        out.push_str(&rest[..amp]);
+        rest = &rest[amp..];
+        let entity = rest.find(';').filter(|end| *end <= 10).and_then(|end| {
+            let ch = match &rest[1..end] {
+                "lt" => '<',
+                "gt" => '>',
+                "quot" => '"',
+                "apos" => '\'',
+                "amp" => '&',
+                numeric => {
+                    let numeric = numeric.strip_prefix('#')?;
+                    let code = match numeric.strip_prefix(['x', 'X']) {
+                        Some(hex) => u32::from_str_radix(hex, 16).ok()?,
+                        None => numeric.parse().ok()?,
+                    };
+                    char::from_u32(code)?
+                }
+            };
+            Some((ch, end + 1))
+        });
+        match entity {
+            Some((ch, len)) => {
+                out.push(ch);
+                rest = &rest[len..];
+            }
+            None => {
+                out.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// תיקייה זמנית לכל בדיקה, נמחקת בסוף
+    struct TempDir(PathBuf);
+
+    impl TempDir {
+        fn new(name: &str) -> Self {
+            let dir = std::env::temp_dir()
+                .join(format!("audiobass-playlist-io-{}-{}", std::process::id(), name));
+            let _ = fs::remove_dir_all(&dir);
+            fs::create_dir_all(&dir).unwrap();
+            Self(dir)
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    fn info(
+        title: &str,
+        artist: Option<&str>,
+        album: Option<&str>,
+        secs: Option<f64>,
+    ) -> EntryInfo {
+        EntryInfo {
+            title: Some(title.to_string()),
+            artist: artist.map(str::to_string),
+            album: album.map(str::to_string),
+            duration_secs: secs,
+        }
+    }
+
+    /// שיר ליד הפלייליסט, שיר בעברית עם תווים מיוחדים בתת-תיקייה, ושיר מחוץ לתיקייה
+    fn sample_tracks(dir: &Path) -> Vec<(PathBuf, EntryInfo)> {
+        vec![
+            (dir.join("01 Intro.mp3"), info("Intro", Some("Band"), Some("First"), Some(61.0))),
+            (
+                dir.join("אלבום שני/02 שיר & \"ציטוט\" 100%.flac"),
+                info("שיר & \"ציטוט\"", Some("זמרת"), Some("אלבום שני"), Some(245.0)),
+            ),
+            (PathBuf::from("/elsewhere/Café <live> #1.ogg"), info("Café <live>", None, None, None)),
+        ]
+    }
+
+    /// מייצא, בודק שהשירים שבתוך התיקייה נכתבו יחסית, ומייבא בחזרה
+    fn round_trip(file_name: &str) -> (Vec<(PathBuf, EntryInfo)>, Playlist, Vec<EntryInfo>) {
+        let dir = TempDir::new(file_name);
+        let tracks = sample_tracks(&dir.0);
+        let mut playlist = Playlist::new("Mix");
+        for (track, _) in &tracks {
+            playlist.add(track.clone());
+        }
+
+        let path = dir.0.join(file_name);
+        let describe = |track: &Path| {
+            let found = tracks.iter().find(|(t, _)| t == track);
+            found.map(|(_, info)| info.clone()).unwrap_or_default()
+        };
+        export(&playlist, &path, describe).unwrap();
+
+        let written = fs::read_to_string(&path).unwrap();
+        assert!(!written.contains(&*dir.0.to_string_lossy()), "{}", written);
+        assert!(written.contains("elsewhere"));
+
+        let (imported, entries) = import(&path).unwrap();
+        (tracks, imported, entries)
+    }
+
+    fn assert_round_trip(file_name: &str, keeps_album: bool) {
+        let (tracks, imported, entries) = round_trip(file_name);
+        let expected_items: Vec<PathBuf> = tracks.iter().map(|(t, _)| t.clone()).collect();
+        assert_eq!(imported.items, expected_items);
+        assert_eq!(entries.len(), tracks.len());
+        for ((_, expected), actual) in tracks.iter().zip(&entries) {
+            let mut expected = expected.clone();
+            if !keeps_album {
+                expected.album = None;
+            }
+            assert_eq!(actual, &expected);
+        }
+    }
+
+    #[test]
+    fn m3u_round_trip() {
+        assert_round_trip("mix.m3u", false);
+    }
+
+    #[test]
+    fn m3u8_round_trip() {
+        assert_round_trip("mix.m3u8", false);
+    }
+
+    #[test]
+    fn pls_round_trip() {
+        assert_round_trip("mix.pls", false);
+    }
+
+    #[test]
+    fn xspf_round_trip() {
+        assert_round_trip("mix.xspf", true);
+    }
+
+    #[test]
+    fn m3u_keeps_extinf() {
+        let content = "#EXTM3U\n\
+            #EXTINF:183 tvg-id=\"x\",Artist - Song - Live\n\
+            a.mp3\n\
+            #EXTINF:-1,Untitled\n\
+            # הערה\n\
+            b.mp3\n\
+            c.mp3\n";
+        let entries = parse_m3u(content);
+        let locations: Vec<&str> = entries.iter().map(|(l, _)| l.as_str()).collect();
+        assert_eq!(locations, ["a.mp3", "b.mp3", "c.mp3"]);
+        assert_eq!(entries[0].1, info("Song - Live", Some("Artist"), None, Some(183.0)));
+        assert_eq!(entries[1].1, info("Untitled", None, None, None));
+        assert_eq!(entries[2].1, EntryInfo::default());
+    }
+
+    #[test]
+    fn pls_entries_in_any_order() {
+        let content = "[playlist]\nTitle2=Second\nFile2=b.mp3\nLength2=90\nFile1=a.mp3\n";
+        let entries = parse_pls(content);
+        assert_eq!(entries[0], ("a.mp3".to_string(), EntryInfo::default()));
+        assert_eq!(entries[1], ("b.mp3".to_string(), info("Second", None, None, Some(90.0))));
+    }
+
+    #[test]
+    fn xspf_tag_scan() {
+        let content = r#"<?xml version="1.0" encoding="UTF-8"?>
+<playlist version="1" xmlns="http://xspf.org/ns/0/" xml:base="file:///music/">
+  <trackList>
+    <track xml:base="Album%20One/">
+      <location>01%20caf%C3%A9.flac</location>
+      <title>Caf&#233; &amp; Bar &#x263A;</title>
+    </track>
+    <track >
+      <location>
+        file:///other/%D7%A9%D7%99%D7%A8.mp3
+      </location>
+      <creator>Tom &apos;n&apos; Jerry</creator>
+      <duration>61500</duration>
+    </track>
+    <track/>
+    <track><title>No location</title></track>
+    <track><location>relative.ogg</location></track>
+  </trackList>
+</playlist>
+"#;
+        let base = Path::new("/lists");
+        let entries = parse_xspf(content);
+        let paths: Vec<PathBuf> = entries
+            .iter()
+            .filter_map(|(location, _)| resolve_location(location, base, PlaylistFormat::Xspf))
+            .collect();
+        assert_eq!(
+            paths,
+            [
+                PathBuf::from("/music/Album One/01 café.flac"),
+                PathBuf::from("/other/שיר.mp3"),
+                PathBuf::from("/music/relative.ogg"),
+            ]
+        );
+        assert_eq!(entries[0].1.title.as_deref(), Some("Café & Bar ☺"));
+        assert_eq!(entries[1].1.artist.as_deref(), Some("Tom 'n' Jerry"));
+        assert_eq!(entries[1].1.duration_secs, Some(61.5));
+    }
+
+    #[test]
+    fn xml_unescape_leaves_stray_ampersands() {
+        assert_eq!(xml_unescape("R&B &amp; &#65;&#x42; &bogus; &#xZZ;"), "R&B & AB &bogus; &#xZZ;");
+    }
+}
@@ -0,0 +1,1063 @@
+use crate::library_db::file_signature;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::thread::{self, JoinHandle};
+
+/// ה-email שרוב הנגנים קוראים ממנו את הדירוג ב-POPM, עם הסולם שלו (0-255)
+const POPM_EMAIL: &[u8] = b"Windows Media Player 9 Series";
+const POPM_STARS: [u8; 6] = [0, 1, 64, 128, 196, 255];
+
+const FMPS_KEY: &str = "FMPS_RATING";
+const VENDOR: &str = "audiobass";
+const PADDING: usize = 1024;
+
+/// קובץ שהדירוג נכתב אליו, עם החתימה (mtime, גודל) שלו לפני הכתיבה ואחריה
+#[derive(Debug)]
+pub struct Written {
+    pub path: PathBuf,
+    pub before: Option<(u64, u64)>,
+    pub after: Option<(u64, u64)>,
+}
+
+/// כותב דירוגים לקבצים ב-Thread משלו: קריאה וכתיבה של קובץ שלם לא קורות ב-UI.
+/// כתיבה שנכשלה חוזרת ב-poll_failures כדי שה-UI יציג אותה, וכתיבה שהצליחה חוזרת
+/// ב-poll_written - השינוי בקובץ הוא שלנו, וה-Watcher לא צריך לסרוק אותו מחדש
+pub struct RatingWriter {
+    jobs_tx: Option<Sender<(PathBuf, u8)>>,
+    written_rx: Receiver<Written>,
+    failures_rx: Receiver<(PathBuf, String)>,
+    worker: Option<JoinHandle<()>>,
+}
+
+impl RatingWriter {
+    pub fn new() -> Self {
+        let (jobs_tx, jobs_rx) = mpsc::channel::<(PathBuf, u8)>();
+        let (written_tx, written_rx) = mpsc::channel();
+        let (failures_tx, failures_rx) = mpsc::channel();
+        let worker = thread::spawn(move || {
+            for (path, stars) in jobs_rx {
+                let before = file_signature(&path);
+                let sent = match write_rating(&path, stars) {
+                    Ok(()) => {
+                        let after = file_signature(&path);
+                        written_tx.send(Written { path, before, after }).is_ok()
+                    }
+                    Err(e) => failures_tx.send((path, e)).is_ok(),
+                };
+                if !sent {
+                    break;
+                }
+            }
+        });
+
+        Self {
+            jobs_tx: Some(jobs_tx),
+            written_rx,
+            failures_rx,
+            worker: Some(worker),
+        }
+    }
+
+    /// קבצים שלא נתמכים (ראו supports) פשוט לא נשלחים לכאן
+    pub fn write(&self, path: PathBuf, stars: u8) {
+        if let Some(tx) = &self.jobs_tx {
+            let _ = tx.send((path, stars));
+        }
+    }
+
+    /// (קובץ, שגיאה) לכל כתיבה שנכשלה מאז הקריאה הקודמת
+    pub fn poll_failures(&self) -> Vec<(PathBuf, String)> {
+        let mut failures = Vec::new();
+        while let Ok(failure) = self.failures_rx.try_recv() {
+            failures.push(failure);
+        }
+        failures
+    }
+
+    /// הקבצים שנכתבו בהצלחה מאז הקריאה הקודמת
+    pub fn poll_written(&self) -> Vec<Written> {
+        let mut written = Vec::new();
+        while let Ok(file) = self.written_rx.try_recv() {
+            written.push(file);
+        }
+        written
+    }
+}
+
+impl Drop for RatingWriter {
+    fn drop(&mut self) {
+        // סגירת התור - מה שכבר נשלח עוד נכתב, כדי שדירוג לא ילך לאיבוד בסגירה
+        self.jobs_tx.take();
+        if let Some(worker) = self.worker.take() {
+            let _ = worker.join();
+        }
+    }
+}
+
+/// MP3 (POPM ב-ID3), ו-FLAC, Ogg Vorbis ו-Opus (FMPS_RATING ב-Vorbis Comment).
+/// בשאר הפורמטים הדירוג נשמר רק בספרייה
+pub fn supports(path: &Path) -> bool {
+    matches!(extension(path).as_str(), "mp3" | "flac" | "ogg" | "opus")
+}
+
+fn extension(path: &Path) -> String {
+    path.extension()
+        .map(|e| e.to_string_lossy().to_lowercase())
+        .unwrap_or_default()
+}
+
+/// כותב את הדירוג (0-5, 0 = מוחק) לתגיות של הקובץ
+fn write_rating(path: &Path, stars: u8) -> Result<(), String> {
+    let stars = stars.min(5);
+    let ext = extension(path);
+    let data = fs::read(path).map_err(|e| e.to_string())?;
+    let updated = match ext.as_str() {
+        "mp3" => id3_with_rating(&data, stars)?,
+        "flac" => flac_with_rating(&data, stars)?,
+        "ogg" | "opus" => ogg_with_rating(&data, stars)?,
+        _ => return Err(format!("rating tags are not supported for .{} files", ext)),
+    };
+    rewrite_file(path, &data, &updated)
+}
+
+/// כותב לתוך הקובץ הקיים (לא קובץ חדש במקומו): ההרשאות, הבעלים והקישורים הקשיחים
+/// נשארים, וה-Watcher רואה שינוי של הקובץ ולא מחיקה ויצירה
+fn rewrite_file(path: &Path, old: &[u8], data: &[u8]) -> Result<(), String> {
+    if data.len() == old.len() {
+        // התגית נכנסה במקום הישן והאודיו לא זז - כותבים רק עד הבית האחרון שהשתנה
+        let Some(last) = old.iter().zip(data).rposition(|(a, b)| a != b) else {
+            return Ok(());
+        };
+        return write_in_place(path, &data[..=last], false).map_err(|e| e.to_string());
+    }
+
+    // האודיו זז וכל הקובץ נכתב מחדש. עותק של המקור נשאר לידו עד שהכתיבה מסתיימת,
+    // כדי שקריסה באמצע לא תשאיר רק קובץ חצי כתוב
+    let file_name = path.file_name().ok_or("invalid file name")?.to_string_lossy();
+    let backup = path.with_file_name(format!(".{}.rating-backup", file_name));
+    fs::write(&backup, old).map_err(|e| e.to_string())?;
+    match write_in_place(path, data, true) {
+        Ok(()) => {
+            let _ = fs::remove_file(&backup);
+            Ok(())
+        }
+        Err(e) => Err(format!("{} (the original file is kept in {:?})", e, backup)),
+    }
+}
+
+/// דורס את תחילת הקובץ ב-data. whole - זה כל הקובץ, ומה שנשאר אחריו נחתך
+fn write_in_place(path: &Path, data: &[u8], whole: bool) -> std::io::Result<()> {
+    let mut file = OpenOptions::new().write(true).open(path)?;
+    file.write_all(data)?;
+    if whole {
+        file.set_len(data.len() as u64)?;
+    }
+    file.sync_all()
+}
+
+// =========================================================
+// ID3v2 (MP3)
+// =========================================================
+
+fn id3_with_rating(data: &[u8], stars: u8) -> Result<Vec<u8>, String> {
+    let (version, frames, audio_start) = if data.starts_with(b"ID3") && data.len() >= 10 {
+        let version = data[3];
+        if !(3..=4).contains(&version) {
+            return Err(format!("ID3v2.{} tags are not supported", version));
+        }
+        // unsynchronisation, כותרת מורחבת ו-footer נדירים - עדיף לא לגעת מאשר להרוס
+        if data[5] & 0xD0 != 0 {
+            return Err("unsupported ID3 tag layout".to_string());
+        }
+        let size = syncsafe(&data[6..10]) as usize;
+        let end = (10 + size).min(data.len());
+        (version, &data[10..end], end)
+    } else if stars == 0 {
+        return Ok(data.to_vec());
+    } else {
+        (3, &data[..0], 0)
+    };
+
+    let mut body = Vec::new();
+    let mut pos = 0;
+    while pos + 10 <= frames.len() && frames[pos] != 0 {
+        let header = &frames[pos..pos + 10];
+        let size = (if version == 4 {
+            syncsafe(&header[4..8])
+        } else {
+            u32::from_be_bytes([header[4], header[5], header[6], header[7]])
+        }) as usize;
+        let end = pos + 10 + size;
+        if end > frames.len() {
+            return Err("corrupt ID3 frame".to_string());
+        }
+        // ה-POPM שלנו מוחלף, דירוגים של נגנים אחרים נשארים כמו שהם
+        let ours = &header[..4] == b"POPM" && frames[pos + 10..end].starts_with(POPM_EMAIL);
+        if !ours {
+            body.extend_from_slice(&frames[pos..end]);
+        }
+        pos = end;
+    }
+
+    if stars > 0 {
+        let mut popm = POPM_EMAIL.to_vec();
+        popm.push(0);
+        popm.push(POPM_STARS[stars as usize]);
+        body.extend_from_slice(b"POPM");
+        let size = popm.len() as u32;
+        if version == 4 {
+            body.extend_from_slice(&to_syncsafe(size));
+        } else {
+            body.extend_from_slice(&size.to_be_bytes());
+        }
+        body.extend_from_slice(&[0, 0]);
+        body.extend_from_slice(&popm);
+    }
+
+    // שומרים על אותו גודל תגית אם אפשר, כדי שהאודיו יישאר באותו מקום
+    let old_size = audio_start.saturating_sub(10);
+    let size = if body.len() <= old_size { old_size } else { body.len() + PADDING };
+    body.resize(size, 0);
+
+    let mut out = Vec::with_capacity(10 + size + data.len() - audio_start);
+    out.extend_from_slice(b"ID3");
+    out.extend_from_slice(&[version, 0, 0]);
+    out.extend_from_slice(&to_syncsafe(size as u32));
+    out.extend_from_slice(&body);
+    out.extend_from_slice(&data[audio_start..]);
+    Ok(out)
+}
+
+fn syncsafe(bytes: &[u8]) -> u32 {
+    bytes.iter().fold(0, |acc, &b| (acc << 7) | (b & 0x7F) as u32)
+}
+
+fn to_syncsafe(value: u32) -> [u8; 4] {
+    [
+        ((value >> 21) & 0x7F) as u8,
+        ((value >> 14) & 0x7F) as u8,
+        ((value >> 7) & 0x7F) as u8,
+        (value & 0x7F) as u8,
+    ]
+}
+
+// =========================================================
+// FLAC (Vorbis Comment)
+// =========================================================
+
+const FLAC_PADDING: u8 = 1;
+const FLAC_VORBIS_COMMENT: u8 = 4;
+
+fn flac_with_rating(data: &[u8], stars: u8) -> Result<Vec<u8>, String> {
+    if !data.starts_with(b"fLaC") {
+        return Err("not a FLAC file".to_string());
+    }
+
+    // הבלוקים של המטא-דאטה: (סוג, תוכן)
+    let mut blocks: Vec<(u8, Vec<u8>)> = Vec::new();
+    let mut pos = 4;
+    loop {
+        if pos + 4 > data.len() {
+            return Err("corrupt FLAC metadata".to_string());
+        }
+        let last = data[pos] & 0x80 != 0;
+        let kind = data[pos] & 0x7F;
+        let len = u32::from_be_bytes([0, data[pos + 1], data[pos + 2], data[pos + 3]]) as usize;
+        let end = pos + 4 + len;
+        if end > data.len() {
+            return Err("corrupt FLAC metadata".to_string());
+        }
+        blocks.push((kind, data[pos + 4..end].to_vec()));
+        pos = end;
+        if last {
+            break;
+        }
+    }
+    let audio_start = pos;
+
+    let rating = (stars > 0).then(|| format!("{}={}", FMPS_KEY, stars as f64 / 5.0));
+    match blocks.iter_mut().find(|(kind, _)| *kind == FLAC_VORBIS_COMMENT) {
+        Some((_, comment)) => *comment = vorbis_with_rating(comment, rating)?,
+        None => {
+            let comment = vorbis_with_rating(&empty_vorbis_comment(), rating)?;
+            blocks.push((FLAC_VORBIS_COMMENT, comment));
+        }
+    }
+
+    // Padding אחד בסוף, בגודל שמשאיר את האודיו באותו מקום אם יש מספיק מקום
+    blocks.retain(|(kind, _)| *kind != FLAC_PADDING);
+    let used: usize = 4 + blocks.iter().map(|(_, b)| 4 + b.len()).sum::<usize>();
+    let padding = if used + 4 <= audio_start { audio_start - used - 4 } else { PADDING };
+    blocks.push((FLAC_PADDING, vec![0; padding]));
+
+    let mut out = Vec::with_capacity(used + 4 + padding + data.len() - audio_start);
+    out.extend_from_slice(b"fLaC");
+    let count = blocks.len();
+    for (i, (kind, block)) in blocks.into_iter().enumerate() {
+        let last = if i + 1 == count { 0x80 } else { 0 };
+        out.push(last | kind);
+        out.extend_from_slice(&(block.len() as u32).to_be_bytes()[1..]);
+        out.extend_from_slice(&block);
+    }
+    out.extend_from_slice(&data[audio_start..]);
+    Ok(out)
+}
+
+fn empty_vorbis_comment() -> Vec<u8> {
+    let mut block = (VENDOR.len() as u32).to_le_bytes().to_vec();
+    block.extend_from_slice(VENDOR.as_bytes());
+    block.extend_from_slice(&0u32.to_le_bytes());
+    block
+}
+
+/// בונה מחדש את רשימת ההערות: בלי FMPS_RATING הישן, ועם החדש (אם יש)
+fn vorbis_with_rating(block: &[u8], rating: Option<String>) -> Result<Vec<u8>, String> {
+    let corrupt = || "corrupt Vorbis comment".to_string();
+    let read_u32 = |pos: usize| -> Result<usize, String> {
+        let bytes = block.get(pos..pos + 4).ok_or_else(corrupt)?;
+        Ok(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as usize)
+    };
+
+    let vendor_end = 4 + read_u32(0)?;
+    let vendor = block.get(..vendor_end).ok_or_else(corrupt)?;
+    let count = read_u32(vendor_end)?;
+    let mut comments: Vec<&[u8]> = Vec::with_capacity(count + 1);
+    let mut pos = vendor_end + 4;
+    for _ in 0..count {
+        let len = read_u32(pos)?;
+        let comment = block.get(pos + 4..pos + 4 + len).ok_or_else(corrupt)?;
+        let key = comment.split(|&b| b == b'=').next().unwrap_or_default();
+        if !key.eq_ignore_ascii_case(FMPS_KEY.as_bytes()) {
+            comments.push(comment);
+        }
+        pos += 4 + len;
+    }
+    if let Some(rating) = &rating {
+        comments.push(rating.as_bytes());
+    }
+
+    let mut out = vendor.to_vec();
+    out.extend_from_slice(&(comments.len() as u32).to_le_bytes());
+    for comment in comments {
+        out.extend_from_slice(&(comment.len() as u32).to_le_bytes());
+        out.extend_from_slice(comment);
+    }
+    // מה שאחרי ההערות (ה-Framing Bit של Vorbis, מידע בינארי של Opus) נשאר כמו שהוא
+    out.extend_from_slice(&block[pos..]);
+    Ok(out)
+}
+
+// =========================================================
+// Ogg (Vorbis / Opus)
+// =========================================================
+
+const OGG_CONTINUED: u8 = 0x01;
+const OGG_FIRST_PAGE: u8 = 0x02;
+const OGG_MAX_SEGMENTS: usize = 255;
+
+/// עמוד Ogg: הכותרת והתוכן נשארים Slices של הקובץ המקורי
+struct OggPage<'a> {
+    serial: u32,
+    sequence: u32,
+    lacing: &'a [u8],
+    body: &'a [u8],
+    raw: &'a [u8],
+}
+
+fn ogg_pages(data: &[u8]) -> Result<Vec<OggPage<'_>>, String> {
+    let corrupt = || "corrupt Ogg page".to_string();
+    let mut pages = Vec::new();
+    let mut pos = 0;
+    while pos < data.len() {
+        let header = data.get(pos..pos + 27).ok_or_else(corrupt)?;
+        if !header.starts_with(b"OggS") || header[4] != 0 {
+            return Err(corrupt());
+        }
+        let lacing_end = pos + 27 + header[26] as usize;
+        let lacing = data.get(pos + 27..lacing_end).ok_or_else(corrupt)?;
+        let body_end = lacing_end + lacing.iter().map(|&l| l as usize).sum::<usize>();
+        let body = data.get(lacing_end..body_end).ok_or_else(corrupt)?;
+        pages.push(OggPage {
+            serial: u32::from_le_bytes([header[14], header[15], header[16], header[17]]),
+            sequence: u32::from_le_bytes([header[18], header[19], header[20], header[21]]),
+            lacing,
+            body,
+            raw: &data[pos..body_end],
+        });
+        pos = body_end;
+    }
+    Ok(pages)
+}
+
+/// מחליף את חבילת ההערות (החבילה השנייה בזרם) ובונה מחדש את העמודים של הכותרות.
+/// אם מספר העמודים השתנה, שאר העמודים של הזרם ממוספרים מחדש (עם CRC חדש)
+fn ogg_with_rating(data: &[u8], stars: u8) -> Result<Vec<u8>, String> {
+    let pages = ogg_pages(data)?;
+    let first = pages.first().ok_or("not an Ogg file")?;
+    let serial = first.serial;
+
+    // אוספים חבילות עד סוף הכותרות: שלוש ב-Vorbis (זיהוי, הערות, Setup), שתיים ב-Opus
+    let mut packets: Vec<Vec<u8>> = Vec::new();
+    let mut partial = Vec::new();
+    let mut header_pages = 0;
+    let mut header = None;
+    for (i, page) in pages.iter().enumerate() {
+        if page.serial != serial {
+            return Err("multiplexed Ogg streams are not supported".to_string());
+        }
+        let mut offset = 0;
+        for &lace in page.lacing {
+            partial.extend_from_slice(&page.body[offset..offset + lace as usize]);
+            offset += lace as usize;
+            if lace < 255 {
+                packets.push(std::mem::take(&mut partial));
+            }
+        }
+        header = match packets.first() {
+            Some(id) if id.starts_with(b"\x01vorbis") => Some((&b"\x03vorbis"[..], 3)),
+            Some(id) if id.starts_with(b"OpusHead") => Some((&b"OpusTags"[..], 2)),
+            Some(_) => return Err("only Vorbis and Opus streams are supported".to_string()),
+            None => None,
+        };
+        if let Some((_, count)) = header
+            && packets.len() >= count
+        {
+            // הכותרות מסתיימות בסוף עמוד, והאודיו מתחיל בעמוד חדש
+            if packets.len() > count || !partial.is_empty() {
+                return Err("unsupported Ogg page layout".to_string());
+            }
+            header_pages = i + 1;
+            break;
+        }
+    }
+    let Some((magic, _)) = header.filter(|_| header_pages > 0) else {
+        return Err("corrupt Ogg headers".to_string());
+    };
+    let comment = packets[1].strip_prefix(magic).ok_or("corrupt Ogg comment header")?;
+    let rating = (stars > 0).then(|| format!("{}={}", FMPS_KEY, stars as f64 / 5.0));
+    packets[1] = [magic, &vorbis_with_rating(comment, rating)?].concat();
+
+    // חבילת הזיהוי לבד בעמוד הראשון, ושאר הכותרות מתחילות בעמוד חדש
+    let mut out = Vec::with_capacity(data.len() + PADDING);
+    let mut sequence = first.sequence;
+    write_ogg_packets(&mut out, serial, &mut sequence, OGG_FIRST_PAGE, &packets[..1]);
+    write_ogg_packets(&mut out, serial, &mut sequence, 0, &packets[1..]);
+
+    let shift = sequence.wrapping_sub(pages.get(header_pages).map_or(sequence, |p| p.sequence));
+    for page in &pages[header_pages..] {
+        let start = out.len();
+        out.extend_from_slice(page.raw);
+        if shift != 0 && page.serial == serial {
+            let renumbered = page.sequence.wrapping_add(shift);
+            out[start + 18..start + 22].copy_from_slice(&renumbered.to_le_bytes());
+            set_ogg_crc(&mut out[start..]);
+        }
+    }
+    Ok(out)
+}
+
+/// מחלק חבילות לעמודים (עד 255 מקטעים בעמוד). flags - של העמוד הראשון בלבד
+fn write_ogg_packets(
+    out: &mut Vec<u8>,
+    serial: u32,
+    sequence: &mut u32,
+    flags: u8,
+    packets: &[Vec<u8>],
+) {
+    let mut flags = flags;
+    let mut lacing = Vec::new();
+    let mut body = Vec::new();
+    let mut packet_ended = false;
+    for packet in packets {
+        let mut rest = &packet[..];
+        loop {
+            let lace = rest.len().min(255);
+            lacing.push(lace as u8);
+            body.extend_from_slice(&rest[..lace]);
+            rest = &rest[lace..];
+            let done = lace < 255;
+            packet_ended |= done;
+            if lacing.len() == OGG_MAX_SEGMENTS {
+                write_ogg_page(out, flags, packet_ended, serial, sequence, &lacing, &body);
+                flags = if done { 0 } else { OGG_CONTINUED };
+                packet_ended = false;
+                lacing.clear();
+                body.clear();
+            }
+            if done {
+                break;
+            }
+        }
+    }
+    if !lacing.is_empty() {
+        write_ogg_page(out, flags, packet_ended, serial, sequence, &lacing, &body);
+    }
+}
+
+fn write_ogg_page(
+    out: &mut Vec<u8>,
+    flags: u8,
+    packet_ended: bool,
+    serial: u32,
+    sequence: &mut u32,
+    lacing: &[u8],
+    body: &[u8],
+) {
+    // בעמודי הכותרות ה-Granule הוא 0, ו-1- בעמוד שאף חבילה לא מסתיימת בו
+    let granule: u64 = if packet_ended { 0 } else { u64::MAX };
+    let start = out.len();
+    out.extend_from_slice(b"OggS");
+    out.extend_from_slice(&[0, flags]);
+    out.extend_from_slice(&granule.to_le_bytes());
+    out.extend_from_slice(&serial.to_le_bytes());
+    out.extend_from_slice(&sequence.to_le_bytes());
+    out.extend_from_slice(&[0; 4]);
+    out.push(lacing.len() as u8);
+    out.extend_from_slice(lacing);
+    out.extend_from_slice(body);
+    set_ogg_crc(&mut out[start..]);
+    *sequence = sequence.wrapping_add(1);
+}
+
+/// ה-CRC של Ogg: פולינום 0x04C11DB7, בלי היפוך ובלי XOR, על העמוד עם שדה CRC מאופס
+static OGG_CRC_TABLE: [u32; 256] = {
+    let mut table = [0u32; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = (i as u32) << 24;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 0x8000_0000 != 0 { (crc << 1) ^ 0x04C1_1DB7 } else { crc << 1 };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+};
+
+fn ogg_crc(bytes: &[u8]) -> u32 {
+    bytes.iter().fold(0, |crc, &b| {
+        (crc << 8) ^ OGG_CRC_TABLE[((crc >> 24) as u8 ^ b) as usize]
+    })
+}
+
+fn set_ogg_crc(page: &mut [u8]) {
+    page[22..26].fill(0);
+    let crc = ogg_crc(page);
+    page[22..26].copy_from_slice(&crc.to_le_bytes());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const AUDIO: &[u8] = b"\xFF\xFBaudio frames";
+    const STREAMINFO: u8 = 0;
+    const OTHER_EMAIL: &[u8] = b"foo@example.com";
+
+    fn popm(email: &[u8], rating: u8) -> Vec<u8> {
+        let mut body = email.to_vec();
+        body.push(0);
+        body.push(rating);
+        body
+    }
+
+    fn id3_frame(version: u8, id: &[u8; 4], body: &[u8]) -> Vec<u8> {
+        let mut frame = id.to_vec();
+        let size = body.len() as u32;
+        if version == 4 {
+            frame.extend_from_slice(&to_syncsafe(size));
+        } else {
+            frame.extend_from_slice(&size.to_be_bytes());
+        }
+        frame.extend_from_slice(&[0, 0]);
+        frame.extend_from_slice(body);
+        frame
+    }
+
+    /// תגית ID3 עם המסגרות ועם ריפוד, ואחריה "אודיו"
+    fn id3_file(version: u8, frames: &[Vec<u8>], padding: usize) -> Vec<u8> {
+        let body = frames.concat();
+        let size = body.len() + padding;
+        let mut data = b"ID3".to_vec();
+        data.extend_from_slice(&[version, 0, 0]);
+        data.extend_from_slice(&to_syncsafe(size as u32));
+        data.extend_from_slice(&body);
+        data.resize(10 + size, 0);
+        data.extend_from_slice(AUDIO);
+        data
+    }
+
+    type Frames = Vec<([u8; 4], Vec<u8>)>;
+
+    /// (המסגרות, גודל התגית) של קובץ שנכתב
+    fn id3_frames(data: &[u8]) -> (Frames, usize) {
+        assert!(data.starts_with(b"ID3"));
+        let version = data[3];
+        let size = syncsafe(&data[6..10]) as usize;
+        let tag = &data[10..10 + size];
+        let mut frames = Vec::new();
+        let mut pos = 0;
+        while pos + 10 <= tag.len() && tag[pos] != 0 {
+            let header = &tag[pos..pos + 10];
+            let len = (if version == 4 {
+                syncsafe(&header[4..8])
+            } else {
+                u32::from_be_bytes(header[4..8].try_into().unwrap())
+            }) as usize;
+            let id = header[..4].try_into().unwrap();
+            frames.push((id, tag[pos + 10..pos + 10 + len].to_vec()));
+            pos += 10 + len;
+        }
+        (frames, size)
+    }
+
+    #[test]
+    fn id3_without_tag() {
+        // בלי דירוג אין סיבה ליצור תגית
+        assert_eq!(id3_with_rating(AUDIO, 0).unwrap(), AUDIO);
+
+        let data = id3_with_rating(AUDIO, 3).unwrap();
+        let (frames, size) = id3_frames(&data);
+        assert_eq!(frames, [(*b"POPM", popm(POPM_EMAIL, 128))]);
+        assert_eq!(size, 10 + POPM_EMAIL.len() + 2 + PADDING);
+        assert_eq!(&data[10 + size..], AUDIO);
+    }
+
+    #[test]
+    fn id3_keeps_foreign_popm_and_replaces_ours() {
+        let title = id3_frame(3, b"TIT2", b"\x00Song");
+        let foreign = id3_frame(3, b"POPM", &popm(OTHER_EMAIL, 200));
+        let ours = id3_frame(3, b"POPM", &popm(POPM_EMAIL, 64));
+        let data = id3_file(3, &[title, foreign, ours], 100);
+
+        let updated = id3_with_rating(&data, 5).unwrap();
+        let (frames, _) = id3_frames(&updated);
+        assert_eq!(
+            frames,
+            [
+                (*b"TIT2", b"\x00Song".to_vec()),
+                (*b"POPM", popm(OTHER_EMAIL, 200)),
+                (*b"POPM", popm(POPM_EMAIL, 255)),
+            ]
+        );
+    }
+
+    #[test]
+    fn id3_reuses_padding_in_place() {
+        let title = id3_frame(4, b"TIT2", b"\x03Song");
+        let data = id3_file(4, &[title], 512);
+
+        let updated = id3_with_rating(&data, 4).unwrap();
+        // אותו גודל תגית - האודיו לא זז
+        assert_eq!(updated.len(), data.len());
+        assert_eq!(updated[3], 4);
+        let (frames, size) = id3_frames(&updated);
+        assert_eq!(size, syncsafe(&data[6..10]) as usize);
+        assert_eq!(frames[1], (*b"POPM", popm(POPM_EMAIL, 196)));
+        assert_eq!(&updated[10 + size..], AUDIO);
+    }
+
+    #[test]
+    fn id3_grows_when_tag_is_full() {
+        let title = id3_frame(3, b"TIT2", b"\x00Song");
+        let title_len = title.len();
+        let data = id3_file(3, &[title], 0);
+
+        let updated = id3_with_rating(&data, 1).unwrap();
+        let (frames, size) = id3_frames(&updated);
+        assert_eq!(frames.len(), 2);
+        assert_eq!(frames[1], (*b"POPM", popm(POPM_EMAIL, 1)));
+        assert_eq!(size, title_len + 10 + POPM_EMAIL.len() + 2 + PADDING);
+        assert_eq!(&updated[10 + size..], AUDIO);
+    }
+
+    #[test]
+    fn id3_rating_zero_removes_ours() {
+        let ours = id3_frame(3, b"POPM", &popm(POPM_EMAIL, 128));
+        let foreign = id3_frame(3, b"POPM", &popm(OTHER_EMAIL, 200));
+        let data = id3_file(3, &[ours, foreign], 0);
+
+        let updated = id3_with_rating(&data, 0).unwrap();
+        assert_eq!(updated.len(), data.len());
+        let (frames, _) = id3_frames(&updated);
+        assert_eq!(frames, [(*b"POPM", popm(OTHER_EMAIL, 200))]);
+    }
+
+    #[test]
+    fn id3_refuses_unsupported_layouts() {
+        let mut unsynchronised = id3_file(3, &[], 16);
+        unsynchronised[5] = 0x80;
+        assert!(id3_with_rating(&unsynchronised, 3).is_err());
+
+        let mut old_version = id3_file(3, &[], 16);
+        old_version[3] = 2;
+        assert!(id3_with_rating(&old_version, 3).is_err());
+    }
+
+    fn flac_file(blocks: &[(u8, Vec<u8>)]) -> Vec<u8> {
+        let mut data = b"fLaC".to_vec();
+        for (i, (kind, block)) in blocks.iter().enumerate() {
+            let last = if i + 1 == blocks.len() { 0x80 } else { 0 };
+            data.push(last | kind);
+            data.extend_from_slice(&(block.len() as u32).to_be_bytes()[1..]);
+            data.extend_from_slice(block);
+        }
+        data.extend_from_slice(AUDIO);
+        data
+    }
+
+    /// הבלוקים של קובץ FLAC שנכתב, והמקום שבו מתחיל האודיו
+    fn flac_blocks(data: &[u8]) -> (Vec<(u8, Vec<u8>)>, usize) {
+        let mut blocks = Vec::new();
+        let mut pos = 4;
+        loop {
+            let last = data[pos] & 0x80 != 0;
+            let len = u32::from_be_bytes([0, data[pos + 1], data[pos + 2], data[pos + 3]]) as usize;
+            blocks.push((data[pos] & 0x7F, data[pos + 4..pos + 4 + len].to_vec()));
+            pos += 4 + len;
+            if last {
+                return (blocks, pos);
+            }
+        }
+    }
+
+    fn vorbis_comment(vendor: &str, comments: &[&str]) -> Vec<u8> {
+        let mut block = (vendor.len() as u32).to_le_bytes().to_vec();
+        block.extend_from_slice(vendor.as_bytes());
+        block.extend_from_slice(&(comments.len() as u32).to_le_bytes());
+        for comment in comments {
+            block.extend_from_slice(&(comment.len() as u32).to_le_bytes());
+            block.extend_from_slice(comment.as_bytes());
+        }
+        block
+    }
+
+    #[test]
+    fn flac_without_comment_gets_one() {
+        let data = flac_file(&[(STREAMINFO, vec![7; 34])]);
+
+        let updated = flac_with_rating(&data, 3).unwrap();
+        let (blocks, audio_start) = flac_blocks(&updated);
+        assert_eq!(
+            blocks,
+            [
+                (STREAMINFO, vec![7; 34]),
+                (FLAC_VORBIS_COMMENT, vorbis_comment(VENDOR, &["FMPS_RATING=0.6"])),
+                (FLAC_PADDING, vec![0; PADDING]),
+            ]
+        );
+        assert_eq!(&updated[audio_start..], AUDIO);
+    }
+
+    #[test]
+    fn flac_reuses_padding_in_place() {
+        let comment = vorbis_comment("ref", &["TITLE=Song", "fmps_rating=0.2"]);
+        let data = flac_file(&[
+            (STREAMINFO, vec![7; 34]),
+            (FLAC_VORBIS_COMMENT, comment),
+            (FLAC_PADDING, vec![0; 256]),
+        ]);
+
+        let updated = flac_with_rating(&data, 5).unwrap();
+        assert_eq!(updated.len(), data.len());
+        let (blocks, audio_start) = flac_blocks(&updated);
+        assert_eq!(audio_start, data.len() - AUDIO.len());
+        assert_eq!(blocks[1].1, vorbis_comment("ref", &["TITLE=Song", "FMPS_RATING=1"]));
+        assert_eq!(blocks[2].0, FLAC_PADDING);
+    }
+
+    #[test]
+    fn flac_grows_without_padding() {
+        let comment = vorbis_comment("ref", &["TITLE=Song"]);
+        let data = flac_file(&[(STREAMINFO, vec![7; 34]), (FLAC_VORBIS_COMMENT, comment)]);
+
+        let updated = flac_with_rating(&data, 2).unwrap();
+        let (blocks, audio_start) = flac_blocks(&updated);
+        assert_eq!(blocks[1].1, vorbis_comment("ref", &["TITLE=Song", "FMPS_RATING=0.4"]));
+        assert_eq!(blocks[2], (FLAC_PADDING, vec![0; PADDING]));
+        assert_eq!(&updated[audio_start..], AUDIO);
+    }
+
+    #[test]
+    fn flac_rating_zero_removes_it() {
+        let comment = vorbis_comment("ref", &["FMPS_RATING=0.8", "ARTIST=Band"]);
+        let data = flac_file(&[
+            (STREAMINFO, vec![7; 34]),
+            (FLAC_VORBIS_COMMENT, comment),
+            (FLAC_PADDING, vec![0; 64]),
+        ]);
+
+        let updated = flac_with_rating(&data, 0).unwrap();
+        assert_eq!(updated.len(), data.len());
+        let (blocks, _) = flac_blocks(&updated);
+        assert_eq!(blocks[1].1, vorbis_comment("ref", &["ARTIST=Band"]));
+    }
+
+    #[test]
+    fn flac_rejects_other_files() {
+        assert!(flac_with_rating(AUDIO, 3).is_err());
+        // מטא-דאטה שנקטעה באמצע
+        let mut truncated = flac_file(&[(STREAMINFO, vec![7; 34])]);
+        truncated.truncate(20);
+        assert!(flac_with_rating(&truncated, 3).is_err());
+    }
+
+    #[test]
+    fn vorbis_comment_replaces_only_the_rating() {
+        let block = vorbis_comment(
+            "vendor",
+            &["TITLE=שיר", "FMPS_RATING=0.4", "Fmps_Rating=0.2", "COMMENT=a=b"],
+        );
+
+        let updated = vorbis_with_rating(&block, Some("FMPS_RATING=0.8".to_string())).unwrap();
+        let expected = vorbis_comment("vendor", &["TITLE=שיר", "COMMENT=a=b", "FMPS_RATING=0.8"]);
+        assert_eq!(updated, expected);
+
+        let removed = vorbis_with_rating(&block, None).unwrap();
+        assert_eq!(removed, vorbis_comment("vendor", &["TITLE=שיר", "COMMENT=a=b"]));
+    }
+
+    #[test]
+    fn vorbis_comment_rejects_truncated_blocks() {
+        let block = vorbis_comment("vendor", &["TITLE=Song"]);
+        assert!(vorbis_with_rating(&block[..block.len() - 2], None).is_err());
+        assert!(vorbis_with_rating(&[1, 0], None).is_err());
+        let empty = vorbis_with_rating(&empty_vorbis_comment(), None).unwrap();
+        assert_eq!(empty, vorbis_comment(VENDOR, &[]));
+    }
+
+    /// עמוד Ogg עם חבילות שלמות
+    fn ogg_page(flags: u8, granule: u64, sequence: u32, packets: &[&[u8]]) -> Vec<u8> {
+        let mut lacing = Vec::new();
+        for packet in packets {
+            lacing.extend(std::iter::repeat_n(255, packet.len() / 255));
+            lacing.push((packet.len() % 255) as u8);
+        }
+        let mut page = b"OggS".to_vec();
+        page.extend_from_slice(&[0, flags]);
+        page.extend_from_slice(&granule.to_le_bytes());
+        page.extend_from_slice(&77u32.to_le_bytes());
+        page.extend_from_slice(&sequence.to_le_bytes());
+        page.extend_from_slice(&[0; 4]);
+        page.push(lacing.len() as u8);
+        page.extend_from_slice(&lacing);
+        page.extend_from_slice(&packets.concat());
+        set_ogg_crc(&mut page);
+        page
+    }
+
+    /// החבילות של קובץ Ogg שנכתב. בודק בדרך שה-CRC תקין ושהעמודים ממוספרים ברצף
+    fn ogg_packets(data: &[u8]) -> Vec<Vec<u8>> {
+        let pages = ogg_pages(data).unwrap();
+        let mut packets = Vec::new();
+        let mut partial = Vec::new();
+        for (i, page) in pages.iter().enumerate() {
+            let mut raw = page.raw.to_vec();
+            set_ogg_crc(&mut raw);
+            assert_eq!(raw, page.raw, "bad CRC on page {}", i);
+            assert_eq!(page.sequence, pages[0].sequence + i as u32);
+            let continued = page.raw[5] & OGG_CONTINUED != 0;
+            assert_eq!(continued, !partial.is_empty());
+            let mut offset = 0;
+            for &lace in page.lacing {
+                partial.extend_from_slice(&page.body[offset..offset + lace as usize]);
+                offset += lace as usize;
+                if lace < 255 {
+                    packets.push(std::mem::take(&mut partial));
+                }
+            }
+        }
+        packets
+    }
+
+    fn opus_head() -> Vec<u8> {
+        [&b"OpusHead"[..], &[1, 2, 0, 0]].concat()
+    }
+
+    fn opus_tags(comments: &[&str]) -> Vec<u8> {
+        [&b"OpusTags"[..], &vorbis_comment("ref", comments)].concat()
+    }
+
+    /// קובץ Opus: כותרות בשני עמודים ועמוד אודיו אחד
+    fn opus_file(tags: &[u8], audio: &[u8]) -> Vec<u8> {
+        [
+            ogg_page(OGG_FIRST_PAGE, 0, 0, &[&opus_head()]),
+            ogg_page(0, 0, 1, &[tags]),
+            audio.to_vec(),
+        ]
+        .concat()
+    }
+
+    #[test]
+    fn ogg_crc_matches_the_reference() {
+        // CRC-32 בלי היפוך, בלי ערך התחלתי ובלי XOR בסוף
+        assert_eq!(ogg_crc(b"123456789"), 0x89A1_897F);
+    }
+
+    #[test]
+    fn ogg_vorbis_replaces_the_comment_packet() {
+        let id = [&b"\x01vorbis"[..], &[0; 23]].concat();
+        let comment = |comments: &[&str]| {
+            [&b"\x03vorbis"[..], &vorbis_comment("ref", comments), &[1]].concat()
+        };
+        let setup = [&b"\x05vorbis"[..], &[9; 300]].concat();
+        let audio = [
+            ogg_page(0, 4096, 2, &[&[1; 100], &[2; 50]]),
+            ogg_page(0x04, 8192, 3, &[&[3; 70]]),
+        ]
+        .concat();
+        let data = [
+            ogg_page(OGG_FIRST_PAGE, 0, 0, &[&id]),
+            ogg_page(0, 0, 1, &[&comment(&["TITLE=Song"]), &setup]),
+            audio.clone(),
+        ]
+        .concat();
+
+        let updated = ogg_with_rating(&data, 3).unwrap();
+        let packets = ogg_packets(&updated);
+        assert_eq!(packets.len(), 6);
+        assert_eq!(packets[0], id);
+        assert_eq!(packets[1], comment(&["TITLE=Song", "FMPS_RATING=0.6"]));
+        assert_eq!(packets[2], setup);
+        assert_eq!(updated[5], OGG_FIRST_PAGE);
+        // אותו מספר עמודים - האודיו מועתק כמו שהוא
+        assert!(updated.ends_with(&audio));
+    }
+
+    #[test]
+    fn ogg_opus_keeps_the_tail_and_removes_the_rating() {
+        let tags = |comments: &[&str]| [opus_tags(comments), b"\x01binary".to_vec()].concat();
+        let audio = ogg_page(0x04, 960, 2, &[&[5; 20]]);
+        let data = opus_file(&tags(&["fmps_rating=1", "ARTIST=Band"]), &audio);
+
+        let updated = ogg_with_rating(&data, 0).unwrap();
+        let packets = ogg_packets(&updated);
+        assert_eq!(packets, [opus_head(), tags(&["ARTIST=Band"]), vec![5; 20]]);
+    }
+
+    #[test]
+    fn ogg_renumbers_pages_when_the_headers_grow() {
+        // חבילת הערות שממלאת עמוד שלם בדיוק (255 מקטעים)
+        let filler = format!("X={}", "a".repeat(255 * 255 - 1 - 23 - 2));
+        let tags = opus_tags(&[&filler]);
+        assert_eq!(tags.len(), 255 * 255 - 1);
+        let audio = ogg_page(0x04, 960, 2, &[&[5; 20]]);
+        let data = opus_file(&tags, &audio);
+
+        let updated = ogg_with_rating(&data, 5).unwrap();
+        let pages = ogg_pages(&updated).unwrap();
+        assert_eq!(pages.len(), 4);
+        // עמוד שאף חבילה לא מסתיימת בו מסומן ב-Granule של 1-
+        assert_eq!(pages[1].raw[6..14], [0xFF; 8]);
+        assert_eq!(pages[3].sequence, 3);
+        assert_eq!(pages[3].body, &audio[28..]);
+        let packets = ogg_packets(&updated);
+        assert_eq!(packets[1], opus_tags(&[&filler, "FMPS_RATING=1"]));
+        assert_eq!(packets[2], vec![5; 20]);
+    }
+
+    #[test]
+    fn ogg_refuses_what_it_cannot_rewrite() {
+        assert!(ogg_with_rating(AUDIO, 3).is_err());
+        let speex = ogg_page(OGG_FIRST_PAGE, 0, 0, &[b"Speex   "]);
+        assert!(ogg_with_rating(&speex, 3).is_err());
+        // הכותרות לא הסתיימו
+        let head_only = ogg_page(OGG_FIRST_PAGE, 0, 0, &[&opus_head()]);
+        assert!(ogg_with_rating(&head_only, 3).is_err());
+        // אודיו באותו עמוד עם הכותרות
+        let mixed = [
+            head_only,
+            ogg_page(0, 0, 1, &[&opus_tags(&[]), &[5; 20]]),
+        ]
+        .concat();
+        assert!(ogg_with_rating(&mixed, 3).is_err());
+    }
+
+    /// תיקייה זמנית לכל בדיקה, נמחקת בסוף
+    struct TempDir(PathBuf);
+
+    impl TempDir {
+        fn new(name: &str) -> Self {
+            let dir = std::env::temp_dir()
+                .join(format!("audiobass-rating-tags-{}-{}", std::process::id(), name));
+            let _ = fs::remove_dir_all(&dir);
+            fs::create_dir_all(&dir).unwrap();
+            Self(dir)
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn writes_into_the_same_file() {
+        use std::os::unix::fs::{MetadataExt, PermissionsExt};
+
+        let dir = TempDir::new("in-place");
+        let path = dir.0.join("song.flac");
+        let link = dir.0.join("link.flac");
+        let comment = vorbis_comment("ref", &["TITLE=Song"]);
+        let padded = flac_file(&[
+            (STREAMINFO, vec![7; 34]),
+            (FLAC_VORBIS_COMMENT, comment.clone()),
+            (FLAC_PADDING, vec![0; 256]),
+        ]);
+        fs::write(&path, &padded).unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o640)).unwrap();
+        fs::hard_link(&path, &link).unwrap();
+        let inode = fs::metadata(&path).unwrap().ino();
+
+        // יש מקום בריפוד - אותו גודל
+        write_rating(&path, 4).unwrap();
+        let data = fs::read(&link).unwrap();
+        assert_eq!(data.len(), padded.len());
+        let expected = vorbis_comment("ref", &["TITLE=Song", "FMPS_RATING=0.8"]);
+        assert_eq!(flac_blocks(&data).0[1].1, expected);
+
+        // בלי ריפוד הקובץ גדל, ועדיין נכתב לאותו קובץ
+        let unpadded = flac_file(&[(STREAMINFO, vec![7; 34]), (FLAC_VORBIS_COMMENT, comment)]);
+        fs::write(&path, unpadded).unwrap();
+        write_rating(&path, 2).unwrap();
+        let data = fs::read(&link).unwrap();
+        assert_eq!(flac_blocks(&data).0[2], (FLAC_PADDING, vec![0; PADDING]));
+        assert!(data.ends_with(AUDIO));
+
+        let meta = fs::metadata(&path).unwrap();
+        assert_eq!(meta.ino(), inode);
+        assert_eq!(meta.permissions().mode() & 0o777, 0o640);
+        // העותק של המקור לא נשאר בתיקייה
+        assert_eq!(fs::read_dir(&dir.0).unwrap().count(), 2);
+    }
+
+    #[test]
+    fn shrinking_rewrite_truncates_the_file() {
+        let dir = TempDir::new("shrink");
+        let path = dir.0.join("song.opus");
+        let audio = ogg_page(0x04, 960, 2, &[&[5; 20]]);
+        let data = opus_file(&opus_tags(&["FMPS_RATING=0.4"]), &audio);
+        fs::write(&path, &data).unwrap();
+
+        write_rating(&path, 0).unwrap();
+        let written = fs::read(&path).unwrap();
+        assert_eq!(written, ogg_with_rating(&data, 0).unwrap());
+        assert!(written.len() < data.len());
+    }
+
+    #[test]
+    fn supported_formats() {
+        assert!(supports(Path::new("a/song.MP3")));
+        assert!(supports(Path::new("song.flac")));
+        assert!(supports(Path::new("song.ogg")));
+        assert!(supports(Path::new("song.opus")));
+        assert!(!supports(Path::new("song.m4a")));
+        assert!(!supports(Path::new("song")));
+    }
+}
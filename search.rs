@@ -0,0 +1,160 @@
+use crate::metadata::TrackMetadata;
+use std::path::Path;
+
+/// התאמה "מטושטשת" של מילה אחת מהחיפוש: כל התווים שלה מופיעים בטקסט לפי הסדר,
+/// לא בהכרח צמודים ("btls" מוצא את "Beatles"). מחזיר את מיקומי התווים שהתאימו (לפי chars)
+pub fn fuzzy_match(term: &str, text: &str) -> Option<Vec<usize>> {
+    let term: Vec<char> = term.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+    if term.is_empty() {
+        return Some(Vec::new());
+    }
+
+    // קודם מחפשים את המילה כרצף - זו ההתאמה שהמשתמש כנראה התכוון אליה
+    if let Some(start) = (0..text.len())
+        .filter(|&start| start + term.len() <= text.len())
+        .find(|&start| (0..term.len()).all(|i| same_char(text[start + i], term[i])))
+    {
+        return Some((start..start + term.len()).collect());
+    }
+
+    // מכל מקום שבו התו הראשון מופיע מתקדמים לפי הסדר, ולוקחים את ההתאמה הצפופה ביותר -
+    // מופע מוקדם של התו הראשון לא צריך לפסול התאמה טובה בהמשך הטקסט
+    let best = (0..text.len())
+        .filter(|&start| same_char(text[start], term[0]))
+        .filter_map(|start| {
+            let mut positions = Vec::with_capacity(term.len());
+            positions.push(start);
+            for &c in &term[1..] {
+                let next = positions[positions.len() - 1] + 1;
+                positions.push((next..text.len()).find(|&i| same_char(text[i], c))?);
+            }
+            Some(positions)
+        })
+        .min_by_key(|positions| positions[positions.len() - 1] - positions[0])?;
+
+    // תווים שמפוזרים על פני כל הטקסט הם כבר לא התאמה אלא צירוף מקרים
+    let span = best[best.len() - 1] - best[0] + 1;
+    if span > term.len() * 3 {
+        return None;
+    }
+    Some(best)
+}
+
+/// האם השיר מתאים לחיפוש: כל מילה צריכה להופיע בשם הקובץ או בשם/אמן/אלבום
+pub fn matches_track(query: &str, path: &Path, meta: Option<&TrackMetadata>) -> bool {
+    let file_name = path
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let fields: Vec<&str> = std::iter::once(file_name.as_str())
+        .chain(meta.into_iter().flat_map(|m| {
+            [&m.title, &m.artist, &m.album]
+                .into_iter()
+                .filter_map(|field| field.as_deref())
+        }))
+        .collect();
+
+    query
+        .split_whitespace()
+        .all(|term| fields.iter().any(|field| fuzzy_match(term, field).is_some()))
+}
+
+/// התווים שצריך להדגיש בטקסט המוצג (מילה שהתאימה רק לאלבום, למשל, לא תודגש בו)
+pub fn highlight_positions(query: &str, text: &str) -> Vec<usize> {
+    let mut positions: Vec<usize> = query
+        .split_whitespace()
+        .filter_map(|term| fuzzy_match(term, text))
+        .flatten()
+        .collect();
+    positions.sort_unstable();
+    positions.dedup();
+    positions
+}
+
+fn same_char(a: char, b: char) -> bool {
+    a == b || a.to_lowercase().eq(b.to_lowercase())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// התווים במיקומים האלה - בדיוק מה ש-highlighted_text ידגיש
+    fn picked(text: &str, positions: &[usize]) -> String {
+        text.chars()
+            .enumerate()
+            .filter(|(i, _)| positions.contains(i))
+            .map(|(_, c)| c)
+            .collect()
+    }
+
+    #[test]
+    fn contiguous_match_is_preferred() {
+        assert_eq!(fuzzy_match("beat", "The Beatles"), Some(vec![4, 5, 6, 7]));
+        // "be" מופיע גם מפוזר (b...e) - הרצף מנצח
+        assert_eq!(fuzzy_match("BE", "bxe be"), Some(vec![4, 5]));
+        assert_eq!(fuzzy_match("", "anything"), Some(vec![]));
+    }
+
+    #[test]
+    fn non_contiguous_match_in_order() {
+        assert_eq!(fuzzy_match("btls", "Beatles"), Some(vec![0, 3, 4, 6]));
+        // הסדר חשוב
+        assert_eq!(fuzzy_match("slb", "Beatles"), None);
+        // תווים מפוזרים על פני טקסט ארוך הם לא התאמה
+        assert_eq!(fuzzy_match("ab", "a-------------b"), None);
+        // מופע מוקדם של התו הראשון לא פוסל התאמה צפופה בהמשך
+        assert_eq!(fuzzy_match("btl", "b-----------Bxtl"), Some(vec![12, 14, 15]));
+        assert_eq!(fuzzy_match("longer", "long"), None);
+    }
+
+    #[test]
+    fn case_folding_beyond_ascii() {
+        assert_eq!(fuzzy_match("ÉTÉ", "Un été"), Some(vec![3, 4, 5]));
+        assert_eq!(fuzzy_match("straße", "STRASSE straße"), Some(vec![8, 9, 10, 11, 12, 13]));
+        assert_eq!(fuzzy_match("мир", "Миру мир"), Some(vec![0, 1, 2]));
+    }
+
+    #[test]
+    fn hebrew_text() {
+        let text = "אריק איינשטיין - אוהב להיות בבית";
+        let positions = fuzzy_match("איינ", text).unwrap();
+        assert_eq!(positions, vec![5, 6, 7, 8]);
+        assert_eq!(picked(text, &positions), "איינ");
+
+        let positions = fuzzy_match("אהב", text).unwrap();
+        assert_eq!(picked(text, &positions), "אהב");
+    }
+
+    #[test]
+    fn highlight_positions_are_char_indices() {
+        let text = "Café Übermut – שיר";
+        let positions = highlight_positions("übER שיר caf", text);
+        assert_eq!(positions, vec![0, 1, 2, 5, 6, 7, 8, 15, 16, 17]);
+        assert_eq!(picked(text, &positions), "CafÜberשיר");
+        // חפיפה בין מילים לא מכפילה מיקומים
+        assert_eq!(highlight_positions("caf afé", text), vec![0, 1, 2, 3]);
+        // מילה שלא התאימה לטקסט הזה פשוט לא מודגשת בו
+        assert_eq!(highlight_positions("zzz caf", text), vec![0, 1, 2]);
+    }
+
+    #[test]
+    fn every_term_must_match_some_field() {
+        let meta = TrackMetadata {
+            title: Some("Yesterday".to_string()),
+            artist: Some("The Beatles".to_string()),
+            album: Some("Help!".to_string()),
+            ..Default::default()
+        };
+        let path = Path::new("/music/שירים/01 track.mp3");
+        assert!(matches_track("btls yest", path, Some(&meta)));
+        assert!(matches_track("HELP  01", path, Some(&meta)));
+        assert!(!matches_track("beatles abbey", path, Some(&meta)));
+        // שם התיקייה לא נחשב, רק שם הקובץ
+        assert!(!matches_track("שירים", path, Some(&meta)));
+        assert!(matches_track("track", path, None));
+        assert!(!matches_track("yesterday", path, None));
+        assert!(matches_track("   ", path, None));
+    }
+}
@@ -0,0 +1,560 @@
+use crate::library_db::{LibraryDb, TrackRow};
+use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+const DAY_SECS: f64 = 24.0 * 60.0 * 60.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum RuleField {
+    Title,
+    Artist,
+    Album,
+    Genre,
+    Format,
+    Year,
+    Duration, // שניות
+    Rating,
+    Loved, // 1 = אהוב, 0 = לא
+    PlayCount,
+    LastPlayed,
+    Added,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FieldKind {
+    Text,
+    Number,
+    Date,
+}
+
+impl RuleField {
+    pub const ALL: [RuleField; 12] = [
+        RuleField::Title,
+        RuleField::Artist,
+        RuleField::Album,
+        RuleField::Genre,
+        RuleField::Format,
+        RuleField::Year,
+        RuleField::Duration,
+        RuleField::Rating,
+        RuleField::Loved,
+        RuleField::PlayCount,
+        RuleField::LastPlayed,
+        RuleField::Added,
+    ];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            RuleField::Title => "Title",
+            RuleField::Artist => "Artist",
+            RuleField::Album => "Album",
+            RuleField::Genre => "Genre",
+            RuleField::Format => "Format",
+            RuleField::Year => "Year",
+            RuleField::Duration => "Duration (s)",
+            RuleField::Rating => "Rating",
+            RuleField::Loved => "Loved (1/0)",
+            RuleField::PlayCount => "Play count",
+            RuleField::LastPlayed => "Last played",
+            RuleField::Added => "Date added",
+        }
+    }
+
+    pub fn kind(&self) -> FieldKind {
+        match self {
+            RuleField::Title
+            | RuleField::Artist
+            | RuleField::Album
+            | RuleField::Genre
+            | RuleField::Format => FieldKind::Text,
+            RuleField::Year
+            | RuleField::Duration
+            | RuleField::Rating
+            | RuleField::Loved
+            | RuleField::PlayCount => FieldKind::Number,
+            RuleField::LastPlayed | RuleField::Added => FieldKind::Date,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum RuleOp {
+    Is,
+    IsNot,
+    Contains,
+    NotContains,
+    AtLeast,
+    AtMost,
+    InLastDays,
+    NotInLastDays, // כולל "אף פעם"
+}
+
+impl RuleOp {
+    pub fn label(&self) -> &'static str {
+        match self {
+            RuleOp::Is => "is",
+            RuleOp::IsNot => "is not",
+            RuleOp::Contains => "contains",
+            RuleOp::NotContains => "does not contain",
+            RuleOp::AtLeast => ">=",
+            RuleOp::AtMost => "<=",
+            RuleOp::InLastDays => "in the last (days)",
+            RuleOp::NotInLastDays => "not in the last (days)",
+        }
+    }
+
+    /// הפעולות שיש להן משמעות לסוג השדה
+    pub fn for_kind(kind: FieldKind) -> &'static [RuleOp] {
+        match kind {
+            FieldKind::Text => &[RuleOp::Is, RuleOp::IsNot, RuleOp::Contains, RuleOp::NotContains],
+            FieldKind::Number => &[RuleOp::Is, RuleOp::IsNot, RuleOp::AtLeast, RuleOp::AtMost],
+            FieldKind::Date => &[RuleOp::InLastDays, RuleOp::NotInLastDays],
+        }
+    }
+}
+
+/// תנאי אחד, למשל genre is Jazz. הערך נשמר כטקסט (כמו שהוקלד) ומפורש בזמן ההערכה
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Rule {
+    pub field: RuleField,
+    pub op: RuleOp,
+    pub value: String,
+}
+
+impl Default for Rule {
+    fn default() -> Self {
+        Self {
+            field: RuleField::Genre,
+            op: RuleOp::Is,
+            value: String::new(),
+        }
+    }
+}
+
+impl Rule {
+    fn matches(&self, track: &TrackRow, now: u64) -> bool {
+        let value = self.value.trim();
+        match self.field.kind() {
+            FieldKind::Text => {
+                let text = text_field(track, self.field).unwrap_or_default().to_lowercase();
+                let value = value.to_lowercase();
+                match self.op {
+                    RuleOp::Is => text == value,
+                    RuleOp::IsNot => text != value,
+                    RuleOp::Contains => text.contains(&value),
+                    RuleOp::NotContains => !text.contains(&value),
+                    _ => false,
+                }
+            }
+            FieldKind::Number => {
+                let Ok(value) = value.parse::<f64>() else {
+                    return false;
+                };
+                let Some(number) = number_field(track, self.field) else {
+                    return self.op == RuleOp::IsNot;
+                };
+                match self.op {
+                    RuleOp::Is => number == value,
+                    RuleOp::IsNot => number != value,
+                    RuleOp::AtLeast => number >= value,
+                    RuleOp::AtMost => number <= value,
+                    _ => false,
+                }
+            }
+            FieldKind::Date => {
+                let Ok(days) = value.parse::<f64>() else {
+                    return false;
+                };
+                let cutoff = now as f64 - days * DAY_SECS;
+                let recent = date_field(track, self.field).is_some_and(|t| t as f64 >= cutoff);
+                match self.op {
+                    RuleOp::InLastDays => recent,
+                    RuleOp::NotInLastDays => !recent,
+                    _ => false,
+                }
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum MatchMode {
+    #[default]
+    All,
+    Any,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum SmartOrder {
+    #[default]
+    Artist, // אמן -> אלבום -> מספר שיר
+    Title,
+    MostPlayed,
+    LeastPlayed,
+    RecentlyPlayed,
+    RecentlyAdded,
+    HighestRated,
+}
+
+impl SmartOrder {
+    pub const ALL: [SmartOrder; 7] = [
+        SmartOrder::Artist,
+        SmartOrder::Title,
+        SmartOrder::MostPlayed,
+        SmartOrder::LeastPlayed,
+        SmartOrder::RecentlyPlayed,
+        SmartOrder::RecentlyAdded,
+        SmartOrder::HighestRated,
+    ];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            SmartOrder::Artist => "Artist / Album",
+            SmartOrder::Title => "Title",
+            SmartOrder::MostPlayed => "Most played",
+            SmartOrder::LeastPlayed => "Least played",
+            SmartOrder::RecentlyPlayed => "Recently played",
+            SmartOrder::RecentlyAdded => "Recently added",
+            SmartOrder::HighestRated => "Highest rated",
+        }
+    }
+
+    fn compare(&self, a: &TrackRow, b: &TrackRow) -> Ordering {
+        let text = |t: &TrackRow, field| text_field(t, field).unwrap_or_default().to_lowercase();
+        match self {
+            SmartOrder::Artist => text(a, RuleField::Artist)
+                .cmp(&text(b, RuleField::Artist))
+                .then_with(|| text(a, RuleField::Album).cmp(&text(b, RuleField::Album)))
+                .then_with(|| a.info.metadata.track_number.cmp(&b.info.metadata.track_number)),
+            SmartOrder::Title => text(a, RuleField::Title).cmp(&text(b, RuleField::Title)),
+            SmartOrder::MostPlayed => b.stats.play_count.cmp(&a.stats.play_count),
+            SmartOrder::LeastPlayed => a.stats.play_count.cmp(&b.stats.play_count),
+            SmartOrder::RecentlyPlayed => b.stats.last_played.cmp(&a.stats.last_played),
+            SmartOrder::RecentlyAdded => b.added.cmp(&a.added),
+            SmartOrder::HighestRated => b.stats.rating.cmp(&a.stats.rating),
+        }
+    }
+}
+
+/// ההגדרה של פלייליסט חכם. זה גם פורמט ה-JSON של הייצוא, למשל:
+/// {"match": "All", "rules": [{"field": "Genre", "op": "Is", "value": "Jazz"},
+///  {"field": "Rating", "op": "AtLeast", "value": "4"}], "order": "MostPlayed", "limit": 50}
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SmartRules {
+    #[serde(rename = "match")]
+    pub match_mode: MatchMode,
+    pub rules: Vec<Rule>,
+    pub order: SmartOrder,
+    pub limit: Option<usize>,
+}
+
+impl SmartRules {
+    /// השירים מהספרייה שעונים על הכללים, בסדר ובמגבלה שהוגדרו
+    pub fn evaluate(&self, db: &LibraryDb, now: u64) -> Vec<PathBuf> {
+        let mut tracks: Vec<&TrackRow> = db
+            .tracks
+            .values()
+            .filter(|track| self.matches(track, now))
+            .collect();
+        tracks.sort_by(|a, b| self.order.compare(a, b).then_with(|| a.path.cmp(&b.path)));
+        if let Some(limit) = self.limit {
+            tracks.truncate(limit);
+        }
+        tracks.into_iter().map(|track| track.path.clone()).collect()
+    }
+
+    fn matches(&self, track: &TrackRow, now: u64) -> bool {
+        // בלי כללים - כל הספרייה (שימושי עם מיון + מגבלה, למשל "50 הכי מושמעים")
+        if self.rules.is_empty() {
+            return true;
+        }
+        match self.match_mode {
+            MatchMode::All => self.rules.iter().all(|rule| rule.matches(track, now)),
+            MatchMode::Any => self.rules.iter().any(|rule| rule.matches(track, now)),
+        }
+    }
+}
+
+fn text_field(track: &TrackRow, field: RuleField) -> Option<String> {
+    let meta = &track.info.metadata;
+    match field {
+        RuleField::Title => Some(meta.display_title(&track.path)),
+        RuleField::Artist => meta.artist.clone().or_else(|| meta.album_artist.clone()),
+        RuleField::Album => meta.album.clone(),
+        RuleField::Genre => meta.genre.clone(),
+        RuleField::Format => track.path.extension().map(|e| e.to_string_lossy().to_string()),
+        _ => None,
+    }
+}
+
+fn number_field(track: &TrackRow, field: RuleField) -> Option<f64> {
+    match field {
+        RuleField::Year => track.info.metadata.year.map(f64::from),
+        RuleField::Duration => Some(track.info.duration_secs),
+        RuleField::Rating => Some(track.stats.rating as f64),
+        RuleField::Loved => Some(if track.stats.loved { 1.0 } else { 0.0 }),
+        RuleField::PlayCount => Some(track.stats.play_count as f64),
+        _ => None,
+    }
+}
+
+fn date_field(track: &TrackRow, field: RuleField) -> Option<u64> {
+    match field {
+        RuleField::LastPlayed => track.stats.last_played,
+        RuleField::Added => Some(track.added),
+        _ => None,
+    }
+}
+
+// =========================================================
+// ייצוא / ייבוא של הכללים כ-JSON
+// =========================================================
+
+#[derive(Serialize, Deserialize)]
+struct SmartPlaylistFile {
+    name: String,
+    #[serde(flatten)]
+    rules: SmartRules,
+}
+
+pub fn export(name: &str, rules: &SmartRules, path: &Path) -> Result<(), String> {
+    let file = SmartPlaylistFile {
+        name: name.to_string(),
+        rules: rules.clone(),
+    };
+    let json = serde_json::to_string_pretty(&file).map_err(|e| e.to_string())?;
+    fs::write(path, json).map_err(|e| e.to_string())
+}
+
+pub fn import(path: &Path) -> Result<(String, SmartRules), String> {
+    let content = fs::read_to_string(path).map_err(|e| e.to_string())?;
+    let file: SmartPlaylistFile = serde_json::from_str(&content).map_err(|e| e.to_string())?;
+    Ok((file.name, file.rules))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::library_db::{LibraryEntry, TrackStats};
+    use crate::metadata::TrackMetadata;
+
+    const NOW: u64 = 1_700_000_000;
+    const DAY: u64 = 24 * 60 * 60;
+
+    struct Song {
+        name: &'static str,
+        artist: &'static str,
+        genre: Option<&'static str>,
+        year: Option<i32>,
+        added_days_ago: u64,
+        stats: TrackStats,
+    }
+
+    fn song(name: &'static str, artist: &'static str) -> Song {
+        Song {
+            name,
+            artist,
+            genre: None,
+            year: None,
+            added_days_ago: 100,
+            stats: TrackStats::default(),
+        }
+    }
+
+    fn library(songs: Vec<Song>) -> LibraryDb {
+        let mut db = LibraryDb::default();
+        for song in songs {
+            let path = PathBuf::from(format!("/music/{}.flac", song.name));
+            let id = db.upsert(
+                path,
+                LibraryEntry {
+                    mtime: 0,
+                    size: 0,
+                    duration_secs: 200.0,
+                    metadata: TrackMetadata {
+                        title: Some(song.name.to_string()),
+                        artist: Some(song.artist.to_string()),
+                        genre: song.genre.map(str::to_string),
+                        year: song.year,
+                        ..Default::default()
+                    },
+                    stream: None,
+                },
+            );
+            let track = db.tracks.get_mut(&id).unwrap();
+            track.added = NOW - song.added_days_ago * DAY;
+            track.stats = song.stats;
+        }
+        db
+    }
+
+    fn rule(field: RuleField, op: RuleOp, value: &str) -> Rule {
+        Rule {
+            field,
+            op,
+            value: value.to_string(),
+        }
+    }
+
+    fn names(rules: &SmartRules, db: &LibraryDb) -> Vec<String> {
+        rules
+            .evaluate(db, NOW)
+            .iter()
+            .map(|p| p.file_stem().unwrap().to_string_lossy().to_string())
+            .collect()
+    }
+
+    fn sample() -> LibraryDb {
+        library(vec![
+            Song {
+                genre: Some("Jazz"),
+                year: Some(1959),
+                stats: TrackStats {
+                    rating: 5,
+                    play_count: 10,
+                    last_played: Some(NOW - 2 * DAY),
+                    ..Default::default()
+                },
+                ..song("so-what", "Miles Davis")
+            },
+            Song {
+                genre: Some("jazz fusion"),
+                added_days_ago: 3,
+                stats: TrackStats {
+                    rating: 2,
+                    play_count: 3,
+                    last_played: Some(NOW - 60 * DAY),
+                    ..Default::default()
+                },
+                ..song("spain", "Chick Corea")
+            },
+            Song {
+                genre: Some("Rock"),
+                year: Some(1971),
+                added_days_ago: 1,
+                stats: TrackStats {
+                    rating: 4,
+                    loved: true,
+                    ..Default::default()
+                },
+                ..song("angie", "The Rolling Stones")
+            },
+            song("untagged", "Unknown"),
+        ])
+    }
+
+    #[test]
+    fn all_and_any() {
+        let db = sample();
+        let mut rules = SmartRules {
+            rules: vec![
+                rule(RuleField::Genre, RuleOp::Contains, "JAZZ"),
+                rule(RuleField::Rating, RuleOp::AtLeast, "4"),
+            ],
+            order: SmartOrder::Title,
+            ..Default::default()
+        };
+        assert_eq!(names(&rules, &db), ["so-what"]);
+        rules.match_mode = MatchMode::Any;
+        assert_eq!(names(&rules, &db), ["angie", "so-what", "spain"]);
+    }
+
+    #[test]
+    fn no_rules_means_the_whole_library() {
+        let db = sample();
+        let rules = SmartRules {
+            order: SmartOrder::Title,
+            ..Default::default()
+        };
+        assert_eq!(names(&rules, &db), ["angie", "so-what", "spain", "untagged"]);
+    }
+
+    #[test]
+    fn date_rules() {
+        let db = sample();
+        let only = |rule| SmartRules {
+            rules: vec![rule],
+            order: SmartOrder::Title,
+            ..Default::default()
+        };
+        // "לא הושמע ב-30 הימים האחרונים" כולל שירים שלא הושמעו אף פעם
+        let stale = only(rule(RuleField::LastPlayed, RuleOp::NotInLastDays, "30"));
+        assert_eq!(names(&stale, &db), ["angie", "spain", "untagged"]);
+        let recent = only(rule(RuleField::LastPlayed, RuleOp::InLastDays, "30"));
+        assert_eq!(names(&recent, &db), ["so-what"]);
+        let added = only(rule(RuleField::Added, RuleOp::InLastDays, "7"));
+        assert_eq!(names(&added, &db), ["angie", "spain"]);
+        let fraction = only(rule(RuleField::Added, RuleOp::InLastDays, "1.5"));
+        assert_eq!(names(&fraction, &db), ["angie"]);
+    }
+
+    #[test]
+    fn missing_fields() {
+        let db = sample();
+        let only = |rule| SmartRules {
+            rules: vec![rule],
+            order: SmartOrder::Title,
+            ..Default::default()
+        };
+        // שנה חסרה: "is not" מתאים, השוואות לא
+        let not_1959 = only(rule(RuleField::Year, RuleOp::IsNot, "1959"));
+        assert_eq!(names(&not_1959, &db), ["angie", "spain", "untagged"]);
+        let old = only(rule(RuleField::Year, RuleOp::AtMost, "2000"));
+        assert_eq!(names(&old, &db), ["angie", "so-what"]);
+        // ז'אנר חסר נחשב טקסט ריק
+        let not_rock = only(rule(RuleField::Genre, RuleOp::IsNot, "rock"));
+        assert_eq!(names(&not_rock, &db), ["so-what", "spain", "untagged"]);
+        let no_genre = only(rule(RuleField::Genre, RuleOp::Is, ""));
+        assert_eq!(names(&no_genre, &db), ["untagged"]);
+        // ערך שהוא לא מספר לא מתאים לכלום
+        let invalid = only(rule(RuleField::Rating, RuleOp::IsNot, "five"));
+        assert!(names(&invalid, &db).is_empty());
+        let loved = only(rule(RuleField::Loved, RuleOp::Is, " 1 "));
+        assert_eq!(names(&loved, &db), ["angie"]);
+    }
+
+    #[test]
+    fn order_and_limit() {
+        let db = sample();
+        let mut rules = SmartRules {
+            order: SmartOrder::MostPlayed,
+            limit: Some(2),
+            ..Default::default()
+        };
+        assert_eq!(names(&rules, &db), ["so-what", "spain"]);
+        rules.order = SmartOrder::HighestRated;
+        assert_eq!(names(&rules, &db), ["so-what", "angie"]);
+        rules.order = SmartOrder::RecentlyAdded;
+        assert_eq!(names(&rules, &db), ["angie", "spain"]);
+        rules.order = SmartOrder::RecentlyPlayed;
+        assert_eq!(names(&rules, &db), ["so-what", "spain"]);
+        // שווים ממוינים לפי הנתיב
+        rules.order = SmartOrder::LeastPlayed;
+        rules.limit = None;
+        assert_eq!(names(&rules, &db), ["angie", "untagged", "spain", "so-what"]);
+        rules.order = SmartOrder::Artist;
+        assert_eq!(names(&rules, &db), ["spain", "so-what", "angie", "untagged"]);
+        rules.limit = Some(0);
+        assert!(names(&rules, &db).is_empty());
+    }
+
+    #[test]
+    fn documented_json_format() {
+        let json = r#"{"name": "Jazz", "match": "Any", "rules": [
+            {"field": "Genre", "op": "Is", "value": "Jazz"},
+            {"field": "Rating", "op": "AtLeast", "value": "4"}],
+            "order": "MostPlayed", "limit": 50}"#;
+        let file: SmartPlaylistFile = serde_json::from_str(json).unwrap();
+        assert_eq!(file.name, "Jazz");
+        assert_eq!(file.rules.match_mode, MatchMode::Any);
+        assert_eq!(file.rules.rules[1], rule(RuleField::Rating, RuleOp::AtLeast, "4"));
+        assert_eq!((file.rules.order, file.rules.limit), (SmartOrder::MostPlayed, Some(50)));
+
+        let again: SmartPlaylistFile =
+            serde_json::from_str(&serde_json::to_string(&file).unwrap()).unwrap();
+        assert_eq!(again.rules, file.rules);
+    }
+}
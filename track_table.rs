@@ -0,0 +1,428 @@
+use crate::library_db::{LibraryEntry, TrackStats};
+use crate::metadata::TrackMetadata;
+use serde::{Deserialize, Deserializer, Serialize};
+use std::cmp::Ordering;
+use std::path::Path;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum TableColumn {
+    Number,
+    Title,
+    Artist,
+    Album,
+    Duration,
+    Format,
+    Bitrate,
+    PlayCount,
+    Rating,
+}
+
+impl TableColumn {
+    pub const ALL: [TableColumn; 9] = [
+        TableColumn::Number,
+        TableColumn::Title,
+        TableColumn::Artist,
+        TableColumn::Album,
+        TableColumn::Duration,
+        TableColumn::Format,
+        TableColumn::Bitrate,
+        TableColumn::PlayCount,
+        TableColumn::Rating,
+    ];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            TableColumn::Number => "#",
+            TableColumn::Title => "Title",
+            TableColumn::Artist => "Artist",
+            TableColumn::Album => "Album",
+            TableColumn::Duration => "Duration",
+            TableColumn::Format => "Format",
+            TableColumn::Bitrate => "Bitrate",
+            TableColumn::PlayCount => "Plays",
+            TableColumn::Rating => "Rating",
+        }
+    }
+
+    fn default_width(&self) -> f32 {
+        match self {
+            TableColumn::Number => 40.0,
+            TableColumn::Title => 260.0,
+            TableColumn::Artist | TableColumn::Album => 160.0,
+            TableColumn::Duration | TableColumn::Format => 65.0,
+            TableColumn::Bitrate => 80.0,
+            TableColumn::PlayCount => 50.0,
+            TableColumn::Rating => 90.0,
+        }
+    }
+
+    /// בלי עמודת השם לא נשאר על מה ללחוץ
+    pub fn can_hide(&self) -> bool {
+        *self != TableColumn::Title
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ColumnState {
+    pub column: TableColumn,
+    pub visible: bool,
+    pub width: f32,
+}
+
+/// הגדרות הטבלה שנשמרות ב-AppState: אילו עמודות מוצגות, הרוחב שלהן והמיון
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct TableConfig {
+    #[serde(deserialize_with = "known_columns")]
+    pub columns: Vec<ColumnState>,
+    #[serde(deserialize_with = "known_sort")]
+    pub sort: Option<(TableColumn, bool)>, // (עמודה, עולה). None = הסדר של הפלייליסט
+}
+
+/// עמודה שהגרסה הזו לא מכירה (למשל מהגדרות של גרסה חדשה יותר) נזרקת - בלי זה שגיאה
+/// אחת הייתה מפילה את כל ה-AppState, כולל הפלייליסטים
+fn known_columns<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<ColumnState>, D::Error> {
+    let values = Vec::<serde_json::Value>::deserialize(deserializer)?;
+    Ok(values
+        .into_iter()
+        .filter_map(|value| serde_json::from_value(value).ok())
+        .collect())
+}
+
+fn known_sort<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<(TableColumn, bool)>, D::Error> {
+    let value = serde_json::Value::deserialize(deserializer)?;
+    Ok(serde_json::from_value(value).ok().flatten())
+}
+
+impl Default for TableConfig {
+    fn default() -> Self {
+        Self {
+            columns: TableColumn::ALL
+                .iter()
+                .map(|&column| ColumnState {
+                    column,
+                    visible: true,
+                    width: column.default_width(),
+                })
+                .collect(),
+            sort: None,
+        }
+    }
+}
+
+impl TableConfig {
+    /// קובץ הגדרות ישן: עמודות שנוספו מאז מצטרפות בסוף, כפילויות נזרקות
+    pub fn normalize(&mut self) {
+        let mut seen = Vec::new();
+        self.columns.retain(|state| {
+            let first = !seen.contains(&state.column);
+            seen.push(state.column);
+            first
+        });
+        for column in TableColumn::ALL {
+            if !seen.contains(&column) {
+                self.columns.push(ColumnState {
+                    column,
+                    visible: true,
+                    width: column.default_width(),
+                });
+            }
+        }
+        for state in &mut self.columns {
+            if !state.column.can_hide() {
+                state.visible = true;
+            }
+        }
+    }
+
+    pub fn visible_columns(&self) -> Vec<ColumnState> {
+        self.columns.iter().filter(|c| c.visible).cloned().collect()
+    }
+
+    pub fn set_width(&mut self, column: TableColumn, width: f32) {
+        if let Some(state) = self.columns.iter_mut().find(|c| c.column == column) {
+            state.width = width;
+        }
+    }
+
+    /// לחיצה על כותרת: עולה -> יורד -> בלי מיון. "#" תמיד חוזר לסדר של הפלייליסט
+    pub fn toggle_sort(&mut self, column: TableColumn) {
+        self.sort = match self.sort {
+            _ if column == TableColumn::Number => None,
+            Some((current, true)) if current == column => Some((column, false)),
+            Some((current, false)) if current == column => None,
+            _ => Some((column, true)),
+        };
+    }
+}
+
+/// מה ששורה אחת בטבלה יודעת על השיר: התגיות, והרשומה והסטטיסטיקה מהספרייה (אם כבר נסרק)
+pub struct RowData<'a> {
+    pub number: usize, // המיקום בפלייליסט, מ-1
+    pub path: &'a Path,
+    pub meta: Option<&'a TrackMetadata>,
+    pub entry: Option<&'a LibraryEntry>,
+    pub stats: Option<&'a TrackStats>,
+}
+
+pub enum SortValue {
+    Text(String),
+    Number(f64),
+}
+
+impl RowData<'_> {
+    pub fn text(&self, column: TableColumn) -> String {
+        match column {
+            TableColumn::Number => self.number.to_string(),
+            TableColumn::Title => match self.meta {
+                Some(meta) => meta.display_title(self.path),
+                None => self
+                    .path
+                    .file_stem()
+                    .unwrap_or_default()
+                    .to_string_lossy()
+                    .to_string(),
+            },
+            TableColumn::Artist => self
+                .meta
+                .and_then(|m| m.artist.clone().or_else(|| m.album_artist.clone()))
+                .unwrap_or_default(),
+            TableColumn::Album => self.meta.and_then(|m| m.album.clone()).unwrap_or_default(),
+            TableColumn::Duration => self
+                .duration()
+                .map(|secs| format!("{}:{:02}", secs as u64 / 60, secs as u64 % 60))
+                .unwrap_or_default(),
+            TableColumn::Format => self
+                .path
+                .extension()
+                .map(|ext| ext.to_string_lossy().to_uppercase())
+                .unwrap_or_default(),
+            TableColumn::Bitrate => self
+                .bitrate_kbps()
+                .map(|kbps| format!("{} kbps", kbps.round()))
+                .unwrap_or_default(),
+            TableColumn::PlayCount => self
+                .stats
+                .filter(|stats| stats.play_count > 0)
+                .map(|stats| stats.play_count.to_string())
+                .unwrap_or_default(),
+            TableColumn::Rating => self
+                .stats
+                .map(|stats| {
+                    let heart = if stats.loved { "♥ " } else { "" };
+                    format!("{}{}", heart, "★".repeat(stats.rating.into()))
+                })
+                .unwrap_or_default(),
+        }
+    }
+
+    /// None = אין ערך (למשל שיר שעוד לא נסרק) - תמיד בסוף, לא משנה כיוון המיון
+    pub fn sort_value(&self, column: TableColumn) -> Option<SortValue> {
+        match column {
+            TableColumn::Number => Some(SortValue::Number(self.number as f64)),
+            TableColumn::Duration => self.duration().map(SortValue::Number),
+            TableColumn::Bitrate => self.bitrate_kbps().map(SortValue::Number),
+            TableColumn::PlayCount => self
+                .stats
+                .map(|stats| SortValue::Number(stats.play_count as f64)),
+            // שירים אהובים לפני שירים אחרים עם אותו דירוג
+            TableColumn::Rating => self.stats.map(|stats| {
+                SortValue::Number(stats.rating as f64 + if stats.loved { 0.5 } else { 0.0 })
+            }),
+            _ => {
+                let text = self.text(column);
+                (!text.is_empty()).then(|| SortValue::Text(text.to_lowercase()))
+            }
+        }
+    }
+
+    fn duration(&self) -> Option<f64> {
+        self.entry.map(|e| e.duration_secs).filter(|secs| *secs > 0.0)
+    }
+
+    /// מהזרם אם ידוע, אחרת ממוצע לפי גודל הקובץ (FLAC למשל לא מדווח bitrate)
+    fn bitrate_kbps(&self) -> Option<f64> {
+        let entry = self.entry?;
+        if let Some(bitrate) = entry.stream.as_ref().and_then(|s| s.bitrate) {
+            return Some(bitrate as f64 / 1000.0);
+        }
+        let secs = self.duration()?;
+        Some(entry.size as f64 * 8.0 / secs / 1000.0)
+    }
+}
+
+/// ממיין את השורות המוצגות (אינדקסים בפלייליסט) לפי עמודה
+pub fn sort_rows<'a>(
+    rows: &mut [usize],
+    column: TableColumn,
+    ascending: bool,
+    row_data: impl Fn(usize) -> RowData<'a>,
+) {
+    let mut keyed: Vec<(Option<SortValue>, usize)> = rows
+        .iter()
+        .map(|&idx| (row_data(idx).sort_value(column), idx))
+        .collect();
+    keyed.sort_by(|(a, a_idx), (b, b_idx)| {
+        let order = match (a, b) {
+            (None, None) => Ordering::Equal,
+            (None, Some(_)) => return Ordering::Greater,
+            (Some(_), None) => return Ordering::Less,
+            (Some(SortValue::Number(a)), Some(SortValue::Number(b))) => {
+                a.partial_cmp(b).unwrap_or(Ordering::Equal)
+            }
+            (Some(SortValue::Text(a)), Some(SortValue::Text(b))) => a.cmp(b),
+            _ => Ordering::Equal,
+        };
+        let order = if ascending { order } else { order.reverse() };
+        // שווים נשארים בסדר של הפלייליסט
+        order.then(a_idx.cmp(b_idx))
+    });
+    for (slot, (_, idx)) in rows.iter_mut().zip(keyed) {
+        *slot = idx;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    struct Track {
+        path: PathBuf,
+        meta: Option<TrackMetadata>,
+        entry: Option<LibraryEntry>,
+        stats: Option<TrackStats>,
+    }
+
+    fn track(name: &str, artist: Option<&str>, secs: Option<f64>, rating: Option<u8>) -> Track {
+        Track {
+            path: PathBuf::from(format!("/music/{}.mp3", name)),
+            meta: artist.map(|artist| TrackMetadata {
+                title: Some(name.to_string()),
+                artist: Some(artist.to_string()),
+                ..Default::default()
+            }),
+            entry: secs.map(|secs| LibraryEntry {
+                mtime: 0,
+                size: 1_000_000,
+                duration_secs: secs,
+                metadata: TrackMetadata::default(),
+                stream: None,
+            }),
+            stats: rating.map(|rating| TrackStats {
+                rating,
+                ..Default::default()
+            }),
+        }
+    }
+
+    fn sorted(tracks: &[Track], column: TableColumn, ascending: bool) -> Vec<usize> {
+        let mut rows: Vec<usize> = (0..tracks.len()).collect();
+        sort_rows(&mut rows, column, ascending, |idx| RowData {
+            number: idx + 1,
+            path: &tracks[idx].path,
+            meta: tracks[idx].meta.as_ref(),
+            entry: tracks[idx].entry.as_ref(),
+            stats: tracks[idx].stats.as_ref(),
+        });
+        rows
+    }
+
+    fn tracks() -> Vec<Track> {
+        vec![
+            track("a", Some("beta"), Some(200.0), Some(3)),
+            track("b", None, None, None),
+            track("c", Some("Alpha"), Some(100.0), Some(3)),
+            track("d", Some("beta"), None, Some(5)),
+            track("e", Some("alpha"), Some(300.0), None),
+        ]
+    }
+
+    #[test]
+    fn text_sort_ignores_case_and_keeps_ties_in_playlist_order() {
+        let tracks = tracks();
+        assert_eq!(sorted(&tracks, TableColumn::Artist, true), [2, 4, 0, 3, 1]);
+        // יורד: השווים עדיין בסדר של הפלייליסט, ומה שחסר עדיין בסוף
+        assert_eq!(sorted(&tracks, TableColumn::Artist, false), [0, 3, 2, 4, 1]);
+    }
+
+    #[test]
+    fn missing_values_sort_last_both_ways() {
+        let tracks = tracks();
+        assert_eq!(sorted(&tracks, TableColumn::Duration, true), [2, 0, 4, 1, 3]);
+        assert_eq!(sorted(&tracks, TableColumn::Duration, false), [4, 0, 2, 1, 3]);
+        assert_eq!(sorted(&tracks, TableColumn::Rating, false), [3, 0, 2, 1, 4]);
+        assert_eq!(sorted(&tracks, TableColumn::Number, false), [4, 3, 2, 1, 0]);
+    }
+
+    #[test]
+    fn loved_tracks_sort_above_same_rating() {
+        let mut tracks = tracks();
+        if let Some(stats) = &mut tracks[2].stats {
+            stats.loved = true;
+        }
+        assert_eq!(sorted(&tracks, TableColumn::Rating, false), [3, 2, 0, 1, 4]);
+    }
+
+    #[test]
+    fn toggle_sort_cycles() {
+        let mut config = TableConfig::default();
+        config.toggle_sort(TableColumn::Title);
+        assert_eq!(config.sort, Some((TableColumn::Title, true)));
+        config.toggle_sort(TableColumn::Title);
+        assert_eq!(config.sort, Some((TableColumn::Title, false)));
+        config.toggle_sort(TableColumn::Title);
+        assert_eq!(config.sort, None);
+        config.toggle_sort(TableColumn::Artist);
+        config.toggle_sort(TableColumn::Number);
+        assert_eq!(config.sort, None);
+    }
+
+    #[test]
+    fn old_config_gets_missing_columns() {
+        let json = r#"{"columns": [
+            {"column": "Title", "visible": false, "width": 300.0},
+            {"column": "Artist", "visible": false, "width": 120.0},
+            {"column": "Title", "visible": true, "width": 10.0}
+        ]}"#;
+        let mut config: TableConfig = serde_json::from_str(json).unwrap();
+        config.normalize();
+
+        let columns: Vec<TableColumn> = config.columns.iter().map(|c| c.column).collect();
+        assert_eq!(columns[..2], [TableColumn::Title, TableColumn::Artist]);
+        assert_eq!(columns.len(), TableColumn::ALL.len());
+        assert!(TableColumn::ALL.iter().all(|c| columns.contains(c)));
+        // Title תמיד מוצגת, והרוחב שנשמר נשאר
+        assert!(config.columns[0].visible);
+        assert_eq!(config.columns[0].width, 300.0);
+        assert!(!config.columns[1].visible);
+        assert_eq!(config.sort, None);
+
+        let mut empty: TableConfig = serde_json::from_str("{}").unwrap();
+        empty.normalize();
+        assert_eq!(empty.columns.len(), TableColumn::ALL.len());
+    }
+
+    #[test]
+    fn unknown_columns_are_dropped() {
+        let json = r#"{
+            "columns": [
+                {"column": "Genre", "visible": true, "width": 90.0},
+                {"column": "Album", "visible": false, "width": 70.0},
+                {"broken": true}
+            ],
+            "sort": ["Genre", true]
+        }"#;
+        let mut config: TableConfig = serde_json::from_str(json).unwrap();
+        assert_eq!(config.sort, None);
+        config.normalize();
+        assert_eq!(config.columns[0].column, TableColumn::Album);
+        assert!(!config.columns[0].visible);
+        assert_eq!(config.columns.len(), TableColumn::ALL.len());
+
+        let config: TableConfig = serde_json::from_str(r#"{"sort": ["Rating", false]}"#).unwrap();
+        assert_eq!(config.sort, Some((TableColumn::Rating, false)));
+    }
+}
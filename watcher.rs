@@ -0,0 +1,197 @@
+use crate::library_db;
+use notify::event::{ModifyKind, RenameMode};
+use notify::{Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender, TryRecvError};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+/// כמה זמן של שקט מחכים לפני שמעבירים את השינויים ל-UI.
+/// העתקה של אלבום שלם יוצרת מאות אירועים - ככה הם מגיעים כמנה אחת
+const DEBOUNCE: Duration = Duration::from_millis(750);
+
+/// מנה של שינויים בתיקיות הנצפות, אחרי איחוד וסינון
+#[derive(Default, Debug)]
+pub struct WatchBatch {
+    pub renamed: Vec<(PathBuf, PathBuf)>, // (מ-, אל-) - קובץ או תיקייה שלמה
+    pub changed: Vec<PathBuf>,            // נוצר או השתנה, וקיים עכשיו בדיסק
+    pub removed: Vec<PathBuf>,            // כבר לא קיים בדיסק
+}
+
+/// השוואה של תיקייה לדיסק: השורש, והשירים שכבר ידועים מתחתיו
+type ReconcileJob = (PathBuf, Vec<PathBuf>);
+
+pub struct FolderWatcher {
+    watcher: Option<RecommendedWatcher>,
+    batches_rx: Receiver<WatchBatch>,
+    worker: Option<JoinHandle<()>>,
+    reconcile_tx: Option<Sender<ReconcileJob>>,
+    reconciler: Option<JoinHandle<()>>,
+}
+
+impl FolderWatcher {
+    pub fn new() -> Self {
+        let (raw_tx, raw_rx) = mpsc::channel();
+        let (batches_tx, batches_rx) = mpsc::channel();
+
+        let watcher = notify::recommended_watcher(move |res| {
+            let _ = raw_tx.send(res);
+        })
+        .map_err(|e| eprintln!("Failed to start folder watcher: {}", e))
+        .ok();
+        let reconcile_batches_tx = batches_tx.clone();
+        let worker = thread::spawn(move || debounce_loop(raw_rx, batches_tx));
+
+        // סריקה של תיקייה גדולה לוקחת זמן - היא רצה כאן ולא ב-UI
+        let (reconcile_tx, reconcile_rx) = mpsc::channel();
+        let reconciler = thread::spawn(move || reconcile_loop(reconcile_rx, reconcile_batches_tx));
+
+        Self {
+            watcher,
+            batches_rx,
+            worker: Some(worker),
+            reconcile_tx: Some(reconcile_tx),
+            reconciler: Some(reconciler),
+        }
+    }
+
+    pub fn watch(&mut self, root: &Path) {
+        if let Some(watcher) = &mut self.watcher
+            && let Err(e) = watcher.watch(root, RecursiveMode::Recursive)
+        {
+            eprintln!("Cannot watch {:?}: {}", root, e);
+        }
+    }
+
+    pub fn unwatch(&mut self, root: &Path) {
+        if let Some(watcher) = &mut self.watcher {
+            let _ = watcher.unwatch(root);
+        }
+    }
+
+    /// משווה את התיקייה לדיסק ברקע. התוצאה מגיעה ב-poll כמנה רגילה:
+    /// קבצים שלא ב-known הם changed, ושירים מ-known שנעלמו הם removed
+    pub fn reconcile(&self, root: &Path, known: Vec<PathBuf>) {
+        if let Some(tx) = &self.reconcile_tx {
+            let _ = tx.send((root.to_path_buf(), known));
+        }
+    }
+
+    /// המנות שהצטברו מאז הקריאה הקודמת (נקרא מה-UI בכל פריים)
+    pub fn poll(&self) -> Vec<WatchBatch> {
+        let mut batches = Vec::new();
+        loop {
+            match self.batches_rx.try_recv() {
+                Ok(batch) => batches.push(batch),
+                Err(TryRecvError::Empty) | Err(TryRecvError::Disconnected) => break,
+            }
+        }
+        batches
+    }
+}
+
+impl Drop for FolderWatcher {
+    fn drop(&mut self) {
+        // שחרור ה-Watcher סוגר את הערוץ, וה-Thread יוצא אחרי המנה האחרונה
+        self.watcher.take();
+        self.reconcile_tx.take();
+        if let Some(worker) = self.worker.take() {
+            let _ = worker.join();
+        }
+        if let Some(reconciler) = self.reconciler.take() {
+            let _ = reconciler.join();
+        }
+    }
+}
+
+// =========================================================
+// Debounce Loop - אוסף אירועים גולמיים עד שיש שקט
+// =========================================================
+fn debounce_loop(raw_rx: Receiver<notify::Result<Event>>, batches_tx: Sender<WatchBatch>) {
+    let mut touched: HashSet<PathBuf> = HashSet::new();
+    let mut renamed: Vec<(PathBuf, PathBuf)> = Vec::new();
+
+    loop {
+        let received = if touched.is_empty() && renamed.is_empty() {
+            raw_rx.recv().map_err(|_| RecvTimeoutError::Disconnected)
+        } else {
+            raw_rx.recv_timeout(DEBOUNCE)
+        };
+
+        match received {
+            Ok(Ok(event)) => match event.kind {
+                // שינוי שם עם שני הצדדים: שומרים את הזוג כדי שהשיר יישאר באותו מקום ברשימה
+                EventKind::Modify(ModifyKind::Name(RenameMode::Both))
+                    if event.paths.len() == 2 =>
+                {
+                    renamed.push((event.paths[0].clone(), event.paths[1].clone()));
+                }
+                EventKind::Create(_) | EventKind::Modify(_) | EventKind::Remove(_) => {
+                    touched.extend(event.paths);
+                }
+                _ => {}
+            },
+            Ok(Err(e)) => eprintln!("Folder watcher error: {}", e),
+            Err(RecvTimeoutError::Timeout) => {
+                let batch = build_batch(std::mem::take(&mut touched), std::mem::take(&mut renamed));
+                if batches_tx.send(batch).is_err() {
+                    break;
+                }
+            }
+            Err(RecvTimeoutError::Disconnected) => break,
+        }
+    }
+}
+
+// =========================================================
+// Reconcile Loop - השוואת תיקייה שלמה לדיסק
+// =========================================================
+fn reconcile_loop(jobs_rx: Receiver<ReconcileJob>, batches_tx: Sender<WatchBatch>) {
+    for (root, known) in jobs_rx {
+        if batches_tx.send(reconcile_batch(&root, known)).is_err() {
+            break;
+        }
+    }
+}
+
+fn reconcile_batch(root: &Path, known: Vec<PathBuf>) -> WatchBatch {
+    let known: HashSet<PathBuf> = known.into_iter().filter(|p| p.starts_with(root)).collect();
+    let on_disk = library_db::collect_audio_files(root);
+    let on_disk_set: HashSet<&PathBuf> = on_disk.iter().collect();
+    let removed = known
+        .iter()
+        .filter(|p| !on_disk_set.contains(p) && !p.exists())
+        .cloned()
+        .collect();
+    let changed = on_disk.iter().filter(|p| !known.contains(*p)).cloned().collect();
+    WatchBatch {
+        renamed: Vec::new(),
+        changed,
+        removed,
+    }
+}
+
+/// מה שקובע זה המצב בדיסק בסוף השקט, לא סדר האירועים
+fn build_batch(touched: HashSet<PathBuf>, renamed: Vec<(PathBuf, PathBuf)>) -> WatchBatch {
+    let mut batch = WatchBatch::default();
+
+    for (from, to) in renamed {
+        if to.exists() {
+            batch.renamed.push((from, to));
+        } else {
+            batch.removed.push(from);
+        }
+    }
+    for path in touched {
+        if batch.renamed.iter().any(|(from, to)| *from == path || *to == path) {
+            continue;
+        }
+        if path.exists() {
+            batch.changed.push(path);
+        } else {
+            batch.removed.push(path);
+        }
+    }
+    batch
+}